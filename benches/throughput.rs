@@ -0,0 +1,134 @@
+//! Throughput of trimming and sizing up a generated dump, which is streamed
+//! rather than held in memory so it can be as big as a real one:
+//!
+//! ```sh
+//! MYSQLTRIM_BENCH_SIZE=4GiB cargo bench --bench throughput
+//! ```
+
+use std::io::{self, BufReader, Read};
+use std::time::Instant;
+
+use regex::Regex;
+
+use mysqltrim::extract::{extract_sql_with, parse_size, ExtractOptions};
+use mysqltrim::stats::compute_table_stats;
+
+const HEADER: &[u8] = b"-- MySQL dump 10.13\n\n/*!40101 SET @OLD_CHARACTER_SET_CLIENT=@@CHARACTER_SET_CLIENT */;\n/*!40101 SET NAMES utf8mb4 */;\n/*!40014 SET @OLD_FOREIGN_KEY_CHECKS=@@FOREIGN_KEY_CHECKS, FOREIGN_KEY_CHECKS=0 */;\n\n";
+const FOOTER: &[u8] = b"/*!40014 SET FOREIGN_KEY_CHECKS=@OLD_FOREIGN_KEY_CHECKS */;\n/*!40101 SET CHARACTER_SET_CLIENT=@OLD_CHARACTER_SET_CLIENT */;\n\n-- Dump completed\n";
+
+/// The bytes of each table's rows
+const TABLE_SIZE: u64 = 64 << 20;
+
+/// A dump of tables named `table_0`, `table_1` and so on, of about `size`
+/// bytes, with extended `INSERT`s like mysqldump's.
+struct GeneratedDump {
+    size: u64,
+    generated: u64,
+    tables: u64,
+    table_generated: u64,
+    insert: Vec<u8>,
+    chunk: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl GeneratedDump {
+    fn new(size: u64) -> Self {
+        let mut insert = b"INSERT INTO `table_N` VALUES ".to_vec();
+        for id in 0..500 {
+            if id > 0 {
+                insert.push(b',');
+            }
+            insert.extend_from_slice(
+                format!("({},'user{}@example.com','It''s a \\'quoted\\' bio, with commas (and parens)',NULL,'2024-01-01 00:00:00')", id, id)
+                    .as_bytes(),
+            );
+        }
+        insert.extend_from_slice(b";\n");
+        GeneratedDump {
+            size,
+            generated: 0,
+            tables: 0,
+            table_generated: TABLE_SIZE,
+            insert,
+            chunk: HEADER.to_vec(),
+            position: 0,
+            done: false,
+        }
+    }
+
+    fn next_chunk(&mut self) {
+        self.chunk.clear();
+        self.position = 0;
+        if self.generated >= self.size {
+            if !self.done {
+                self.chunk.extend_from_slice(b"UNLOCK TABLES;\n");
+                self.chunk.extend_from_slice(FOOTER);
+                self.done = true;
+            }
+            return;
+        }
+        if self.table_generated >= TABLE_SIZE {
+            if self.tables > 0 {
+                self.chunk.extend_from_slice(b"UNLOCK TABLES;\n\n");
+            }
+            let table = format!("table_{}", self.tables);
+            self.chunk.extend_from_slice(
+                format!(
+                    "--\n-- Table structure for table `{0}`\n--\n\nDROP TABLE IF EXISTS `{0}`;\nCREATE TABLE `{0}` (\n  `id` bigint unsigned NOT NULL,\n  `email` varchar(100) NOT NULL,\n  `bio` text,\n  `deleted` datetime DEFAULT NULL,\n  `created` datetime NOT NULL,\n  PRIMARY KEY (`id`)\n) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;\n\nLOCK TABLES `{0}` WRITE;\n",
+                    table
+                )
+                .as_bytes(),
+            );
+            let start = b"INSERT INTO `".len();
+            let end = self.insert[start..].iter().position(|&b| b == b'`').unwrap() + start;
+            self.insert.splice(start..end, table.bytes());
+            self.tables += 1;
+            self.table_generated = 0;
+        }
+        self.chunk.extend_from_slice(&self.insert);
+        self.table_generated += self.insert.len() as u64;
+        self.generated += self.chunk.len() as u64;
+    }
+}
+
+impl Read for GeneratedDump {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            self.next_chunk();
+        }
+        let length = buf.len().min(self.chunk.len() - self.position);
+        buf[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+fn bench(name: &str, size: u64, run: impl FnOnce(GeneratedDump) -> io::Result<()>) {
+    let started = Instant::now();
+    run(GeneratedDump::new(size)).expect("the generated dump is valid");
+    let seconds = started.elapsed().as_secs_f64();
+    let mib = size as f64 / (1 << 20) as f64;
+    println!("{:<24} {:>8.0} MiB in {:>6.2}s, {:>7.1} MiB/s", name, mib, seconds, mib / seconds);
+}
+
+fn main() {
+    let size = match std::env::var("MYSQLTRIM_BENCH_SIZE") {
+        Ok(size) => parse_size(&size).expect("MYSQLTRIM_BENCH_SIZE should be a size such as 4GiB") as u64,
+        Err(_) => 512 << 20,
+    };
+
+    bench("extract everything", size, |dump| {
+        extract_sql_with(dump, &mut io::sink(), &mut ExtractOptions::new()).map(drop)
+    });
+    bench("extract half the tables", size, |dump| {
+        let mut options = ExtractOptions::new().include(Regex::new("[02468]$").unwrap());
+        extract_sql_with(dump, &mut io::sink(), &mut options).map(drop)
+    });
+    bench("schema only", size, |dump| {
+        extract_sql_with(dump, &mut io::sink(), &mut ExtractOptions::new().schema_only(true)).map(drop)
+    });
+    bench("table stats", size, |dump| {
+        compute_table_stats(BufReader::new(dump), &ExtractOptions::new()).map(drop)
+    });
+}
@@ -0,0 +1,80 @@
+//! Splitting an `INSERT` into its tuples skips ahead with memchr; this checks
+//! it against walking the bytes one at a time.
+//!
+//! ```sh
+//! cargo +nightly fuzz run tuples
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mysqltrim::insert::{count_tuples, parse_insert};
+
+fuzz_target!(|values: &[u8]| {
+    let mut text = b"INSERT INTO `t` VALUES ".to_vec();
+    text.extend_from_slice(values);
+
+    let expected = scalar_tuples(&text, b"INSERT INTO `t` VALUES ".len());
+    let tuples = parse_insert(&text).map(|insert| insert.tuples);
+    assert_eq!(tuples, expected);
+    assert_eq!(count_tuples(&text), expected.map(|tuples| tuples.len()));
+});
+
+/// The tuples from `position` on, found byte by byte.
+fn scalar_tuples(text: &[u8], mut position: usize) -> Option<Vec<&[u8]>> {
+    let mut tuples = Vec::new();
+    loop {
+        position += text[position..].len() - text[position..].trim_ascii_start().len();
+        let length = scalar_tuple_length(&text[position..])?;
+        tuples.push(&text[position..position + length]);
+        position += length;
+        let after = text[position..].trim_ascii_start();
+        match after.first() {
+            Some(b',') => position = text.len() - after.len() + 1,
+            _ => return Some(tuples),
+        }
+    }
+}
+
+fn scalar_tuple_length(text: &[u8]) -> Option<usize> {
+    if text.first() != Some(&b'(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'\'' | b'"' => i += scalar_quoted_length(&text[i..])? - 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn scalar_quoted_length(text: &[u8]) -> Option<usize> {
+    let quote = text[0];
+    let mut i = 1;
+    while i < text.len() {
+        match text[i] {
+            b'\\' => i += 1,
+            b if b == quote => {
+                if text.get(i + 1) == Some(&quote) {
+                    i += 1;
+                } else {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
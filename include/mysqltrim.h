@@ -0,0 +1,57 @@
+/*
+ * The C API of mysqltrim, built with `cargo build --release --features capi`
+ * as libmysqltrim.so (or .dylib, or .dll).
+ *
+ * Functions returning int return 0 on success and -1 on failure, when
+ * mysqltrim_last_error() has the error message. A panic inside mysqltrim is
+ * caught and fails the same way.
+ */
+
+#ifndef MYSQLTRIM_H
+#define MYSQLTRIM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MysqltrimOptions MysqltrimOptions;
+
+typedef struct MysqltrimCallbacks {
+    /* Read up to length bytes into buffer, returning how many were read, 0 at
+     * the end of the dump, or -1 on failure */
+    intptr_t (*read)(void *user_data, uint8_t *buffer, size_t length);
+    /* Write up to length bytes from buffer, returning how many were written,
+     * or -1 on failure */
+    intptr_t (*write)(void *user_data, const uint8_t *buffer, size_t length);
+    /* Told how far the extract has got, now and then, if not NULL */
+    void (*progress)(void *user_data, uint64_t bytes_read, uint64_t statements);
+    void *user_data;
+} MysqltrimCallbacks;
+
+/* The message of the last error on this thread, or NULL if there's been none.
+ * It's valid until the next call that fails. */
+const char *mysqltrim_last_error(void);
+
+MysqltrimOptions *mysqltrim_options_new(void);
+void mysqltrim_options_free(MysqltrimOptions *options);
+/* Only keep the tables matching the regex pattern, or any other included */
+int mysqltrim_options_include(MysqltrimOptions *options, const char *pattern);
+/* Drop the tables matching the regex pattern */
+int mysqltrim_options_exclude(MysqltrimOptions *options, const char *pattern);
+/* Drop the rows, keeping each table's structure */
+int mysqltrim_options_schema_only(MysqltrimOptions *options, bool schema_only);
+/* Drop the tables' structure, views and routines, keeping the rows */
+int mysqltrim_options_data_only(MysqltrimOptions *options, bool data_only);
+
+/* Trim the dump callbacks reads, writing it through callbacks */
+int mysqltrim_extract(MysqltrimOptions *options, const MysqltrimCallbacks *callbacks);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
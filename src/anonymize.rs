@@ -0,0 +1,534 @@
+//! Replacing personal data with realistic fake values, chosen by rule or by
+//! guessing from column names.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::dump::StatementReader;
+use crate::insert::{parse_value, quote_string, Value};
+use crate::preset::Preset;
+use crate::schema::parse_create_table;
+use crate::scrub::{Replacement, ScrubColumn, Scrubs};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alex", "Sam", "Jordan", "Taylor", "Morgan", "Casey", "Riley", "Jamie", "Avery", "Quinn", "Robin", "Drew",
+    "Charlie", "Emery", "Harper", "Jesse", "Kai", "Logan", "Parker", "Rowan",
+];
+const LAST_NAMES: &[&str] = &[
+    "Smith", "Jones", "Brown", "Taylor", "Wilson", "Evans", "Walker", "Wright", "Green", "Hall", "Wood", "Clarke",
+    "Hughes", "Turner", "Hill", "Moore", "Baker", "Young", "King", "Lee",
+];
+const STREETS: &[&str] = &["High", "Station", "Church", "Park", "Mill", "Victoria", "Green", "Manor", "Kings", "Queens"];
+const STREET_SUFFIXES: &[&str] = &["Street", "Road", "Lane", "Avenue", "Close", "Way"];
+/// The IPv4 ranges reserved for documentation, which are never routed
+const IPV4_PREFIXES: &[&str] = &["192.0.2", "198.51.100", "203.0.113"];
+
+/// A generator of fake values of one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fake {
+    Name,
+    Email,
+    Phone,
+    Address,
+    Ip,
+    Uuid,
+}
+
+impl FromStr for Fake {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Fake::Name),
+            "email" => Ok(Fake::Email),
+            "phone" => Ok(Fake::Phone),
+            "address" => Ok(Fake::Address),
+            "ip" => Ok(Fake::Ip),
+            "uuid" => Ok(Fake::Uuid),
+            _ => Err(format!("unknown generator `{}`, expected name, email, phone, address, ip or uuid", s)),
+        }
+    }
+}
+
+impl Fake {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fake::Name => "name",
+            Fake::Email => "email",
+            Fake::Phone => "phone",
+            Fake::Address => "address",
+            Fake::Ip => "ip",
+            Fake::Uuid => "uuid",
+        }
+    }
+
+    /// Guess the kind of personal data a column holds from its name. A plain
+    /// `name` column is only taken as a person's name in tables that look
+    /// like they hold people, not products.
+    pub fn guess(table: &str, column: &str) -> Option<Fake> {
+        let table = table.to_ascii_lowercase();
+        let people = ["user", "customer", "member", "author", "contact", "person", "people", "employee", "client", "subscriber"];
+        let column = column.to_ascii_lowercase();
+        let words: Vec<&str> = column.split(['_', '-']).collect();
+        let has = |word: &str| words.contains(&word);
+        if column.contains("email") {
+            Some(Fake::Email)
+        } else if has("phone") || has("mobile") || has("telephone") || has("tel") || has("fax") {
+            Some(Fake::Phone)
+        } else if has("ip") || column == "remote_addr" || column.ends_with("_ip_address") {
+            Some(Fake::Ip)
+        } else if has("uuid") {
+            Some(Fake::Uuid)
+        } else if has("address") || has("street") || column.starts_with("address") {
+            Some(Fake::Address)
+        } else if matches!(
+            column.as_str(),
+            "first_name" | "last_name" | "firstname" | "lastname" | "full_name" | "fullname" | "surname"
+                | "display_name" | "given_name" | "family_name" | "comment_author"
+        ) || column.ends_with("_first_name")
+            || column.ends_with("_last_name")
+            || (column == "name" && people.iter().any(|word| table.contains(word)))
+        {
+            Some(Fake::Name)
+        } else {
+            None
+        }
+    }
+
+    /// The SQL literal to write in place of `value`, as written in the dump.
+    /// NULLs and empty strings are kept, and so are the value's format (the
+    /// punctuation of a phone number, the case of a UUID) and, where it can,
+    /// its length.
+    ///
+    /// The fake is picked by `pseudonyms` from the original value, so the same
+    /// value always gets the same fake, and is recorded as the fake for
+    /// `column` if `pseudonyms` keeps a mapping.
+    pub fn literal(&self, value: &[u8], column: &str, pseudonyms: &mut Pseudonyms) -> Vec<u8> {
+        let original = match parse_value(value) {
+            Value::Null => return value.to_vec(),
+            Value::String(bytes) if bytes.is_empty() => return value.to_vec(),
+            Value::String(bytes) => bytes,
+            Value::Literal(literal) => literal.to_vec(),
+        };
+        let mut rng = pseudonyms.rng(self.name(), &original);
+        let literal = match self {
+            // Emails are often unique keys, so no two originals share a fake
+            Fake::Email => {
+                let hash = pseudonyms.hash("email", &original);
+                match pseudonyms.emails.get(&hash) {
+                    Some(literal) => literal.clone(),
+                    None => {
+                        let mut attempt = 0;
+                        let literal = loop {
+                            let literal = self.generate(&original, &mut rng, attempt);
+                            if !pseudonyms.used_emails.contains(&literal) || attempt == 8 {
+                                break literal;
+                            }
+                            attempt += 1;
+                        };
+                        pseudonyms.used_emails.insert(literal.clone());
+                        pseudonyms.emails.insert(hash, literal.clone());
+                        literal
+                    }
+                }
+            }
+            _ => self.generate(&original, &mut rng, 0),
+        };
+        pseudonyms.record(column, &original, &literal);
+        literal
+    }
+
+    /// A fake for `original`. Later attempts, after a fake that was already
+    /// taken, make room for more distinct values.
+    fn generate(&self, original: &[u8], rng: &mut Rng, attempt: u32) -> Vec<u8> {
+        // Numbers, as phone numbers and IPs are sometimes stored
+        if original.iter().all(u8::is_ascii_digit) {
+            return match self {
+                Fake::Ip => (u32::from_be_bytes([192, 0, 2, 0]) + rng.below(256) as u32).to_string().into_bytes(),
+                _ => fake_digits(original, rng),
+            };
+        }
+        let length = original.len();
+
+        let fake = match self {
+            Fake::Name if original.contains(&b' ') => {
+                fitting(length, rng, |rng| format!("{} {}", rng.pick(FIRST_NAMES), rng.pick(LAST_NAMES)))
+            }
+            Fake::Name => fitting(length, rng, |rng| rng.pick(FIRST_NAMES).to_string()),
+            Fake::Email => {
+                let domain = "@example.com";
+                let local = fitting(length.saturating_sub(domain.len()), rng, |rng| {
+                    let first = rng.pick(FIRST_NAMES).to_ascii_lowercase();
+                    match rng.below(2) {
+                        0 => format!("{}.{}", first, rng.pick(LAST_NAMES).to_ascii_lowercase()),
+                        _ => first,
+                    }
+                });
+                match attempt {
+                    0 => format!("{}{}", local, domain),
+                    _ => format!("{}{}{}", local, rng.below(10u64.pow(attempt.min(9))), domain),
+                }
+            }
+            Fake::Phone => String::from_utf8_lossy(&fake_digits(original, rng)).into_owned(),
+            Fake::Address => fitting(length, rng, |rng| {
+                format!("{} {} {}", 1 + rng.below(200), rng.pick(STREETS), rng.pick(STREET_SUFFIXES))
+            }),
+            Fake::Ip if original.contains(&b':') => {
+                format!("2001:db8::{:x}:{:x}", rng.below(0x10000), rng.below(0x10000))
+            }
+            Fake::Ip => format!("{}.{}", rng.pick(IPV4_PREFIXES), 1 + rng.below(254)),
+            Fake::Uuid => {
+                let bytes: Vec<u8> = (0..16).map(|_| rng.below(256) as u8).collect();
+                let mut uuid: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                // Version 4, variant 1
+                uuid.replace_range(12..13, "4");
+                uuid.replace_range(16..17, &format!("{:x}", 8 + rng.below(4)));
+                if original.contains(&b'-') {
+                    uuid = format!("{}-{}-{}-{}-{}", &uuid[..8], &uuid[8..12], &uuid[12..16], &uuid[16..20], &uuid[20..]);
+                }
+                if original.iter().any(u8::is_ascii_uppercase) {
+                    uuid = uuid.to_ascii_uppercase();
+                }
+                uuid
+            }
+        };
+        quote_string(fake.as_bytes())
+    }
+}
+
+/// Replace each digit with a random one, keeping everything else. The first
+/// digit is only a zero if it was one, as in a trunk prefix.
+fn fake_digits(text: &[u8], rng: &mut Rng) -> Vec<u8> {
+    let mut first = true;
+    text.iter()
+        .map(|&b| match b {
+            b'0' if first => {
+                first = false;
+                b'0'
+            }
+            b'1'..=b'9' if first => {
+                first = false;
+                b'1' + rng.below(9) as u8
+            }
+            b'0'..=b'9' => b'0' + rng.below(10) as u8,
+            b => b,
+        })
+        .collect()
+}
+
+/// A value from `generate` no longer than `length` bytes, if one turns up in
+/// a few tries, or else the last one cut down to size.
+fn fitting(length: usize, rng: &mut Rng, generate: impl Fn(&mut Rng) -> String) -> String {
+    let mut text = String::new();
+    for _ in 0..32 {
+        text = generate(rng);
+        if text.len() <= length {
+            return text;
+        }
+    }
+    text.truncate(length.max(1));
+    text
+}
+
+/// A small, fast pseudo-random generator (SplitMix64). Fake values don't need
+/// to be unpredictable, only varied.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Picks each fake value from a keyed hash (HMAC-SHA256) of the original, so
+/// the same value, such as an email in both `wp_users` and `wp_comments`, gets
+/// the same fake everywhere and joins still work. With the same key, separate
+/// runs pick the same fakes; without the key the originals can't be worked
+/// out from the fakes.
+#[derive(Clone)]
+pub struct Pseudonyms {
+    key: [u8; 32],
+    mapping: Option<BTreeSet<MappingEntry>>,
+    /// The fake picked for each email, by the keyed hash of the original
+    emails: HashMap<[u8; 32], Vec<u8>>,
+    used_emails: HashSet<Vec<u8>>,
+}
+
+/// What [`Pseudonyms`] picks its fakes with, saved in a checkpoint so a
+/// resumed extract picks the same ones: its key, and the fake picked for each
+/// email, by the keyed hash of the original rather than the original.
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PseudonymState {
+    pub key: [u8; 32],
+    pub emails: Vec<([u8; 32], Vec<u8>)>,
+}
+
+impl std::fmt::Debug for PseudonymState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PseudonymState").finish_non_exhaustive()
+    }
+}
+
+/// A column, and an original value of it with its fake
+type MappingEntry = (String, Vec<u8>, Vec<u8>);
+
+const MAPPING_MAGIC: &[u8] = b"mysqltrim-mapping-1\n";
+
+impl std::fmt::Debug for Pseudonyms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pseudonyms").finish_non_exhaustive()
+    }
+}
+
+impl Pseudonyms {
+    pub fn new(key: &[u8]) -> Self {
+        Pseudonyms::with_key(derive_key(key, b"pseudonyms"))
+    }
+
+    /// With a random key, so fakes are only consistent within one run.
+    pub fn random() -> Self {
+        Pseudonyms::with_key(ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    fn with_key(key: [u8; 32]) -> Self {
+        Pseudonyms { key, mapping: None, emails: HashMap::new(), used_emails: HashSet::new() }
+    }
+
+    /// The key, and the fakes picked for emails so far, for resuming from a
+    /// checkpoint.
+    pub(crate) fn state(&self) -> PseudonymState {
+        let mut emails: Vec<_> = self.emails.iter().map(|(hash, literal)| (*hash, literal.clone())).collect();
+        emails.sort();
+        PseudonymState { key: self.key, emails }
+    }
+
+    /// Carry on with the key and fakes of `state`, from [`state`](Self::state).
+    pub(crate) fn resume(&mut self, state: PseudonymState) {
+        self.key = state.key;
+        self.used_emails = state.emails.iter().map(|(_, literal)| literal.clone()).collect();
+        self.emails = state.emails.into_iter().collect();
+    }
+
+    /// Keep the original and fake values, for [`Pseudonyms::write_mapping`].
+    pub fn keep_mapping(&mut self) {
+        self.mapping.get_or_insert_with(BTreeSet::new);
+    }
+
+    /// A generator seeded from `original`, for picking what replaces it.
+    /// `purpose` (such as the name of a [`Fake`]) keeps the picks for
+    /// different purposes apart.
+    pub(crate) fn rng(&self, purpose: &str, original: &[u8]) -> Rng {
+        let hash = self.hash(purpose, original);
+        Rng::new(u64::from_le_bytes(hash[..8].try_into().unwrap()))
+    }
+
+    /// The keyed hash of `original`, for `purpose`.
+    pub(crate) fn hash(&self, purpose: &str, original: &[u8]) -> [u8; 32] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        mac.update(purpose.as_bytes());
+        mac.update(&[0]);
+        mac.update(original);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn record(&mut self, column: &str, original: &[u8], literal: &[u8]) {
+        if let Some(mapping) = &mut self.mapping {
+            let fake = match parse_value(literal) {
+                Value::String(bytes) => bytes,
+                _ => literal.to_vec(),
+            };
+            mapping.insert((column.to_string(), original.to_vec(), fake));
+        }
+    }
+
+    /// Write the mapping from originals to fakes, as tab-separated `column`,
+    /// `original` and `fake` lines encrypted (with ChaCha20-Poly1305) by a key
+    /// derived from `key`.
+    pub fn write_mapping<W: Write>(&self, key: &[u8], output: &mut W) -> io::Result<()> {
+        let mut plaintext = Vec::new();
+        for (column, original, fake) in self.mapping.iter().flatten() {
+            plaintext.extend_from_slice(&escape_field(column.as_bytes()));
+            plaintext.push(b'\t');
+            plaintext.extend_from_slice(&escape_field(original));
+            plaintext.push(b'\t');
+            plaintext.extend_from_slice(&escape_field(fake));
+            plaintext.push(b'\n');
+        }
+        let cipher = ChaCha20Poly1305::new(&derive_key(key, b"mapping").into());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, plaintext.as_slice()).map_err(|e| io::Error::other(e.to_string()))?;
+        output.write_all(MAPPING_MAGIC)?;
+        output.write_all(&nonce)?;
+        output.write_all(&ciphertext)?;
+        output.flush()
+    }
+}
+
+/// Decrypt a mapping written by [`Pseudonyms::write_mapping`], returning its
+/// tab-separated lines.
+pub fn decrypt_mapping(key: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let data = data.strip_prefix(MAPPING_MAGIC).ok_or_else(|| invalid("not a mysqltrim mapping file"))?;
+    if data.len() < 12 {
+        return Err(invalid("mapping file is truncated"));
+    }
+    let (nonce, ciphertext) = data.split_at(12);
+    let cipher = ChaCha20Poly1305::new(&derive_key(key, b"mapping").into());
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid("wrong key, or the mapping file is damaged"))
+}
+
+fn derive_key(key: &[u8], purpose: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(b"mysqltrim ");
+    mac.update(purpose);
+    mac.finalize().into_bytes().into()
+}
+
+/// Escape backslashes, tabs and newlines in a mapping field.
+fn escape_field(field: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(field.len());
+    for &b in field {
+        match b {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\t' => escaped.extend_from_slice(b"\\t"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b => escaped.push(b),
+        }
+    }
+    escaped
+}
+
+/// A line of an anonymization rules file: `table.column=generator`, where the
+/// generator is one of [`Fake`]'s, `null`, or `keep` to leave a column alone
+/// that would otherwise be guessed.
+#[derive(Debug, Clone)]
+pub struct AnonymizeRule {
+    pub table: String,
+    pub column: String,
+    /// `None` to keep the column
+    pub replacement: Option<Replacement>,
+}
+
+impl FromStr for AnonymizeRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected table.column=generator, got `{}`", s);
+        let (target, generator) = s.split_once('=').ok_or_else(error)?;
+        let (table, column) = match target.trim().split_once('.') {
+            Some((table, column)) if !table.is_empty() && !column.is_empty() => (table, column),
+            _ => return Err(error()),
+        };
+        let replacement = match generator.trim() {
+            "keep" => None,
+            "null" => Some(Replacement::Null),
+            generator => Some(Replacement::Fake(generator.parse()?)),
+        };
+        Ok(AnonymizeRule { table: table.to_string(), column: column.to_string(), replacement })
+    }
+}
+
+/// Parse a rules file: one `table.column=generator` per line, with blank
+/// lines and lines starting with `#` ignored.
+pub fn parse_rules_file(text: &str) -> Result<Vec<AnonymizeRule>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// Copy `input` to `output`, replacing the values of the columns named by
+/// `rules`, by `preset` (for tables starting with its table prefix) and, with
+/// `guess`, of the columns [`Fake::guess`] recognises. The rules take
+/// precedence over the preset, and both over the guesses.
+///
+/// `scrubs` picks the fakes with its pseudonyms, and does any other scrubbing
+/// it's been set up with, such as of IPs or dates. Returns the columns that
+/// were anonymized and how, for checking the guesses, and `scrubs` with the
+/// pseudonyms it used.
+pub fn anonymize_dump<R: BufRead, W: Write>(
+    input: R,
+    rules: &[AnonymizeRule],
+    preset: Option<(Preset, &str)>,
+    guess: bool,
+    mut scrubs: Scrubs,
+    output: &mut W,
+) -> io::Result<(Vec<ScrubColumn>, Scrubs)> {
+    let mut applied = Vec::new();
+    for rule in rules {
+        if let Some(replacement) = &rule.replacement {
+            let rule = ScrubColumn {
+                table: rule.table.clone(),
+                column: rule.column.clone(),
+                replacement: replacement.clone(),
+                rows: None,
+            };
+            scrubs.insert(rule.clone());
+            applied.push(rule);
+        }
+    }
+
+    for statement in StatementReader::new(input) {
+        let statement = statement?;
+        if let Some(schema) = parse_create_table(&statement.text) {
+            let ruled = |column: &str| {
+                rules.iter().any(|rule| rule.table == schema.name && rule.column.eq_ignore_ascii_case(column))
+            };
+            let mut table_rules = match preset {
+                Some((preset, table_prefix)) => preset.rules(&schema.name, table_prefix),
+                None => Vec::new(),
+            };
+            table_rules.retain(|rule| !ruled(&rule.column) && schema.column_index(&rule.column).is_some());
+            if guess {
+                for column in &schema.columns {
+                    let covered = ruled(&column.name)
+                        || table_rules.iter().any(|rule| rule.column.eq_ignore_ascii_case(&column.name));
+                    if let (false, Some(fake)) = (covered, Fake::guess(&schema.name, &column.name)) {
+                        table_rules.push(ScrubColumn {
+                            table: schema.name.clone(),
+                            column: column.name.clone(),
+                            replacement: Replacement::Fake(fake),
+                            rows: None,
+                        });
+                    }
+                }
+            }
+            for rule in table_rules {
+                scrubs.insert(rule.clone());
+                applied.push(rule);
+            }
+        }
+        output.write_all(&scrubs.rewrite(&statement.text)?)?;
+    }
+
+    output.flush()?;
+    Ok((applied, scrubs))
+}
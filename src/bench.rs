@@ -0,0 +1,108 @@
+//! Timing how fast a dump is read and parsed, without writing anything, to
+//! compare releases and tuning flags on real dumps.
+
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use crate::dump::{classify_statement, SectionKind, SectionReader, StatementKind};
+use crate::extract::ExtractOptions;
+use crate::insert::{count_tuples, TupleCounter};
+
+/// What [`bench_dump`] read, and how long it took.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BenchReport {
+    pub bytes_read: u64,
+    /// Statements, including comment lines
+    pub statements: u64,
+    /// `INSERT` and `REPLACE` statements
+    pub inserts: u64,
+    pub rows: u64,
+    /// Tables and views
+    pub tables: u64,
+    /// Time spent waiting for the input
+    pub read_time: Duration,
+    pub duration: Duration,
+}
+
+impl BenchReport {
+    /// Time spent on anything other than waiting for the input, which is
+    /// mostly parsing.
+    pub fn parse_time(&self) -> Duration {
+        self.duration.saturating_sub(self.read_time)
+    }
+
+    /// Bytes read per second.
+    pub fn throughput(&self) -> f64 {
+        self.bytes_read as f64 / self.duration.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Counts the time spent reading from the reader it wraps.
+struct Timed<R> {
+    inner: R,
+    time: Duration,
+}
+
+impl<R: Read> Read for Timed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started = Instant::now();
+        let read = self.inner.read(buf);
+        self.time += started.elapsed();
+        read
+    }
+}
+
+/// Read and parse the dump in `input` as an extract would, splitting it
+/// into statements and sections and counting the rows of each `INSERT`, but
+/// keeping everything and writing nothing. Only `options`' buffer sizes and
+/// maximum statement size are used.
+pub fn bench_dump<R: Read>(input: R, options: &ExtractOptions) -> io::Result<BenchReport> {
+    let started = Instant::now();
+    let mut input = Timed { inner: input, time: Duration::ZERO };
+    let mut report = BenchReport::default();
+    let mut sections = SectionReader::with_capacity(options.reader(&mut input), options.read_buffer_size.unwrap_or(0));
+    if let Some(size) = options.max_statement_size {
+        sections = sections.max_statement_size(size);
+    }
+
+    let mut current_section = usize::MAX;
+    // Counting the rows of an INSERT read in pieces
+    let mut counter: Option<TupleCounter> = None;
+    let mut continuing = false;
+    while let Some(item) = sections.next() {
+        let (index, statement) = item?;
+        report.bytes_read += statement.text.len() as u64;
+        if std::mem::replace(&mut continuing, statement.continued) {
+            if let Some(counting) = &mut counter {
+                counting.push(&statement.text);
+                if !statement.continued {
+                    report.rows += counting.count() as u64;
+                    counter = None;
+                }
+            }
+            continue;
+        }
+
+        report.statements += 1;
+        if index != current_section {
+            current_section = index;
+            if let SectionKind::Table(_) | SectionKind::View(_) = sections.sections()[index].kind {
+                report.tables += 1;
+            }
+        }
+        if let StatementKind::Insert(_) | StatementKind::Replace(_) = classify_statement(&statement.text) {
+            report.inserts += 1;
+            if statement.continued {
+                counter = TupleCounter::new(&statement.text);
+            } else {
+                report.rows += count_tuples(&statement.text).map_or(0, |rows| rows as u64);
+            }
+        }
+    }
+
+    drop(sections);
+    report.read_time = input.time;
+    report.duration = started.elapsed();
+    Ok(report)
+}
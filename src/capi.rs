@@ -0,0 +1,216 @@
+//! A C API, for embedding the extract in programs in other languages, such
+//! as with PHP's FFI or cgo. `include/mysqltrim.h` declares it.
+//!
+//! Functions returning `int` return 0 on success and -1 on failure, when
+//! [`mysqltrim_last_error`] has the error message. A panic is caught rather
+//! than unwinding into the caller, and fails the same way.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use regex::Regex;
+
+use crate::extract::{extract_sql_with, ExtractOptions};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: impl ToString) -> c_int {
+    let message = CString::new(message.to_string().replace('\0', "")).expect("NULs are removed");
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+    -1
+}
+
+/// Run `body`, returning `failed` with the error set if it panics, as
+/// unwinding out of an `extern "C"` function aborts the caller.
+fn catch_panic<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| {
+        let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+            (Some(message), _) => message,
+            (_, Some(message)) => message.as_str(),
+            _ => "unknown panic",
+        };
+        set_error(format!("mysqltrim panicked: {message}"));
+        failed
+    })
+}
+
+/// The message of the last error on this thread, or NULL if there's been
+/// none. It's valid until the next call that fails.
+#[no_mangle]
+pub extern "C" fn mysqltrim_last_error() -> *const c_char {
+    catch_panic(ptr::null(), || {
+        LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+    })
+}
+
+/// Options for [`mysqltrim_extract`], which `mysqltrim_options_free` frees.
+pub struct MysqltrimOptions(ExtractOptions);
+
+#[no_mangle]
+pub extern "C" fn mysqltrim_options_new() -> *mut MysqltrimOptions {
+    catch_panic(ptr::null_mut(), || Box::into_raw(Box::new(MysqltrimOptions(ExtractOptions::new()))))
+}
+
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`, and not used after, or
+/// NULL.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_options_free(options: *mut MysqltrimOptions) {
+    catch_panic((), || {
+        if !options.is_null() {
+            drop(Box::from_raw(options));
+        }
+    })
+}
+
+/// Apply `set` to the options with the regex in `pattern`.
+unsafe fn with_regex(
+    options: *mut MysqltrimOptions,
+    pattern: *const c_char,
+    set: fn(ExtractOptions, Regex) -> ExtractOptions,
+) -> c_int {
+    let Some(options) = options.as_mut() else { return set_error("options is NULL") };
+    if pattern.is_null() {
+        return set_error("pattern is NULL");
+    }
+    let regex = match CStr::from_ptr(pattern).to_str().map_err(|e| e.to_string()).and_then(|pattern| {
+        Regex::new(pattern).map_err(|e| e.to_string())
+    }) {
+        Ok(regex) => regex,
+        Err(e) => return set_error(e),
+    };
+    options.0 = set(std::mem::take(&mut options.0), regex);
+    0
+}
+
+/// Only keep the tables matching the regex `pattern`, or any other included.
+///
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`, and `pattern` a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_options_include(options: *mut MysqltrimOptions, pattern: *const c_char) -> c_int {
+    catch_panic(-1, || with_regex(options, pattern, ExtractOptions::include))
+}
+
+/// Drop the tables matching the regex `pattern`.
+///
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`, and `pattern` a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_options_exclude(options: *mut MysqltrimOptions, pattern: *const c_char) -> c_int {
+    catch_panic(-1, || with_regex(options, pattern, ExtractOptions::exclude))
+}
+
+/// Drop the rows, keeping each table's structure.
+///
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_options_schema_only(options: *mut MysqltrimOptions, schema_only: bool) -> c_int {
+    catch_panic(-1, || {
+        let Some(options) = options.as_mut() else { return set_error("options is NULL") };
+        options.0 = std::mem::take(&mut options.0).schema_only(schema_only);
+        0
+    })
+}
+
+/// Drop the tables' structure, views and routines, keeping the rows.
+///
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_options_data_only(options: *mut MysqltrimOptions, data_only: bool) -> c_int {
+    catch_panic(-1, || {
+        let Some(options) = options.as_mut() else { return set_error("options is NULL") };
+        options.0 = std::mem::take(&mut options.0).data_only(data_only);
+        0
+    })
+}
+
+/// Where [`mysqltrim_extract`] reads the dump from and writes to, through
+/// functions passed `user_data`.
+#[repr(C)]
+pub struct MysqltrimCallbacks {
+    /// Read up to `length` bytes into `buffer`, returning how many were read,
+    /// 0 at the end of the dump, or -1 on failure
+    pub read: Option<unsafe extern "C" fn(user_data: *mut c_void, buffer: *mut u8, length: usize) -> isize>,
+    /// Write up to `length` bytes from `buffer`, returning how many were
+    /// written, or -1 on failure
+    pub write: Option<unsafe extern "C" fn(user_data: *mut c_void, buffer: *const u8, length: usize) -> isize>,
+    /// Told how far the extract has got, now and then, if not NULL
+    pub progress: Option<unsafe extern "C" fn(user_data: *mut c_void, bytes_read: u64, statements: u64)>,
+    pub user_data: *mut c_void,
+}
+
+struct CallbackReader<'a>(&'a MysqltrimCallbacks);
+
+impl Read for CallbackReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.0.read.expect("checked by mysqltrim_extract");
+        match unsafe { read(self.0.user_data, buf.as_mut_ptr(), buf.len()) } {
+            read if read < 0 => Err(io::Error::other("the read callback failed")),
+            read => Ok(read as usize),
+        }
+    }
+}
+
+struct CallbackWriter<'a>(&'a MysqltrimCallbacks);
+
+impl Write for CallbackWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write = self.0.write.expect("checked by mysqltrim_extract");
+        match unsafe { write(self.0.user_data, buf.as_ptr(), buf.len()) } {
+            written if written < 0 => Err(io::Error::other("the write callback failed")),
+            written => Ok(written as usize),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Trim the dump `callbacks` reads, writing it through `callbacks`.
+///
+/// # Safety
+///
+/// `options` has to be from `mysqltrim_options_new`, and `callbacks` has to
+/// point to callbacks that are safe to call with its `user_data`.
+#[no_mangle]
+pub unsafe extern "C" fn mysqltrim_extract(
+    options: *mut MysqltrimOptions,
+    callbacks: *const MysqltrimCallbacks,
+) -> c_int {
+    catch_panic(-1, || {
+        let Some(options) = options.as_mut() else { return set_error("options is NULL") };
+        let Some(callbacks) = callbacks.as_ref() else { return set_error("callbacks is NULL") };
+        if callbacks.read.is_none() || callbacks.write.is_none() {
+            return set_error("the read and write callbacks are required");
+        }
+
+        let mut extract_options = std::mem::take(&mut options.0);
+        if let Some(progress) = callbacks.progress {
+            let user_data = callbacks.user_data;
+            extract_options = extract_options.progress(move |at| unsafe { progress(user_data, at.bytes_read, at.statements) });
+        }
+        let result = extract_sql_with(CallbackReader(callbacks), &mut CallbackWriter(callbacks), &mut extract_options);
+        // The callback's user_data may not outlive this call
+        options.0 = extract_options.without_progress();
+        match result {
+            Ok(_) => 0,
+            Err(e) => set_error(e),
+        }
+    })
+}
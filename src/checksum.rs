@@ -0,0 +1,133 @@
+//! Checksums of what's written, computed as it's written, so a copy of a
+//! large output can be verified without reading the output again.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+use std::io::{self, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use sha2::{Digest, Sha256};
+
+/// A checksum to write of each output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256,
+}
+
+impl Checksum {
+    /// The extension of the file beside an output holding its checksum,
+    /// e.g. `sha256` for `trimmed.sql.sha256`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Checksum::Sha256 => "sha256",
+        }
+    }
+
+    /// The name of the file listing the checksums of the files in a
+    /// directory, e.g. `SHA256SUMS`.
+    pub fn sums_file(&self) -> &'static str {
+        match self {
+            Checksum::Sha256 => "SHA256SUMS",
+        }
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(Checksum::Sha256),
+            _ => Err(format!("unknown checksum `{}`, expected sha256", s)),
+        }
+    }
+}
+
+/// A line of a checksums file, as `sha256sum` writes them and `sha256sum -c`
+/// reads them: the checksum, two spaces, and the file's name.
+pub fn checksum_line(digest: &str, file: &str) -> String {
+    format!("{}  {}\n", digest, file)
+}
+
+/// Writes to another writer, working out the checksum of what's written as
+/// it goes, if there's one to work out.
+#[derive(Debug)]
+pub struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Option<Sha256>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W, checksum: Option<Checksum>) -> Self {
+        let hasher = checksum.map(|Checksum::Sha256| Sha256::new());
+        ChecksumWriter { inner, hasher }
+    }
+
+    /// The writer, and the checksum of what was written to it in hex.
+    pub fn finish(self) -> (W, Option<String>) {
+        let digest = self.hasher.map(|hasher| hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect());
+        (self.inner, digest)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The checksums of the files a [`FileSink`](crate::sink::FileSink) or
+/// [`GzipFileSink`](crate::sink::GzipFileSink) has written, shared between
+/// its clones, such as those writing tables on other threads.
+#[derive(Debug, Clone)]
+pub struct Checksums {
+    checksum: Checksum,
+    files: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl Checksums {
+    pub fn new(checksum: Checksum) -> Self {
+        Checksums { checksum, files: Arc::default() }
+    }
+
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn add(&self, file: String, digest: String) {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner).push((file, digest));
+    }
+
+    /// Each file written so far and its checksum, by name.
+    pub fn files(&self) -> Vec<(String, String)> {
+        let mut files = self.files.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        files.sort();
+        files
+    }
+
+    /// Write the checksums of the files written so far to `output`, as
+    /// `sha256sum` would, once they've all been written.
+    pub fn write(&self, mut output: impl Write) -> io::Result<()> {
+        let sums: String = self.files().iter().map(|(file, digest)| checksum_line(digest, file)).collect();
+        output.write_all(sums.as_bytes())
+    }
+
+    /// [`write`](Self::write) the checksums of the files written to `dir` to
+    /// the file named by [`Checksum::sums_file`] in it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_to(&self, dir: &Path) -> io::Result<()> {
+        self.write(File::create(dir.join(self.checksum.sums_file()))?)
+    }
+}
@@ -0,0 +1,240 @@
+//! Converting a dump into other formats that restore faster, such as a
+//! directory of data files loaded with `LOAD DATA`.
+
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::dump::{quote_identifier, starts_with_ignore_case, strip_conditional_comment, strip_keywords, StatementReader};
+use crate::insert::{parse_insert, parse_value, quote_string, split_values, Value};
+
+/// The script a restore pack is restored from, written next to its data files.
+pub const RESTORE_SCRIPT: &str = "restore.sql";
+
+/// What to convert a dump into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertTo {
+    /// A tab-separated data file per table, and a `restore.sql` that creates
+    /// the tables and loads them with `LOAD DATA LOCAL INFILE`
+    LoadData,
+}
+
+impl FromStr for ConvertTo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "load-data" => Ok(ConvertTo::LoadData),
+            _ => Err(format!("unknown format `{}`, expected load-data", s)),
+        }
+    }
+}
+
+/// The rows written to one table's data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableFile {
+    pub name: String,
+    /// The data file's name, in the pack's directory
+    pub file: String,
+    pub rows: u64,
+}
+
+/// Convert `input` into a restore pack in `out_dir`: a `.tsv` file of each
+/// table's rows, and a [`RESTORE_SCRIPT`] holding every other statement, with
+/// a `LOAD DATA LOCAL INFILE` for each table where its first `INSERT` was.
+///
+/// The data files are written in `LOAD DATA`'s default format: fields
+/// separated by tabs, rows by newlines, `\N` for `NULL` and backslash escapes
+/// for the rest, in the character set of the dump's `SET NAMES`. The script
+/// names them relative to `out_dir`, so it has to be run from there.
+///
+/// Fails on values `LOAD DATA` can't take as written, such as bit literals
+/// and expressions, and on a table whose `INSERT`s list different columns.
+/// It isn't built for WebAssembly, which has no directory to write to, but
+/// [`load_data_pack_with`] is.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_data_pack<R: BufRead>(input: R, out_dir: &Path) -> io::Result<Vec<TableFile>> {
+    load_data_pack_with(input, |file, append| {
+        let path = out_dir.join(file);
+        if append {
+            OpenOptions::new().append(true).open(path)
+        } else {
+            File::create(path)
+        }
+    })
+}
+
+/// [`load_data_pack`], writing each file of the pack to what `open` opens
+/// for its name: to add to if `append` is set, for a table whose rows are
+/// split up by other statements, and from the start otherwise.
+pub fn load_data_pack_with<R: BufRead, W: Write>(
+    input: R,
+    mut open: impl FnMut(&str, bool) -> io::Result<W>,
+) -> io::Result<Vec<TableFile>> {
+    let mut script = BufWriter::new(open(RESTORE_SCRIPT, false)?);
+    let mut tables: Vec<TableFile> = Vec::new();
+    // The columns each table's rows were loaded with, from its first INSERT
+    let mut columns: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    let mut charset = "utf8mb4".to_string();
+    let mut current: Option<(String, BufWriter<W>)> = None;
+
+    for statement in StatementReader::new(input) {
+        let statement = statement?;
+        let text = &statement.text;
+        let Some(insert) = parse_insert(text) else {
+            if let Some(names) = set_names(text) {
+                charset = names;
+            }
+            script.write_all(text)?;
+            continue;
+        };
+
+        match columns.get(&insert.table) {
+            Some(loaded) if *loaded != insert.columns => {
+                return Err(invalid_input(format!("INSERTs into `{}` list different columns", insert.table)));
+            }
+            Some(_) => {}
+            None => {
+                let file = file_name(&insert.table, ".tsv");
+                // `Posts` and `posts` would be the same file on some filesystems
+                if tables.iter().any(|table| table.file.eq_ignore_ascii_case(&file)) {
+                    return Err(invalid_input(format!("two tables would both be written to `{}`", file)));
+                }
+                script.write_all(&load_data(&insert.table, &file, insert.prefix, insert.columns.as_deref(), &charset))?;
+                columns.insert(insert.table.clone(), insert.columns.clone());
+                tables.push(TableFile { name: insert.table.clone(), file, rows: 0 });
+            }
+        }
+
+        // Open the table's file, or reopen it to add to it if its rows are
+        // split up by other statements
+        let table = tables.iter_mut().find(|table| table.name == insert.table).expect("the table was added");
+        if current.as_ref().is_none_or(|(name, _)| *name != table.name) {
+            if let Some((_, mut writer)) = current.take() {
+                writer.flush()?;
+            }
+            current = Some((table.name.clone(), BufWriter::new(open(&table.file, table.rows > 0)?)));
+        }
+        let (_, writer) = current.as_mut().expect("the file was opened");
+
+        let mut row = Vec::new();
+        for tuple in &insert.tuples {
+            row.clear();
+            for (i, value) in split_values(tuple).into_iter().enumerate() {
+                if i > 0 {
+                    row.push(b'\t');
+                }
+                write_field(&mut row, value).map_err(|value| {
+                    invalid_input(format!("can't load `{}` into `{}` with LOAD DATA", value, table.name))
+                })?;
+            }
+            row.push(b'\n');
+            writer.write_all(&row)?;
+        }
+        table.rows += insert.tuples.len() as u64;
+    }
+
+    if let Some((_, mut writer)) = current {
+        writer.flush()?;
+    }
+    script.flush()?;
+    Ok(tables)
+}
+
+/// The `LOAD DATA` statement loading `file` into `table`, replacing rows
+/// with the same keys if they were written with `REPLACE`.
+fn load_data(table: &str, file: &str, prefix: &[u8], columns: Option<&[String]>, charset: &str) -> Vec<u8> {
+    let mut statement = b"LOAD DATA LOCAL INFILE ".to_vec();
+    statement.extend_from_slice(&quote_string(file.as_bytes()));
+    if starts_with_ignore_case(prefix, b"REPLACE") {
+        statement.extend_from_slice(b" REPLACE");
+    }
+    statement.extend_from_slice(format!(" INTO TABLE {} CHARACTER SET {}", quote_identifier(table), charset).as_bytes());
+    if let Some(columns) = columns {
+        let columns: Vec<String> = columns.iter().map(|column| quote_identifier(column)).collect();
+        statement.extend_from_slice(format!(" ({})", columns.join(",")).as_bytes());
+    }
+    statement.extend_from_slice(b";\n");
+    statement
+}
+
+/// The character set of a `SET NAMES` statement, such as mysqldump's
+/// `/*!40101 SET NAMES utf8mb4 */;`.
+fn set_names(text: &[u8]) -> Option<String> {
+    let text = strip_conditional_comment(text.trim_ascii().strip_suffix(b";")?);
+    let rest = strip_keywords(text, &["SET", "NAMES"])?;
+    let end = rest.iter().position(|b| !(b.is_ascii_alphanumeric() || *b == b'_')).unwrap_or(rest.len());
+    (end > 0).then(|| String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+/// The file for `table`, with `extension`: its name, with anything but
+/// letters, digits, `_`, `-` and `$` written as `@` and its hex bytes so names
+/// can't collide or leave the directory.
+pub fn file_name(table: &str, extension: &str) -> String {
+    let mut name = String::with_capacity(table.len() + extension.len());
+    for b in table.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'$' => name.push(b as char),
+            _ => name.push_str(&format!("@{:02x}", b)),
+        }
+    }
+    name.push_str(extension);
+    name
+}
+
+/// Write a value as a `LOAD DATA` field. `Err` with the value if it can't be
+/// written as one.
+fn write_field(row: &mut Vec<u8>, raw: &[u8]) -> Result<(), String> {
+    let bytes = match parse_value(raw) {
+        Value::Null => {
+            row.extend_from_slice(b"\\N");
+            return Ok(());
+        }
+        Value::String(bytes) => bytes,
+        Value::Literal(literal) if is_number(literal) => literal.to_vec(),
+        Value::Literal(literal) => hex_literal(literal).ok_or_else(|| String::from_utf8_lossy(literal).into_owned())?,
+    };
+    for b in bytes {
+        match b {
+            0 => row.extend_from_slice(b"\\0"),
+            b'\t' => row.extend_from_slice(b"\\t"),
+            b'\n' => row.extend_from_slice(b"\\n"),
+            b'\r' => row.extend_from_slice(b"\\r"),
+            b'\\' => row.extend_from_slice(b"\\\\"),
+            b => row.push(b),
+        }
+    }
+    Ok(())
+}
+
+fn is_number(literal: &[u8]) -> bool {
+    let digits = literal.strip_prefix(b"-").unwrap_or(literal);
+    !digits.is_empty()
+        && digits.first().is_some_and(u8::is_ascii_digit)
+        && digits.iter().all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-'))
+}
+
+/// The bytes of a `0x...` or `X'...'` literal, as mysqldump writes binary
+/// data with `--hex-blob`.
+fn hex_literal(literal: &[u8]) -> Option<Vec<u8>> {
+    let digits = match literal.strip_prefix(b"0x") {
+        Some(digits) => digits,
+        None => literal.strip_prefix(b"X'").or_else(|| literal.strip_prefix(b"x'"))?.strip_suffix(b"'")?,
+    };
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    // An odd number of digits is read with a leading zero, as MySQL does
+    let digits = if digits.len() % 2 == 0 { digits.to_vec() } else { [b"0", digits].concat() };
+    let nibble = |b: u8| (b as char).to_digit(16).expect("checked it's a hex digit") as u8;
+    Some(digits.chunks(2).map(|pair| nibble(pair[0]) << 4 | nibble(pair[1])).collect())
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,311 @@
+//! Shifting `DATE`, `DATETIME` and `TIMESTAMP` values by an interval.
+
+use std::str::FromStr;
+
+/// An ISO 8601 duration such as `P90D`, `P1Y2M` or `-PT12H`. Months are kept
+/// apart from the rest, as they vary in length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Interval {
+    pub months: i64,
+    pub seconds: i64,
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected an ISO 8601 duration such as P90D or -PT12H, got `{}`", s);
+        let (sign, rest) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let rest = rest.strip_prefix(['P', 'p']).ok_or_else(error)?;
+        let mut interval = Interval::default();
+        let mut time = false;
+        let mut number = String::new();
+        let mut parts = 0;
+        for c in rest.chars() {
+            match c.to_ascii_uppercase() {
+                'T' if !time && number.is_empty() => time = true,
+                '0'..='9' => number.push(c),
+                unit if !number.is_empty() => {
+                    let n: i64 = number.parse().map_err(|_| error())?;
+                    number.clear();
+                    let (months, seconds) = match (time, unit) {
+                        (false, 'Y') => (12, 0),
+                        (false, 'M') => (1, 0),
+                        (false, 'W') => (0, 7 * 86_400),
+                        (false, 'D') => (0, 86_400),
+                        (true, 'H') => (0, 3_600),
+                        (true, 'M') => (0, 60),
+                        (true, 'S') => (0, 1),
+                        _ => return Err(error()),
+                    };
+                    interval.months = n.checked_mul(months).and_then(|m| interval.months.checked_add(m)).ok_or_else(error)?;
+                    interval.seconds = n.checked_mul(seconds).and_then(|s| interval.seconds.checked_add(s)).ok_or_else(error)?;
+                    parts += 1;
+                }
+                _ => return Err(error()),
+            }
+        }
+        if parts == 0 || !number.is_empty() {
+            return Err(error());
+        }
+        Ok(Interval { months: sign * interval.months, seconds: sign * interval.seconds })
+    }
+}
+
+impl Interval {
+    /// The interval's length in seconds, taking months as 30 days.
+    pub fn approximate_seconds(&self) -> i64 {
+        self.months.saturating_mul(30 * 86_400).saturating_add(self.seconds)
+    }
+}
+
+/// A `[table[.column]=][random:]INTERVAL` rule, shifting the dates in every
+/// date column, or one table's, or one column's. The most specific rule for a
+/// column wins.
+///
+/// A `random:` shift is by an interval picked once (for the rule) from up to
+/// `interval` earlier to up to `interval` later, so dates shifted by the same
+/// rule keep their order and spacing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShiftDates {
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub interval: Interval,
+    pub random: bool,
+}
+
+impl FromStr for ShiftDates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, interval) = match s.split_once('=') {
+            Some((target, interval)) => (Some(target.trim()), interval.trim()),
+            None => (None, s.trim()),
+        };
+        let (table, column) = match target {
+            None => (None, None),
+            Some(target) => match target.split_once('.') {
+                Some((table, column)) if !table.is_empty() && !column.is_empty() => {
+                    (Some(table.to_string()), Some(column.to_string()))
+                }
+                None if !target.is_empty() => (Some(target.to_string()), None),
+                _ => return Err(format!("expected [table[.column]=]INTERVAL, got `{}`", s)),
+            },
+        };
+        let (random, interval) = match interval.strip_prefix("random:") {
+            Some(interval) => (true, interval),
+            None => (false, interval),
+        };
+        Ok(ShiftDates { table, column, interval: interval.parse()?, random })
+    }
+}
+
+impl ShiftDates {
+    /// How closely the rule targets a column of `table`: `None` if not at
+    /// all, and higher for a table than for every table, and for a column than
+    /// for a table.
+    pub fn specificity(&self, table: &str, column: &str) -> Option<u8> {
+        match (&self.table, &self.column) {
+            (None, _) => Some(0),
+            (Some(t), None) if t == table => Some(1),
+            (Some(t), Some(c)) if t == table && c.eq_ignore_ascii_case(column) => Some(2),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a column definition from `CREATE TABLE` is of a type holding
+/// dates: `DATE`, `DATETIME` or `TIMESTAMP`.
+pub fn is_date_type(definition: &str) -> bool {
+    let end = definition.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(definition.len());
+    let ty = &definition[..end];
+    ["date", "datetime", "timestamp"].iter().any(|date| ty.eq_ignore_ascii_case(date))
+}
+
+/// Whether `value` is a valid `YYYY-MM-DD[ HH:MM:SS[.ffffff]]` date, which
+/// MySQL's zero date `0000-00-00`, and dates with a zero month or day, aren't.
+pub fn is_valid_date(value: &[u8]) -> bool {
+    shift(value, Interval::default()).is_some()
+}
+
+/// Shift a `YYYY-MM-DD[ HH:MM:SS[.ffffff]]` value by `interval`, in the same
+/// format. Months are added first, with the day kept to the end of shorter
+/// months, so later dates never end up before earlier ones. A date alone is
+/// shifted as its midnight.
+///
+/// `None` if `value` isn't a date (including MySQL's zero date), or the
+/// result would be outside years 1 to 9999.
+pub fn shift(value: &[u8], interval: Interval) -> Option<String> {
+    let text = std::str::from_utf8(value).ok()?;
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut fields = date.splitn(3, '-');
+    let (year, month, day) = (fields.next()?, fields.next()?, fields.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (i64, i64, i64) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let (hms, fraction) = match time {
+        Some(time) => {
+            let (hms, fraction) = match time.split_once('.') {
+                Some((hms, fraction)) => (hms, Some(fraction)),
+                None => (time, None),
+            };
+            let mut fields = hms.splitn(3, ':');
+            let (hour, minute, second) = (fields.next()?, fields.next()?, fields.next()?);
+            if [hour, minute, second].iter().any(|field| field.len() != 2) {
+                return None;
+            }
+            let (hour, minute, second): (i64, i64, i64) = (hour.parse().ok()?, minute.parse().ok()?, second.parse().ok()?);
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            (Some(hour * 3_600 + minute * 60 + second), fraction)
+        }
+        None => (None, None),
+    };
+
+    let months = (year * 12 + month - 1).checked_add(interval.months)?;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+    if !(0..=10_000).contains(&year) {
+        return None;
+    }
+    let day = day.min(days_in_month(year, month));
+    let seconds = days_from_civil(year, month, day)
+        .checked_mul(86_400)?
+        .checked_add(hms.unwrap_or(0))?
+        .checked_add(interval.seconds)?;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    if !(1..=9999).contains(&year) {
+        return None;
+    }
+    let mut shifted = format!("{:04}-{:02}-{:02}", year, month, day);
+    if hms.is_some() {
+        let second = seconds.rem_euclid(86_400);
+        shifted.push_str(&format!(" {:02}:{:02}:{:02}", second / 3_600, second / 60 % 60, second % 60));
+        if let Some(fraction) = fraction {
+            shifted.push('.');
+            shifted.push_str(fraction);
+        }
+    }
+    Some(shifted)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` after 1970-01-01, as (year, month, day).
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shifted(value: &str, interval: &str) -> Option<String> {
+        shift(value.as_bytes(), interval.parse().unwrap())
+    }
+
+    #[test]
+    fn month_ends() {
+        assert_eq!(shifted("2023-01-31", "P1M").as_deref(), Some("2023-02-28"));
+        assert_eq!(shifted("2024-01-31", "P1M").as_deref(), Some("2024-02-29"));
+        assert_eq!(shifted("2024-01-31 10:30:00", "P1M").as_deref(), Some("2024-02-29 10:30:00"));
+        assert_eq!(shifted("2024-03-31", "-P1M").as_deref(), Some("2024-02-29"));
+        assert_eq!(shifted("2024-05-31", "P1M").as_deref(), Some("2024-06-30"));
+        // Months first, then days
+        assert_eq!(shifted("2024-01-31", "P1M1D").as_deref(), Some("2024-03-01"));
+    }
+
+    #[test]
+    fn leap_days() {
+        assert_eq!(shifted("2024-02-28", "P1D").as_deref(), Some("2024-02-29"));
+        assert_eq!(shifted("2023-02-28", "P1D").as_deref(), Some("2023-03-01"));
+        assert_eq!(shifted("2024-02-29", "P1Y").as_deref(), Some("2025-02-28"));
+        assert_eq!(shifted("2024-02-29", "P4Y").as_deref(), Some("2028-02-29"));
+        assert_eq!(shifted("2024-02-29", "P1D").as_deref(), Some("2024-03-01"));
+        assert_eq!(shifted("2000-02-29", "P0D").as_deref(), Some("2000-02-29"));
+        assert_eq!(shifted("2023-02-29", "P1D"), None);
+        assert_eq!(shifted("1900-02-29", "P1D"), None);
+    }
+
+    #[test]
+    fn negative_intervals() {
+        assert_eq!(shifted("2024-01-01 00:00:00", "-PT1S").as_deref(), Some("2023-12-31 23:59:59"));
+        assert_eq!(shifted("2024-01-15", "-P1M").as_deref(), Some("2023-12-15"));
+        assert_eq!(shifted("2023-01-10", "-P15D").as_deref(), Some("2022-12-26"));
+        assert_eq!(shifted("2024-03-01 06:00:00", "-P1DT7H").as_deref(), Some("2024-02-28 23:00:00"));
+        assert_eq!(shifted("2021-02-10", "-P1Y1M").as_deref(), Some("2020-01-10"));
+        assert_eq!(shifted("2024-12-31 23:59:59.123456", "PT1S").as_deref(), Some("2025-01-01 00:00:00.123456"));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for value in [
+            "0000-00-00",
+            "0000-00-00 00:00:00",
+            "2024-00-10",
+            "2024-05-00",
+            "2024-13-01",
+            "2024-04-31",
+            "2024-01-01 24:00:00",
+            "2024-01-01 12:60:00",
+            "2024-1-01",
+            "not a date",
+            "",
+        ] {
+            assert_eq!(shifted(value, "P1D"), None, "{}", value);
+            assert!(!is_valid_date(value.as_bytes()), "{}", value);
+        }
+        assert!(is_valid_date(b"2024-02-29 23:59:59"));
+        // Outside years 1 to 9999
+        assert_eq!(shifted("9999-12-31", "P1D"), None);
+        assert_eq!(shifted("0001-01-01", "-P1D"), None);
+    }
+
+    #[test]
+    fn civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        for days in (-800_000..3_000_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert!((1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day));
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
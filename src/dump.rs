@@ -0,0 +1,687 @@
+//! Reading a mysqldump file as a stream of statements, and grouping those
+//! statements into the per-table sections mysqldump writes them in.
+
+use std::io::{self, BufRead};
+
+/// A single statement (or comment / blank line) from a dump, as raw bytes.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    /// Byte offset of the start of the statement in the input
+    pub offset: u64,
+    /// The statement text, including its trailing newline
+    pub text: Vec<u8>,
+    /// Whether the statement goes on in the next one, as it's longer than
+    /// the reader's maximum statement size. The statements that follow are
+    /// the rest of it, in pieces, up to one that isn't continued.
+    pub continued: bool,
+}
+
+impl Statement {
+    /// Comments and blank lines, which carry no SQL of their own.
+    pub fn is_trivia(&self) -> bool {
+        let text = self.text.trim_ascii();
+        text.is_empty() || text.starts_with(b"--") || text.starts_with(b"#")
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.text.len() as u64
+    }
+}
+
+/// The smallest maximum statement size, so the first piece of a statement
+/// always holds enough of it to tell what it is, such as an `INSERT`'s table
+/// and column list.
+const MIN_STATEMENT_SIZE: usize = 64 << 10;
+
+/// Splits a dump into statements.
+///
+/// mysqldump writes one statement per line, except for CREATE statements which
+/// span several lines, and routines / triggers which are wrapped in
+/// `DELIMITER ;;` blocks. A statement is therefore gathered line by line until
+/// a line ends with the current delimiter.
+pub struct StatementReader<R> {
+    reader: R,
+    offset: u64,
+    delimiter: Vec<u8>,
+    /// Where statements are gathered, kept between them so a big statement
+    /// doesn't grow a new buffer each time
+    buffer: Vec<u8>,
+    max_size: Option<usize>,
+    /// Where the statement being returned in pieces is up to, if one is
+    split: Option<Split>,
+}
+
+/// What [`StatementReader`] keeps of a statement between its pieces.
+struct Split {
+    /// Whether it's a comment, which ends with its line
+    comment: bool,
+    /// The end of the line a piece ended in the middle of
+    line: Option<LineEnd>,
+}
+
+/// The end of a line read in pieces, to tell whether it ends with the
+/// delimiter without keeping all of it.
+#[derive(Default)]
+struct LineEnd {
+    /// The last few bytes before any trailing whitespace, with a space for
+    /// any whitespace among them
+    last: Vec<u8>,
+    whitespace: bool,
+}
+
+impl LineEnd {
+    /// How many bytes are kept, more than any delimiter's length.
+    const KEPT: usize = 16;
+
+    fn push(&mut self, part: &[u8]) {
+        let trimmed = part.trim_ascii_end();
+        if trimmed.is_empty() {
+            self.whitespace |= !part.is_empty();
+            return;
+        }
+        if self.whitespace {
+            self.last.push(b' ');
+        }
+        self.last.extend_from_slice(&trimmed[trimmed.len().saturating_sub(Self::KEPT)..]);
+        self.last.drain(..self.last.len().saturating_sub(Self::KEPT));
+        self.whitespace = trimmed.len() < part.len();
+    }
+
+    fn ends_with(&self, delimiter: &[u8]) -> bool {
+        !delimiter.is_empty() && self.last.ends_with(delimiter)
+    }
+}
+
+impl<R: BufRead> StatementReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_capacity(reader, 0)
+    }
+
+    /// A reader whose buffer for gathering statements starts out with room
+    /// for `capacity` bytes.
+    pub fn with_capacity(reader: R, capacity: usize) -> Self {
+        StatementReader {
+            reader,
+            offset: 0,
+            delimiter: b";".to_vec(),
+            buffer: Vec::with_capacity(capacity),
+            max_size: None,
+            split: None,
+        }
+    }
+
+    /// Return statements longer than `max_size` bytes (or 64KiB, if that's
+    /// more) in pieces of about that size, rather than gathering all of
+    /// them, such as an extended `INSERT` of hundreds of megabytes.
+    pub fn max_statement_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size.max(MIN_STATEMENT_SIZE));
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for StatementReader<R> {
+    type Item = io::Result<Statement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let text = &mut self.buffer;
+        text.clear();
+        let mut split = self.split.take();
+        let continues = split.is_some();
+        loop {
+            let start = text.len();
+            let read = match self.max_size {
+                Some(max_size) => read_until_limit(&mut self.reader, text, max_size),
+                None => self.reader.read_until(b'\n', text),
+            };
+            let read = match read {
+                Ok(read) => read,
+                Err(e) => return Some(Err(e)),
+            };
+            self.offset += read as u64;
+            if read == 0 {
+                if text.is_empty() && !continues {
+                    return None;
+                }
+                split = None;
+                break;
+            }
+
+            if text.last() != Some(&b'\n') {
+                // Cut short by the maximum size, unless it's the end of the
+                // input, which the next read finds
+                let full = self.max_size.is_some_and(|max_size| text.len() >= max_size);
+                match self.reader.fill_buf() {
+                    Ok(rest) if full && !rest.is_empty() => {}
+                    Ok(_) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+                let line = text[start..].trim_ascii_start();
+                let split = split.get_or_insert(Split {
+                    comment: start == 0 && (line.starts_with(b"--") || line.starts_with(b"#")),
+                    line: None,
+                });
+                split.line.get_or_insert_with(LineEnd::default).push(&text[start..]);
+                break;
+            }
+
+            let ends = match split.as_mut().and_then(|split| Some((split.comment, split.line.take()?))) {
+                // The rest of a line the last piece ended in the middle of
+                Some((comment, mut line)) => {
+                    line.push(&text[..]);
+                    comment || line.ends_with(&self.delimiter)
+                }
+                None => {
+                    let line = text[start..].trim_ascii();
+                    if start == 0 && !continues {
+                        if line.is_empty() || line.starts_with(b"--") || line.starts_with(b"#") {
+                            break;
+                        }
+                        if starts_with_ignore_case(line, b"DELIMITER ") {
+                            self.delimiter = line[b"DELIMITER ".len()..].trim_ascii().to_vec();
+                            break;
+                        }
+                    }
+                    line.ends_with(&self.delimiter)
+                }
+            };
+            if ends {
+                split = None;
+                break;
+            }
+            if self.max_size.is_some_and(|max_size| text.len() >= max_size) {
+                split.get_or_insert(Split { comment: false, line: None });
+                break;
+            }
+        }
+        self.split = split;
+        Some(Ok(Statement { offset, text: text.clone(), continued: self.split.is_some() }))
+    }
+}
+
+/// Read into `text` up to and including a newline, as
+/// [`BufRead::read_until`] does, but stopping once `text` holds `limit`
+/// bytes. Returns how many bytes were read.
+fn read_until_limit<R: BufRead>(reader: &mut R, text: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+    let mut read = 0;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let available = &available[..available.len().min(limit.saturating_sub(text.len()))];
+        let (used, done) = match memchr::memchr(b'\n', available) {
+            Some(at) => (at + 1, true),
+            None => (available.len(), available.is_empty()),
+        };
+        text.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        read += used;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+/// The part of a dump a section covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    /// Session setup before the first table
+    Header,
+    /// `CREATE DATABASE` / `USE` in dumps made with `--databases`
+    Database(String),
+    /// A table's structure, data and triggers
+    Table(String),
+    /// A view, or the temporary table standing in for it
+    View(String),
+    /// A stored procedure, function or event
+    Routine(String),
+    /// Session restore statements after the last table
+    Footer,
+}
+
+impl SectionKind {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            SectionKind::Database(name)
+            | SectionKind::Table(name)
+            | SectionKind::View(name)
+            | SectionKind::Routine(name) => Some(name),
+            SectionKind::Header | SectionKind::Footer => None,
+        }
+    }
+}
+
+/// A contiguous run of statements belonging to one table, view, routine etc.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub kind: SectionKind,
+    /// Byte offset of the first statement in the section
+    pub start: u64,
+    /// Byte offset just past the last statement in the section
+    pub end: u64,
+}
+
+/// Wraps a [`StatementReader`], tagging each statement with the index of the
+/// section it belongs to.
+///
+/// Comments and blank lines are held back until the next real statement is
+/// seen, so the `-- Table structure for table` banner ends up in the section
+/// of the table it describes rather than the one before it.
+pub struct SectionReader<R> {
+    statements: StatementReader<R>,
+    sections: Vec<Section>,
+    pending: Vec<Statement>,
+    ready: std::collections::VecDeque<(usize, Statement)>,
+    /// Whether the next statement is the rest of a continued one
+    continuing: bool,
+}
+
+impl<R: BufRead> SectionReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_capacity(reader, 0)
+    }
+
+    /// A reader whose buffer for gathering statements starts out with room
+    /// for `capacity` bytes.
+    pub fn with_capacity(reader: R, capacity: usize) -> Self {
+        SectionReader {
+            statements: StatementReader::with_capacity(reader, capacity),
+            sections: vec![Section { kind: SectionKind::Header, start: 0, end: 0 }],
+            pending: Vec::new(),
+            ready: Default::default(),
+            continuing: false,
+        }
+    }
+
+    /// Return statements longer than `max_size` bytes in pieces, as
+    /// [`StatementReader::max_statement_size`] does. The pieces of a
+    /// statement are all in its section.
+    pub fn max_statement_size(mut self, max_size: usize) -> Self {
+        self.statements = self.statements.max_statement_size(max_size);
+        self
+    }
+
+    /// Sections seen so far. A section's `end` is only final once a statement
+    /// from a later section has been returned.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn into_sections(self) -> Vec<Section> {
+        self.sections
+    }
+
+    fn push(&mut self, statement: Statement) {
+        let current = self.sections.len() - 1;
+        if let Some(kind) = section_start(&statement.text, &self.sections[current].kind) {
+            let start = self.pending.first().unwrap_or(&statement).offset;
+            self.sections.push(Section { kind, start, end: start });
+        }
+        let index = self.sections.len() - 1;
+        for statement in self.pending.drain(..).chain(std::iter::once(statement)) {
+            self.sections[index].end = statement.end();
+            self.ready.push_back((index, statement));
+        }
+    }
+
+    /// Append the rest of `statement`, if it's continued, for when all of it
+    /// is needed after all.
+    pub fn read_rest(&mut self, statement: &mut Statement) -> io::Result<()> {
+        while statement.continued {
+            match self.next().transpose()? {
+                Some((_, piece)) => {
+                    statement.text.extend_from_slice(&piece.text);
+                    statement.continued = piece.continued;
+                }
+                None => statement.continued = false,
+            }
+        }
+        Ok(())
+    }
+
+    /// Put a piece of a continued statement wherever the statement went.
+    fn push_rest(&mut self, piece: Statement) {
+        if !self.pending.is_empty() {
+            self.pending.push(piece);
+            return;
+        }
+        let index = self.sections.len() - 1;
+        self.sections[index].end = piece.end();
+        self.ready.push_back((index, piece));
+    }
+
+    fn flush_pending(&mut self) {
+        let index = self.sections.len() - 1;
+        for statement in self.pending.drain(..) {
+            self.sections[index].end = statement.end();
+            self.ready.push_back((index, statement));
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SectionReader<R> {
+    type Item = io::Result<(usize, Statement)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(Ok(item));
+            }
+            match self.statements.next() {
+                Some(Ok(statement)) if self.continuing => {
+                    self.continuing = statement.continued;
+                    self.push_rest(statement);
+                }
+                Some(Ok(statement)) => {
+                    self.continuing = statement.continued;
+                    if statement.is_trivia() {
+                        self.pending.push(statement);
+                    } else {
+                        self.push(statement);
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.pending.is_empty() => return None,
+                None => self.flush_pending(),
+            }
+        }
+    }
+}
+
+/// Whether `text` begins a new section, given the kind of the current one.
+fn section_start(text: &[u8], current: &SectionKind) -> Option<SectionKind> {
+    let kind = match classify_statement(text) {
+        StatementKind::CreateTable(name) | StatementKind::DropTable(name) => SectionKind::Table(name),
+        StatementKind::ViewDef(name) => SectionKind::View(name),
+        StatementKind::Routine(name) => SectionKind::Routine(name),
+        StatementKind::Set if contains(text, b"=@OLD_") => SectionKind::Footer,
+        _ => {
+            let text = strip_conditional_comment(text.trim_ascii());
+            let rest = strip_keywords(text, &["CREATE", "DATABASE"]).or_else(|| strip_keywords(text, &["USE"]))?;
+            let rest = rest.strip_prefix(b"/*!32312 IF NOT EXISTS*/").unwrap_or(rest);
+            SectionKind::Database(parse_identifier(rest)?.0)
+        }
+    };
+
+    // A view's temporary table and the view itself share a section, as do
+    // the DROP and CREATE of the same object.
+    let continues = match (&kind, current) {
+        (SectionKind::Footer, SectionKind::Footer) => true,
+        (SectionKind::Routine(a), SectionKind::Routine(b)) => a == b,
+        (SectionKind::Database(a), SectionKind::Database(b)) => a == b,
+        (SectionKind::Table(a) | SectionKind::View(a), SectionKind::Table(b) | SectionKind::View(b)) => a == b,
+        _ => false,
+    };
+    if continues {
+        None
+    } else {
+        Some(kind)
+    }
+}
+
+/// What a statement does, as far as trimming a dump is concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StatementKind {
+    /// `CREATE TABLE`, with the table's name
+    CreateTable(String),
+    /// `DROP TABLE`, with the table's name
+    DropTable(String),
+    /// `INSERT`, with the table it writes to
+    Insert(String),
+    /// `REPLACE`, with the table it writes to
+    Replace(String),
+    /// `LOCK TABLES`, with the first table it locks
+    Lock(String),
+    /// `UNLOCK TABLES`
+    Unlock,
+    /// `SET`, plain or in a version comment
+    Set,
+    /// Any other statement wrapped in a `/*!NNNNN ... */` version comment,
+    /// such as `ALTER TABLE ... DISABLE KEYS`
+    ConditionalComment,
+    /// `CREATE VIEW` or `DROP VIEW`, with the view's name
+    ViewDef(String),
+    /// `CREATE TRIGGER` or `DROP TRIGGER`, with the trigger's name
+    Trigger(String),
+    /// `CREATE` or `DROP` of a procedure, function or event, with its name
+    Routine(String),
+    /// Anything else, including comments and blank lines
+    Other,
+}
+
+/// Classify a statement, looking through the version comments mysqldump
+/// wraps parts of it in, as in
+/// `` /*!50003 CREATE*/ /*!50017 DEFINER=`root`@`%`*/ /*!50003 TRIGGER `t` ... ``.
+pub fn classify_statement(text: &[u8]) -> StatementKind {
+    let text = text.trim_ascii_start();
+    let conditional = text.starts_with(b"/*!");
+    let statement = skip_version_markers(text);
+
+    if let Some(rest) = strip_keywords(statement, &["INSERT"]) {
+        let rest = strip_keywords(rest, &["IGNORE"]).unwrap_or(rest);
+        if let Some(name) = strip_keywords(rest, &["INTO"]).and_then(target_name) {
+            return StatementKind::Insert(name);
+        }
+    } else if let Some(name) = strip_keywords(statement, &["REPLACE", "INTO"]).and_then(target_name) {
+        return StatementKind::Replace(name);
+    } else if let Some(name) = strip_keywords(statement, &["LOCK", "TABLES"]).and_then(target_name) {
+        return StatementKind::Lock(name);
+    } else if strip_keywords(statement, &["UNLOCK", "TABLES"]).is_some() {
+        return StatementKind::Unlock;
+    } else if strip_keywords(statement, &["SET"]).is_some() {
+        return StatementKind::Set;
+    } else if let Some(rest) = strip_keywords(statement, &["CREATE"]) {
+        if let Some(kind) = classify_object(create_object(rest), true) {
+            return kind;
+        }
+    } else if let Some(rest) = strip_keywords(statement, &["DROP"]) {
+        let rest = skip_version_markers(rest);
+        let rest = strip_keywords(rest, &["TEMPORARY"]).unwrap_or(rest);
+        if let Some(kind) = classify_object(rest, false) {
+            return kind;
+        }
+    }
+
+    if conditional {
+        StatementKind::ConditionalComment
+    } else {
+        StatementKind::Other
+    }
+}
+
+/// Skip the modifiers between `CREATE` and the kind of object, such as
+/// `OR REPLACE`, `ALGORITHM=UNDEFINED`, `` DEFINER=`root`@`%` `` and
+/// `SQL SECURITY DEFINER`.
+fn create_object(mut rest: &[u8]) -> &[u8] {
+    const OBJECTS: &[&str] = &["TABLE", "TEMPORARY", "VIEW", "TRIGGER", "PROCEDURE", "FUNCTION", "EVENT"];
+    for _ in 0..8 {
+        rest = skip_version_markers(rest);
+        if OBJECTS.iter().any(|object| strip_keywords(rest, &[object]).is_some()) {
+            break;
+        }
+        // Skip a word, along with any quoted parts of it like a definer's
+        let mut quote = None;
+        let len = rest
+            .iter()
+            .take_while(|&&b| match quote {
+                Some(q) => {
+                    if b == q {
+                        quote = None;
+                    }
+                    true
+                }
+                None if b == b'`' || b == b'\'' || b == b'"' => {
+                    quote = Some(b);
+                    true
+                }
+                None => !b.is_ascii_whitespace() && b != b'*',
+            })
+            .count();
+        if len == 0 {
+            break;
+        }
+        rest = &rest[len..];
+    }
+    rest
+}
+
+/// Classify the `CREATE` or `DROP` of the object `rest` starts with.
+fn classify_object(rest: &[u8], create: bool) -> Option<StatementKind> {
+    let if_exists: &[&str] = if create { &["IF", "NOT", "EXISTS"] } else { &["IF", "EXISTS"] };
+    let name = |rest: &[u8]| {
+        let rest = skip_version_markers(rest);
+        target_name(skip_version_markers(strip_keywords(rest, if_exists).unwrap_or(rest)))
+    };
+    let rest = strip_keywords(rest, &["TEMPORARY"]).unwrap_or(rest);
+    if let Some(rest) = strip_keywords(rest, &["TABLE"]) {
+        let name = name(rest)?;
+        Some(if create { StatementKind::CreateTable(name) } else { StatementKind::DropTable(name) })
+    } else if let Some(rest) = strip_keywords(rest, &["VIEW"]) {
+        Some(StatementKind::ViewDef(name(rest)?))
+    } else if let Some(rest) = strip_keywords(rest, &["TRIGGER"]) {
+        Some(StatementKind::Trigger(name(rest)?))
+    } else {
+        let rest = ["PROCEDURE", "FUNCTION", "EVENT"].iter().find_map(|object| strip_keywords(rest, &[object]))?;
+        Some(StatementKind::Routine(name(rest)?))
+    }
+}
+
+/// The name of a possibly database-qualified table or other object.
+fn target_name(text: &[u8]) -> Option<String> {
+    parse_qualified_identifier(text).map(|(_, name, _)| name)
+}
+
+/// Skip any `/*!NNNNN` version markers and `*/` comment ends at the start of
+/// `text`.
+fn skip_version_markers(mut text: &[u8]) -> &[u8] {
+    loop {
+        text = text.trim_ascii_start();
+        if text.starts_with(b"/*!") {
+            text = strip_conditional_comment(text);
+        } else if let Some(rest) = text.strip_prefix(b"*/") {
+            text = rest;
+        } else {
+            return text;
+        }
+    }
+}
+
+/// Parse a possibly backtick-quoted identifier from the start of `text`,
+/// returning it along with the remaining bytes.
+pub fn parse_identifier(text: &[u8]) -> Option<(String, &[u8])> {
+    let mut name = String::new();
+    let rest = parse_identifier_into(text, &mut name)?;
+    Some((name, rest))
+}
+
+/// [`parse_identifier`] into `name`, replacing what it held, so a loop can
+/// reuse one `String`. Returns the remaining bytes.
+pub fn parse_identifier_into<'a>(text: &'a [u8], name: &mut String) -> Option<&'a [u8]> {
+    name.clear();
+    let text = text.trim_ascii_start();
+    if let Some(quoted) = text.strip_prefix(b"`") {
+        let mut start = 0;
+        loop {
+            let end = start + memchr::memchr(b'`', &quoted[start..])?;
+            name.push_str(&String::from_utf8_lossy(&quoted[start..end]));
+            if quoted.get(end + 1) == Some(&b'`') {
+                name.push('`');
+                start = end + 2;
+            } else {
+                return Some(&quoted[end + 1..]);
+            }
+        }
+    } else {
+        let len = text
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_' || **b == b'$' || **b >= 0x80)
+            .count();
+        if len == 0 {
+            return None;
+        }
+        name.push_str(&String::from_utf8_lossy(&text[..len]));
+        Some(&text[len..])
+    }
+}
+
+/// Skip a possibly backtick-quoted identifier at the start of `text`, without
+/// parsing it, returning the remaining bytes.
+pub(crate) fn skip_identifier(text: &[u8]) -> Option<&[u8]> {
+    let text = text.trim_ascii_start();
+    if let Some(quoted) = text.strip_prefix(b"`") {
+        let mut start = 0;
+        loop {
+            let end = start + memchr::memchr(b'`', &quoted[start..])?;
+            if quoted.get(end + 1) == Some(&b'`') {
+                start = end + 2;
+            } else {
+                return Some(&quoted[end + 1..]);
+            }
+        }
+    } else {
+        let len = text
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_' || **b == b'$' || **b >= 0x80)
+            .count();
+        (len > 0).then(|| &text[len..])
+    }
+}
+
+/// Parse an identifier that may be qualified with a database name, as in
+/// `` `db`.`table` ``, returning `(database, name, rest)`.
+pub fn parse_qualified_identifier(text: &[u8]) -> Option<(Option<String>, String, &[u8])> {
+    let (first, rest) = parse_identifier(text)?;
+    match rest.strip_prefix(b".") {
+        Some(rest) => {
+            let (second, rest) = parse_identifier(rest)?;
+            Some((Some(first), second, rest))
+        }
+        None => Some((None, first, rest)),
+    }
+}
+
+/// Strip a leading `/*!NNNNN` version marker, so conditional statements can
+/// be matched like plain ones.
+pub fn strip_conditional_comment(text: &[u8]) -> &[u8] {
+    match text.strip_prefix(b"/*!") {
+        Some(rest) => rest[rest.iter().take_while(|b| b.is_ascii_digit()).count()..].trim_ascii_start(),
+        None => text,
+    }
+}
+
+/// Match a sequence of whitespace-separated keywords (case-insensitively) at
+/// the start of `text`, returning what follows them.
+pub fn strip_keywords<'a>(text: &'a [u8], keywords: &[&str]) -> Option<&'a [u8]> {
+    let mut rest = text.trim_ascii_start();
+    for keyword in keywords {
+        if !starts_with_ignore_case(rest, keyword.as_bytes()) {
+            return None;
+        }
+        let after = &rest[keyword.len()..];
+        match after.first() {
+            Some(b) if b.is_ascii_alphanumeric() || *b == b'_' => return None,
+            _ => rest = after.trim_ascii_start(),
+        }
+    }
+    Some(rest)
+}
+
+pub fn starts_with_ignore_case(text: &[u8], prefix: &[u8]) -> bool {
+    text.len() >= prefix.len() && text[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+pub fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    memchr::memmem::find(haystack, needle)
+}
+
+/// Backtick-quote an identifier, escaping any backticks in it.
+pub fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
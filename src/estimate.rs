@@ -0,0 +1,66 @@
+//! Estimating how long each table of a dump takes to restore, from its
+//! stats, to pick what to leave out to restore in a given time.
+
+use std::time::Duration;
+
+use crate::stats::TableStats;
+
+/// A restore's speed, in bytes of the dump a second, unless a measured one
+/// is given: a rough guess at the mysql client restoring into InnoDB.
+pub const DEFAULT_RESTORE_RATE: f64 = 10.0 * 1024.0 * 1024.0;
+
+/// What each key other than the primary key adds to the cost of writing a
+/// table's rows, as each row's written to it too.
+const INDEX_COST: f64 = 0.3;
+
+/// What each `FULLTEXT` or `SPATIAL` key adds, as they're far slower to
+/// build.
+const FULLTEXT_COST: f64 = 2.0;
+
+/// What each statement costs, as bytes of rows, for the round trip and
+/// commit, which are what's slow about a table of many small `INSERT`s.
+const STATEMENT_COST: f64 = 4096.0;
+
+/// How long a table's estimated to take to restore.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RestoreEstimate {
+    pub name: String,
+    /// The table's share of the restore's time, from 0 to 1
+    pub share: f64,
+    pub duration: Duration,
+}
+
+/// Estimate how long each of `tables` takes to restore, in the same order,
+/// for a restore going at `bytes_per_second`, such as one measured restoring
+/// the dump, or [`DEFAULT_RESTORE_RATE`].
+///
+/// The restore's whole time is its bytes at that rate. It's shared between
+/// the tables by a cost for each, of its bytes, weighted up by each key it
+/// has besides the primary key, more for a `FULLTEXT` one, and its
+/// statements. So a table's estimate is only as good as the rate, but how
+/// they compare holds whatever it is.
+pub fn estimate_restore(tables: &[TableStats], bytes_per_second: f64) -> Vec<RestoreEstimate> {
+    let costs: Vec<f64> = tables.iter().map(restore_cost).collect();
+    let total_cost: f64 = costs.iter().sum();
+    let total_bytes: u64 = tables.iter().map(|table| table.data_bytes + table.ddl_bytes).sum();
+    let total_seconds = total_bytes as f64 / bytes_per_second.max(f64::MIN_POSITIVE);
+    tables
+        .iter()
+        .zip(costs)
+        .map(|(table, cost)| {
+            let share = if total_cost > 0.0 { cost / total_cost } else { 0.0 };
+            RestoreEstimate {
+                name: table.name.clone(),
+                share,
+                duration: Duration::from_secs_f64(total_seconds * share),
+            }
+        })
+        .collect()
+}
+
+/// A table's cost to restore, relative to the others'.
+fn restore_cost(table: &TableStats) -> f64 {
+    let weight = 1.0 + INDEX_COST * table.indexes as f64 + FULLTEXT_COST * table.fulltext_indexes as f64;
+    table.data_bytes as f64 * weight + table.ddl_bytes as f64 + STATEMENT_COST * table.statements as f64
+}
@@ -0,0 +1,1911 @@
+//! Trimming a dump down to the tables matching the include / exclude filters,
+//! or a library user's own [`TableFilter`].
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::dump::{
+    classify_statement, strip_conditional_comment, SectionKind, SectionReader, Statement, StatementKind,
+};
+use crate::insert::{count_tuples, TupleCounter};
+use crate::schema::{parse_create_table, ForeignKey, TableSchema};
+use crate::sink::TableSink;
+use crate::transform::{alters_keys, FkChecks, TransformState, Transforms};
+use crate::visit::{scan_dump, StatementVisitor, StopWhen};
+
+/// Written before and after each table's data with `fast_restore`, saving and
+/// restoring the session's settings so the header's own are kept.
+const FAST_RESTORE_START: &[u8] = b"SET @FAST_RESTORE_AUTOCOMMIT=@@AUTOCOMMIT, @FAST_RESTORE_UNIQUE_CHECKS=@@UNIQUE_CHECKS, @FAST_RESTORE_FOREIGN_KEY_CHECKS=@@FOREIGN_KEY_CHECKS;\nSET AUTOCOMMIT=0, UNIQUE_CHECKS=0, FOREIGN_KEY_CHECKS=0;\n";
+const FAST_RESTORE_END: &[u8] = b"COMMIT;\nSET AUTOCOMMIT=@FAST_RESTORE_AUTOCOMMIT, UNIQUE_CHECKS=@FAST_RESTORE_UNIQUE_CHECKS, FOREIGN_KEY_CHECKS=@FAST_RESTORE_FOREIGN_KEY_CHECKS;\n";
+
+/// Written around the whole output with `FkChecks::Disable`, outside the
+/// header's and footer's own save and restore.
+const FK_CHECKS_DISABLE: &[u8] = b"SET @FK_CHECKS_DISABLE=@@FOREIGN_KEY_CHECKS, FOREIGN_KEY_CHECKS=0;\n";
+const FK_CHECKS_RESTORE: &[u8] = b"SET FOREIGN_KEY_CHECKS=@FK_CHECKS_DISABLE;\n";
+
+/// How many bytes are read between progress callbacks, besides the ones at
+/// the start of each table.
+const PROGRESS_INTERVAL: u64 = 1 << 20;
+
+/// What to keep of a dump and how to rewrite it, for [`extract_sql_with`].
+///
+/// ```no_run
+/// # use mysqltrim::extract::ExtractOptions;
+/// let options = ExtractOptions::new()
+///     .include(regex::Regex::new("^wp_").unwrap())
+///     .exclude(regex::Regex::new("_log$").unwrap())
+///     .schema_only(true);
+/// ```
+#[derive(Debug, Default)]
+pub struct ExtractOptions {
+    names: RegexFilter,
+    engines: EngineFilter,
+    filters: Vec<Filter>,
+    schema_only: bool,
+    schema_only_tables: Vec<Regex>,
+    data_only: bool,
+    transforms: Transforms,
+    buffer_size: Option<usize>,
+    pub(crate) read_buffer_size: Option<usize>,
+    pub(crate) max_statement_size: Option<usize>,
+    max_file_size: Option<u64>,
+    progress: Option<ProgressCallback>,
+    events: Option<EventCallback>,
+    count_rows: bool,
+    annotate: Option<String>,
+    cancel: Option<Arc<AtomicBool>>,
+    checkpoint: Option<(u64, CheckpointCallback)>,
+    resume: Option<Checkpoint>,
+    rest_to: Option<RestWriter>,
+}
+
+/// How far an extract has got, for [`ExtractOptions::progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Progress<'a> {
+    pub bytes_read: u64,
+    /// The table or view being read, if it's in one
+    pub table: Option<&'a str>,
+    /// Statements (and comment lines) read so far
+    pub statements: u64,
+}
+
+/// What's happened in an extract, for [`ExtractOptions::events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum ExtractEvent<'a> {
+    /// A table (or view) has been reached, and the filters have decided
+    /// whether to keep it
+    TableStarted { name: &'a str, kept: bool },
+    /// A table's section is over, with what it contributed
+    TableFinished(&'a TableOutcome),
+}
+
+/// A table (or view) an extract has reached, for a [`TableFilter`] to decide
+/// whether to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableContext<'a> {
+    pub name: &'a str,
+    /// The database the dump last switched to, in dumps made with
+    /// `--databases`
+    pub database: Option<&'a str>,
+    /// Bytes of the dump read before the table's section
+    pub bytes_read: u64,
+    /// Bytes written before the table's section
+    pub bytes_written: u64,
+    /// The table's storage engine, from its `CREATE TABLE`, if that's been
+    /// read (it hasn't for a view)
+    pub engine: Option<&'a str>,
+    /// The table's foreign keys, from its `CREATE TABLE`, or `None` if
+    /// they're not known, as for the tables listed on a server
+    pub foreign_keys: Option<&'a [ForeignKey]>,
+}
+
+/// Decides which tables (and views) to keep, for selecting them by more than
+/// their names, such as with an allowlist from elsewhere or a size budget.
+///
+/// ```no_run
+/// # use mysqltrim::extract::{ExtractOptions, TableContext};
+/// // Stop keeping tables once 100MiB has been written
+/// let options = ExtractOptions::new().filter(|table: &TableContext| table.bytes_written < 100 << 20);
+/// ```
+pub trait TableFilter {
+    fn keep(&self, table: &TableContext) -> bool;
+}
+
+impl<F: Fn(&TableContext) -> bool> TableFilter for F {
+    fn keep(&self, table: &TableContext) -> bool {
+        self(table)
+    }
+}
+
+/// Keeps the tables whose names match any of `include`, or that are one of
+/// `dependents_of` or have a foreign key referencing one, or all of them if
+/// both are empty, unless they match any of `exclude`. A table whose foreign
+/// keys aren't known is kept by `dependents_of`. [`ExtractOptions::include`],
+/// [`ExtractOptions::exclude`] and [`ExtractOptions::with_dependents`] add to
+/// one of these.
+#[derive(Debug, Clone, Default)]
+pub struct RegexFilter {
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+    pub dependents_of: Vec<String>,
+}
+
+impl RegexFilter {
+    /// Whether `table` is one of `dependents_of`, or references one.
+    fn is_dependent(&self, table: &TableContext) -> bool {
+        let references = |key: &ForeignKey| {
+            self.dependents_of.contains(&key.referenced_table)
+                && key.referenced_database.as_deref().is_none_or(|database| table.database == Some(database))
+        };
+        self.dependents_of.iter().any(|name| name == table.name)
+            || table.foreign_keys.is_none_or(|keys| keys.iter().any(references))
+    }
+}
+
+/// Read the dump from `reader` for `tables` and every table that references
+/// one of them by a foreign key, directly or through other tables, from the
+/// `CREATE TABLE` statements, to pass to [`ExtractOptions::with_dependents`]
+/// so it keeps the tables referencing those in turn. Statements are read
+/// whole, however long. It stops with a [`Cancelled`] error once `cancel` is
+/// set.
+pub fn find_dependents<R: BufRead>(reader: R, tables: &[String], cancel: Option<&AtomicBool>) -> io::Result<Vec<String>> {
+    /// The tables referencing each table
+    #[derive(Default)]
+    struct Dependents(HashMap<String, Vec<String>>);
+
+    impl StatementVisitor for Dependents {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            let Some(schema) = parse_create_table(&statement.text) else { return Ok(()) };
+            for foreign_key in schema.foreign_keys {
+                self.0.entry(foreign_key.referenced_table).or_default().push(schema.name.clone());
+            }
+            Ok(())
+        }
+    }
+
+    let mut dependents = Dependents::default();
+    scan_dump(reader, &mut StopWhen::new(&mut dependents, cancel))?;
+
+    let mut found = tables.to_vec();
+    let mut next = 0;
+    while let Some(table) = found.get(next) {
+        for dependent in dependents.0.get(table).into_iter().flatten() {
+            if !found.contains(dependent) {
+                found.push(dependent.clone());
+            }
+        }
+        next += 1;
+    }
+    Ok(found)
+}
+
+impl TableFilter for RegexFilter {
+    fn keep(&self, table: &TableContext) -> bool {
+        let included = (self.include.is_empty() && self.dependents_of.is_empty())
+            || self.include.iter().any(|regex| regex.is_match(table.name))
+            || (!self.dependents_of.is_empty() && self.is_dependent(table));
+        included && !self.exclude.iter().any(|regex| regex.is_match(table.name))
+    }
+}
+
+/// Keeps the tables whose storage engines are any of `include`, or all of
+/// them if it's empty, unless they're any of `exclude`, ignoring case. Those
+/// whose engines aren't known, such as views, are kept.
+/// [`ExtractOptions::engine`] and [`ExtractOptions::exclude_engine`] add to
+/// one of these.
+#[derive(Debug, Clone, Default)]
+pub struct EngineFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl TableFilter for EngineFilter {
+    fn keep(&self, table: &TableContext) -> bool {
+        let Some(engine) = table.engine else { return true };
+        let matches = |engines: &[String]| engines.iter().any(|name| name.eq_ignore_ascii_case(engine));
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+struct Filter(Box<dyn TableFilter>);
+
+impl std::fmt::Debug for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Filter")
+    }
+}
+
+struct ProgressCallback(Box<dyn FnMut(Progress)>);
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+struct EventCallback(Box<dyn FnMut(ExtractEvent)>);
+
+impl std::fmt::Debug for EventCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventCallback")
+    }
+}
+
+type SaveCheckpoint = dyn FnMut(&Checkpoint) -> io::Result<()>;
+
+struct CheckpointCallback(Box<SaveCheckpoint>);
+
+impl std::fmt::Debug for CheckpointCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CheckpointCallback")
+    }
+}
+
+struct RestWriter(Box<dyn Write>);
+
+impl std::fmt::Debug for RestWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RestWriter")
+    }
+}
+
+impl ExtractOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the tables (and views) matching this regex, or any of the
+    /// others passed. Everything is kept if none are.
+    pub fn include(mut self, regex: Regex) -> Self {
+        self.names.include.push(regex);
+        self
+    }
+
+    /// Drop the tables (and views) matching this regex, or any of the others
+    /// passed, even if they're included.
+    pub fn exclude(mut self, regex: Regex) -> Self {
+        self.names.exclude.push(regex);
+        self
+    }
+
+    /// Keep this table and the tables with foreign keys referencing it, or
+    /// any of the others passed, as well as those `include` keeps. Only the
+    /// tables referencing it directly are kept, as they're decided on as each
+    /// is reached, so pass the tables [`find_dependents`] finds for those
+    /// referencing them in turn.
+    pub fn with_dependents(mut self, table: impl Into<String>) -> Self {
+        self.names.dependents_of.push(table.into());
+        self
+    }
+
+    /// Only keep the tables whose storage engine is this, such as `InnoDB`,
+    /// or any of the others passed. Views are kept.
+    pub fn engine(mut self, engine: impl Into<String>) -> Self {
+        self.engines.include.push(engine.into());
+        self
+    }
+
+    /// Drop the tables whose storage engine is this, such as `MEMORY`, or any
+    /// of the others passed.
+    pub fn exclude_engine(mut self, engine: impl Into<String>) -> Self {
+        self.engines.exclude.push(engine.into());
+        self
+    }
+
+    /// Only keep the tables (and views) `filter` keeps, as well as the
+    /// include and exclude regexes and any other filters passed.
+    pub fn filter(mut self, filter: impl TableFilter + 'static) -> Self {
+        self.filters.push(Filter(Box::new(filter)));
+        self
+    }
+
+    /// Drop the rows, keeping each table's structure.
+    pub fn schema_only(mut self, schema_only: bool) -> Self {
+        self.schema_only = schema_only;
+        self
+    }
+
+    /// Drop the rows of the tables matching this regex, or any of the others
+    /// passed, keeping their structure.
+    pub fn schema_only_tables(mut self, regex: Regex) -> Self {
+        self.schema_only_tables.push(regex);
+        self
+    }
+
+    /// Drop the tables' structure, views and routines, keeping the rows.
+    pub fn data_only(mut self, data_only: bool) -> Self {
+        self.data_only = data_only;
+        self
+    }
+
+    pub fn transforms(mut self, transforms: Transforms) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// The size of the buffers for reading the input and writing the output,
+    /// rather than the standard library's default.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// The size of the buffer for reading the input, rather than the buffer
+    /// size, and the room the buffer gathering each statement starts with.
+    /// Big reads help on network filesystems.
+    pub fn read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = Some(read_buffer_size);
+        self
+    }
+
+    /// Read statements longer than this, such as an extended `INSERT` of
+    /// hundreds of megabytes, in pieces of about this size rather than all
+    /// at once. The rows of an `INSERT` are written as they're read, unless
+    /// a transform rewrites them, when the whole statement's gathered after
+    /// all. Sizes under 64KiB are taken as 64KiB.
+    pub fn max_statement_size(mut self, max_statement_size: usize) -> Self {
+        self.max_statement_size = Some(max_statement_size);
+        self
+    }
+
+    /// Write each table's output in parts of up to this many bytes, from
+    /// [`TableSink::open_part`], rather than in one piece, when writing each
+    /// table to its own writer. Parts are split between statements, each
+    /// starting with the dump's header, so a statement longer than this gets
+    /// a part of its own, which is longer.
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    pub(crate) fn reader<R: Read>(&self, input: R) -> BufReader<R> {
+        match self.read_buffer_size.or(self.buffer_size) {
+            Some(size) => BufReader::with_capacity(size, input),
+            None => BufReader::new(input),
+        }
+    }
+
+    /// Call `progress` as the extract goes: at the start of each table, after
+    /// every megabyte or so read, and once at the end.
+    pub fn progress(mut self, progress: impl FnMut(Progress) + 'static) -> Self {
+        self.progress = Some(ProgressCallback(Box::new(progress)));
+        self
+    }
+
+    /// Call `events` as each table (or view) starts and finishes.
+    pub fn events(mut self, events: impl FnMut(ExtractEvent) + 'static) -> Self {
+        self.events = Some(EventCallback(Box::new(events)));
+        self
+    }
+
+    /// Count the rows written for each table, in [`TableOutcome::rows`],
+    /// which means reading through every `INSERT` kept.
+    pub fn count_rows(mut self, count_rows: bool) -> Self {
+        self.count_rows = count_rows;
+        self
+    }
+
+    /// Write comments into the output recording how it was made: a header
+    /// with the version of mysqltrim and `description`, such as the command
+    /// line, and before and after each table's section, where it was in the
+    /// dump, whether it was kept, and its size and rows. Sets
+    /// [`count_rows`](Self::count_rows), for the rows.
+    pub fn annotate(mut self, description: impl Into<String>) -> Self {
+        self.annotate = Some(description.into());
+        self.count_rows = true;
+        self
+    }
+
+    /// Stop the extract with a [`Cancelled`] error once `cancel` is set, such
+    /// as from another thread. It's checked before each statement, so what's
+    /// been written so far ends on a whole statement. The scans passed these
+    /// options, such as [`compute_table_stats`](crate::stats::compute_table_stats)
+    /// and [`find_orphans`](crate::orphans::find_orphans), stop the same way.
+    pub fn cancel_when(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// The flag [`cancel_when`](Self::cancel_when) was passed.
+    pub(crate) fn cancel_flag(&self) -> Option<&Arc<AtomicBool>> {
+        self.cancel.as_ref()
+    }
+
+    /// Whether the flag [`cancel_when`](Self::cancel_when) was passed is set.
+    pub(crate) fn cancel_requested(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Call `checkpoint` with where the extract could be resumed from, after
+    /// every `interval` bytes read or so, once what's been written is
+    /// flushed. It's only called between the statements of a table or view,
+    /// and only when writing to one writer, as [`extract_sql_with`] does.
+    /// An error from it stops the extract.
+    pub fn checkpoint(mut self, interval: u64, checkpoint: impl FnMut(&Checkpoint) -> io::Result<()> + 'static) -> Self {
+        self.checkpoint = Some((interval, CheckpointCallback(Box::new(checkpoint))));
+        self
+    }
+
+    /// Carry on from `checkpoint`, with the input starting where its section
+    /// does and the output cut back to what had been written. The section is
+    /// read again up to the checkpoint without writing it, so the transforms
+    /// see its `CREATE TABLE` again, and the extract fails if it wouldn't
+    /// have written the same, as when the dump or the options have changed.
+    /// What the transforms learned before the section that changes what
+    /// they write, such as the databases the renames strip and the key fakes
+    /// are picked with, is carried on from the checkpoint, while what they
+    /// counted is lost.
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.resume = Some(checkpoint);
+        self
+    }
+
+    /// Write what the filters leave out to `rest` rather than dropping it:
+    /// the sections of the tables and views skipped, and the statements
+    /// [`schema_only`](Self::schema_only) and [`data_only`](Self::data_only)
+    /// drop, as they are in the dump, without the transforms. The dump's
+    /// header, `CREATE DATABASE` and `USE` statements and footer are written
+    /// to it too, so restoring it after the output restores what was left
+    /// out. It's flushed at the end.
+    pub fn rest_to(mut self, rest: impl Write + 'static) -> Self {
+        self.rest_to = Some(RestWriter(Box::new(rest)));
+        self
+    }
+
+    #[cfg(feature = "capi")]
+    pub(crate) fn without_progress(mut self) -> Self {
+        self.progress = None;
+        self
+    }
+
+    fn report_progress(&mut self, bytes_read: u64, table: Option<&str>, statements: u64) {
+        if let Some(ProgressCallback(progress)) = &mut self.progress {
+            progress(Progress { bytes_read, table, statements });
+        }
+    }
+
+    fn report_event(&mut self, event: ExtractEvent) {
+        if let Some(EventCallback(events)) = &mut self.events {
+            events(event);
+        }
+    }
+
+    fn write_rest(&mut self, text: &[u8]) -> io::Result<()> {
+        match &mut self.rest_to {
+            Some(RestWriter(rest)) => rest.write_all(text),
+            None => Ok(()),
+        }
+    }
+
+    /// The transforms, with what they've counted and learned during the
+    /// extract.
+    pub fn into_transforms(self) -> Transforms {
+        self.transforms
+    }
+
+    /// Whether the filters keep `table`, as when deciding what to dump
+    /// before there's a dump to trim.
+    pub fn keeps(&self, table: &TableContext) -> bool {
+        self.names.keep(table) && self.engines.keep(table) && self.filters.iter().all(|Filter(filter)| filter.keep(table))
+    }
+}
+
+/// Parse a size in bytes, such as `65536`, `64KiB` or `8MiB`. `K`, `M` and
+/// `G` (with or without `iB` or `B`) are all powers of 1024.
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let invalid = || format!("expected a size such as 65536, 64KiB or 8MiB, got `{}`", s);
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let number: usize = s[..digits].parse().map_err(|_| invalid())?;
+    let unit = match &s[digits..].trim_start().to_ascii_uppercase()[..] {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    match number.checked_mul(unit) {
+        Some(0) | None => Err(invalid()),
+        Some(size) => Ok(size),
+    }
+}
+
+/// A size in bytes such as `1.5 GiB`, for people to read.
+pub fn format_size(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let power = ((bytes.max(1.0).log2() / 10.0) as usize).min(units.len() - 1);
+    match power {
+        0 => format!("{} B", bytes as u64),
+        _ => format!("{:.1} {}", bytes / (1u64 << (10 * power)) as f64, units[power]),
+    }
+}
+
+/// The error an extract, or a scan for stats or the like, fails with once
+/// it's cancelled, inside an [`io::Error`] of kind [`io::ErrorKind::Other`]
+/// rather than `Interrupted`, which `read_exact`, `write_all` and the like
+/// retry after. [`is_cancelled`] tells it apart from other errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether `error` is from an extract or scan being cancelled.
+pub fn is_cancelled(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
+
+pub(crate) fn cancelled() -> io::Error {
+    io::Error::other(Cancelled)
+}
+
+/// What an extract read, wrote and skipped.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtractReport {
+    /// Each table and view, in the order of the dump
+    pub tables: Vec<TableOutcome>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Statements (and comment lines) read but not written, whether their
+    /// table was excluded or the options or transforms dropped them
+    pub statements_skipped: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub duration: Duration,
+}
+
+impl ExtractReport {
+    /// The names of the tables and views that were kept.
+    pub fn kept(&self) -> impl Iterator<Item = &str> {
+        self.tables.iter().filter(|table| table.kept).map(|table| table.name.as_str())
+    }
+
+    /// The outcome for the table or view named `name`.
+    pub fn table(&self, name: &str) -> Option<&TableOutcome> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+}
+
+/// Where an extract can be resumed from, for [`ExtractOptions::checkpoint`]
+/// and [`ExtractOptions::resume`]: between two statements of a table's (or
+/// view's) section, which is read again from its start.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    /// What had been read and written when the section started, which is
+    /// `bytes_read` into the dump, and the tables before it
+    pub section: ExtractReport,
+    /// Statements read before the section
+    pub statements: u64,
+    /// The database the dump had switched to before the section
+    pub database: Option<String>,
+    /// Bytes read, up to the end of a statement
+    pub bytes_read: u64,
+    /// Bytes written, which the output is cut back to when resuming
+    pub bytes_written: u64,
+    /// What the transforms had learned before the section. It holds the key
+    /// fakes and hashes are picked with, so keep it as private as the dump.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub transforms: TransformState,
+}
+
+#[cfg(feature = "serde")]
+impl Checkpoint {
+    /// The checkpoint as JSON, to be read back with
+    /// [`from_json`](Self::from_json) by a later run resuming from it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a checkpoint is plain data")
+    }
+
+    /// Read back a checkpoint saved by [`to_json`](Self::to_json).
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// What a table (or view) contributed to an extract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableOutcome {
+    pub name: String,
+    /// Whether the filters kept it
+    pub kept: bool,
+    pub statements: u64,
+    /// Rows written, if [`ExtractOptions::count_rows`] is set
+    pub rows: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Copy `input` to `output`, keeping only the tables (and views) whose names
+/// match `include` and don't match `exclude`, and applying `transforms`.
+///
+/// The header, footer, routines and `USE` statements are always kept. Returns
+/// the names of the tables that were kept, unordered. [`extract_sql_with`]
+/// takes the other options too, and reports on each table in the order of
+/// the dump.
+#[deprecated(note = "use extract_sql_with, whose report lists the tables in order")]
+pub fn extract_sql<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    include: Option<&Regex>,
+    exclude: Option<&Regex>,
+    transforms: &mut Transforms,
+) -> io::Result<HashSet<String>> {
+    let mut options = ExtractOptions::new().transforms(std::mem::take(transforms));
+    options.names.include.extend(include.cloned());
+    options.names.exclude.extend(exclude.cloned());
+    let result = extract(input, output, &mut options);
+    *transforms = options.into_transforms();
+    Ok(result?.kept().map(str::to_string).collect())
+}
+
+/// Copy `input` to `output`, keeping what `options` selects and applying its
+/// transforms, through buffers of its buffer size.
+///
+/// The header, footer and `USE` statements are always kept, and routines
+/// unless it's data only.
+pub fn extract_sql_with<R: Read, W: Write>(
+    input: R,
+    output: &mut W,
+    options: &mut ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let input = options.reader(input);
+    let mut output = match options.buffer_size {
+        Some(size) => BufWriter::with_capacity(size, output),
+        None => BufWriter::new(output),
+    };
+    let report = extract(input, &mut output, options)?;
+    output.flush()?;
+    Ok(report)
+}
+
+/// Copy the dump in `input`, such as a memory-mapped file, to `output` as
+/// [`extract_sql_with`] does, but scanning it in place rather than through a
+/// read buffer.
+pub fn extract_sql_from_slice<W: Write>(
+    input: &[u8],
+    output: &mut W,
+    options: &mut ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let mut output = match options.buffer_size {
+        Some(size) => BufWriter::with_capacity(size, output),
+        None => BufWriter::new(output),
+    };
+    let report = extract(input, &mut output, options)?;
+    output.flush()?;
+    Ok(report)
+}
+
+/// Copy each table (and view) `options` keeps from `input` to its own
+/// writer from `sink`, applying the transforms, and then finish the sink.
+///
+/// Each table's output starts with the dump's header, so it restores on its
+/// own, unless the sink [`appends`](TableSink::appends) to what's there,
+/// when only the table's rows are written. Routines, `USE` statements and the
+/// footer aren't written.
+pub fn extract_sql_per_table<R: Read, S: TableSink>(
+    input: R,
+    mut sink: S,
+    options: &mut ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let input = options.reader(input);
+    let mut output = PerTable {
+        sink: &mut sink,
+        header: Vec::new(),
+        in_header: true,
+        current: None,
+        max_file_size: options.max_file_size,
+    };
+    let report = extract(input, &mut output, options)?;
+    sink.finish()?;
+    Ok(report)
+}
+
+/// Copy each database of a dump made with `--databases` from `input` to its
+/// own writer from `sink`, keeping the tables `options` keeps and applying
+/// the transforms, and then finish the sink.
+///
+/// Each database's output starts with the dump's header and its `CREATE
+/// DATABASE` and `USE`, and ends with the footer, so it restores on its own.
+/// The writers are all closed at the end, as mysqldump comes back to each
+/// database for its views.
+pub fn extract_sql_per_database<R: Read, S: TableSink>(
+    input: R,
+    mut sink: S,
+    options: &mut ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let input = options.reader(input);
+    let mut output = PerDatabase {
+        sink: &mut sink,
+        header: Vec::new(),
+        footer: Vec::new(),
+        section: SectionKind::Header,
+        databases: Vec::new(),
+        current: None,
+    };
+    let report = extract(input, &mut output, options)?;
+    sink.finish()?;
+    Ok(report)
+}
+
+/// Copy each section of `input` that `options` keeps to its own writer from
+/// `sink`, applying the transforms, and then finish the sink.
+///
+/// Each writer's name starts with its place among them, padded to `digits`
+/// digits, followed by what the section is: `0042_wp_posts` for a table or
+/// view, and `0001_header`, `0002_database_shop`, `0099_routine_name` and
+/// `0100_footer` for the others. So the writers in the order of their names,
+/// as `cat *.sql` reads files, are what [`extract_sql`] would write, in the
+/// dump's order. Sections with nothing written get none, and an extract with
+/// too many to number in `digits` digits fails.
+pub fn extract_sql_numbered<R: Read, S: TableSink>(
+    input: R,
+    mut sink: S,
+    options: &mut ExtractOptions,
+    digits: usize,
+) -> io::Result<ExtractReport> {
+    let input = options.reader(input);
+    let mut output = Numbered { sink: &mut sink, digits, files: 0, section: None, current: None, pending: Vec::new() };
+    let report = extract(input, &mut output, options)?;
+    sink.finish()?;
+    Ok(report)
+}
+
+/// [`extract_sql_per_table`], writing the tables on `threads` threads while
+/// the dump's read, so compressing and writing them overlaps with parsing.
+/// Each table's written by one thread, with the tables taking turns between
+/// them, and the sink's `open` and `close` are called on the writing thread.
+/// The sink isn't asked whether it [`appends`](TableSink::appends), so each
+/// table's written whole.
+pub fn extract_sql_per_table_threaded<R, S>(
+    input: R,
+    sink: S,
+    options: &mut ExtractOptions,
+    threads: usize,
+) -> io::Result<ExtractReport>
+where
+    R: Read,
+    S: TableSink + Send,
+    S::Writer: Send,
+{
+    let input = options.reader(input);
+    let sink = Mutex::new(sink);
+    let report = std::thread::scope(|scope| {
+        let (writers, handles): (Vec<_>, Vec<_>) = (0..threads.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel(WRITE_QUEUE);
+                let sink = &sink;
+                (sender, scope.spawn(move || write_tables(sink, receiver)))
+            })
+            .unzip();
+        let mut output = PooledPerTable {
+            writers,
+            tables: 0,
+            header: Vec::new(),
+            in_header: true,
+            current: None,
+            database: None,
+            max_file_size: options.max_file_size,
+        };
+        let report = extract(input, &mut output, options);
+        // Hang up, so the threads finish once they've written what's queued
+        drop(output);
+        for handle in handles {
+            // A thread's error explains the extract's failing to send to it
+            handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+        }
+        report
+    })?;
+    sink.into_inner().unwrap_or_else(PoisonError::into_inner).finish()?;
+    Ok(report)
+}
+
+fn extract<R: BufRead, O: Output>(input: R, output: &mut O, options: &mut ExtractOptions) -> io::Result<ExtractReport> {
+    match options.resume.take() {
+        Some(checkpoint) => {
+            // What was written of the section before is already there
+            let skip = checkpoint.bytes_written.saturating_sub(checkpoint.section.bytes_written);
+            extract_from(input, &mut Resumed { inner: output, skip }, options, Some(checkpoint))
+        }
+        None => extract_from(input, output, options, None),
+    }
+}
+
+/// [`extract`], from the start of the dump or the section of a checkpoint.
+fn extract_from<R: BufRead, O: Output>(
+    input: R,
+    output: &mut O,
+    options: &mut ExtractOptions,
+    resume: Option<Checkpoint>,
+) -> io::Result<ExtractReport> {
+    let started = Instant::now();
+    let mut report = ExtractReport::default();
+    let mut current_section = usize::MAX;
+    // The section's entry in the report, if it's a table or view
+    let mut current_table: Option<usize> = None;
+    // The database of the last `CREATE DATABASE` or `USE`
+    let mut current_database = None;
+    let mut skip = false;
+    // Whether the section's rows are dropped
+    let mut schema_only = options.schema_only;
+    let mut in_data = false;
+    let mut statements = 0;
+    // Entered for the statements of each table or view
+    let mut _span = None;
+    // Where the table or view being read started, for checkpoints
+    let mut section_start = Checkpoint::default();
+    // The bytes read and written at the checkpoint being resumed from, until
+    // the extract's caught up with it
+    let mut resume_at = None;
+
+    match resume {
+        Some(checkpoint) => {
+            report = checkpoint.section;
+            statements = checkpoint.statements;
+            current_database = checkpoint.database;
+            options.transforms.resume(checkpoint.transforms);
+            resume_at = Some((checkpoint.bytes_read, checkpoint.bytes_written));
+        }
+        None => {
+            if let Some(description) = &options.annotate {
+                let mut header = format!("-- mysqltrim {}\n", env!("CARGO_PKG_VERSION"));
+                for line in description.lines() {
+                    header += &format!("-- mysqltrim: {}\n", line);
+                }
+                emit(output, &mut report, header.as_bytes())?;
+            }
+            if options.transforms.fk_checks == FkChecks::Disable {
+                emit(output, &mut report, FK_CHECKS_DISABLE)?;
+            }
+        }
+    }
+    let mut next_progress = report.bytes_read + PROGRESS_INTERVAL;
+    let mut next_checkpoint = match (&options.checkpoint, resume_at) {
+        (Some((interval, _)), Some((bytes_read, _))) => bytes_read + interval,
+        (Some((interval, _)), None) => *interval,
+        (None, _) => u64::MAX,
+    };
+
+    let mut reader = SectionReader::with_capacity(input, options.read_buffer_size.unwrap_or(0));
+    if let Some(size) = options.max_statement_size {
+        reader = reader.max_statement_size(size);
+    }
+    // Whether the rest of a statement read in pieces is written, while it's
+    // being read
+    let mut rest = None;
+    // Counting the rows of a kept INSERT read in pieces, for count_rows
+    let mut counter: Option<TupleCounter> = None;
+    // Whether the rest of a statement read in pieces is written to rest_to
+    let mut rest_to_rest = false;
+    // Statements read ahead of a table's first to find its CREATE TABLE,
+    // still to be gone through
+    let mut held = VecDeque::new();
+    while let Some(item) = held.pop_front().map(Ok).or_else(|| reader.next()) {
+        if options.cancel_requested() {
+            return Err(cancelled());
+        }
+        // Between statements, where a checkpoint can be resumed from
+        if rest.is_none() {
+            if let Some((bytes_read, bytes_written)) = resume_at.filter(|(bytes_read, _)| report.bytes_read >= *bytes_read) {
+                if report.bytes_read != bytes_read || report.bytes_written != bytes_written {
+                    return Err(resume_mismatch());
+                }
+                resume_at = None;
+            }
+            if report.bytes_read >= next_checkpoint {
+                if let (Some(table), Some((interval, CheckpointCallback(checkpoint)))) = (current_table, &mut options.checkpoint) {
+                    output.checkpoint()?;
+                    let mut at = section_start.clone();
+                    at.section.tables = report.tables[..table].to_vec();
+                    at.bytes_read = report.bytes_read;
+                    at.bytes_written = report.bytes_written;
+                    checkpoint(&at)?;
+                    next_checkpoint = report.bytes_read + *interval;
+                }
+            }
+        }
+        let (index, mut statement) = item?;
+        report.bytes_read += statement.text.len() as u64;
+        if let Some(write) = rest {
+            if !statement.continued {
+                rest = None;
+            }
+            if rest_to_rest {
+                options.write_rest(&statement.text)?;
+                rest_to_rest = statement.continued;
+            }
+            if let Some(table) = current_table {
+                report.tables[table].bytes_read += statement.text.len() as u64;
+            }
+            if write {
+                emit_rest(output, &mut report, &statement.text)?;
+                if let Some(table) = current_table {
+                    report.tables[table].bytes_written += statement.text.len() as u64;
+                    if let Some(counting) = &mut counter {
+                        counting.push(&statement.text);
+                        if !statement.continued {
+                            report.tables[table].rows += counting.count() as u64;
+                        }
+                    }
+                }
+            }
+            if !statement.continued {
+                counter = None;
+            }
+            if report.bytes_read >= next_progress {
+                let table = current_table.map(|table| report.tables[table].name.as_str());
+                options.report_progress(report.bytes_read, table, statements);
+                next_progress = report.bytes_read + PROGRESS_INTERVAL;
+            }
+            continue;
+        }
+        statements += 1;
+        if index != current_section {
+            if in_data {
+                emit(output, &mut report, FAST_RESTORE_END)?;
+                in_data = false;
+            }
+            if let (Some(table), Some(_)) = (current_table, &options.annotate) {
+                let note = table_note(&reader.sections()[current_section].kind, &report.tables[table], &section_start, true);
+                emit(output, &mut report, note.as_bytes())?;
+            }
+            current_section = index;
+            schema_only = options.schema_only;
+            if let Some(table) = current_table.take() {
+                options.report_event(ExtractEvent::TableFinished(&report.tables[table]));
+            }
+            _span = None;
+            let schema = match reader.sections()[index].kind {
+                SectionKind::Table(_) => read_create_table(&mut reader, index, &statement, &mut held)?,
+                _ => None,
+            };
+            let kind = &reader.sections()[index].kind;
+            skip = match kind {
+                SectionKind::Database(name) => {
+                    current_database = Some(name.clone());
+                    false
+                }
+                SectionKind::Table(name) | SectionKind::View(name) => {
+                    section_start = Checkpoint {
+                        section: ExtractReport {
+                            bytes_read: report.bytes_read - statement.text.len() as u64,
+                            bytes_written: report.bytes_written,
+                            statements_skipped: report.statements_skipped,
+                            ..Default::default()
+                        },
+                        statements: statements - 1,
+                        database: current_database.clone(),
+                        // Only worth copying when it's saved
+                        transforms: match options.checkpoint {
+                            Some(_) => options.transforms.state(),
+                            None => TransformState::default(),
+                        },
+                        ..Default::default()
+                    };
+                    schema_only |= options.schema_only_tables.iter().any(|regex| regex.is_match(name));
+                    let keep = options.keeps(&TableContext {
+                        name,
+                        database: current_database.as_deref(),
+                        bytes_read: report.bytes_read - statement.text.len() as u64,
+                        bytes_written: report.bytes_written,
+                        engine: schema.as_ref().and_then(|schema| schema.option("ENGINE")),
+                        foreign_keys: Some(schema.as_ref().map_or(&[], |schema| &schema.foreign_keys)),
+                    });
+                    _span = Some(tracing::info_span!("table", name = %name).entered());
+                    if keep {
+                        tracing::debug!("keeping");
+                    } else {
+                        tracing::debug!("skipping, as it's filtered out");
+                    }
+                    current_table = Some(report.tables.len());
+                    report.tables.push(TableOutcome {
+                        name: name.clone(),
+                        kept: keep,
+                        statements: 0,
+                        rows: 0,
+                        bytes_read: 0,
+                        bytes_written: 0,
+                    });
+                    options.report_event(ExtractEvent::TableStarted { name, kept: keep });
+                    !keep
+                }
+                _ => false,
+            };
+            output.enter(kind, skip)?;
+            if let (Some(table), Some(_), false) = (current_table, &options.annotate, skip) {
+                let note = table_note(kind, &report.tables[table], &section_start, false);
+                emit(output, &mut report, note.as_bytes())?;
+            }
+            if let Some(table) = current_table {
+                options.report_progress(report.bytes_read, Some(&report.tables[table].name), statements);
+            }
+        }
+        if let Some(table) = current_table {
+            report.tables[table].statements += 1;
+            report.tables[table].bytes_read += statement.text.len() as u64;
+        }
+        if report.bytes_read >= next_progress {
+            let table = current_table.map(|table| report.tables[table].name.as_str());
+            options.report_progress(report.bytes_read, table, statements);
+            next_progress = report.bytes_read + PROGRESS_INTERVAL;
+        }
+
+        if skip {
+            report.statements_skipped += 1;
+            rest = statement.continued.then_some(false);
+            options.write_rest(&statement.text)?;
+            rest_to_rest = statement.continued;
+            continue;
+        }
+        let is_data = is_data_statement(&statement.text);
+        if statement.continued && !(is_data && options.transforms.keeps_rows()) {
+            // A transform needs all of it
+            let read = statement.text.len() as u64;
+            reader.read_rest(&mut statement)?;
+            let rest = statement.text.len() as u64 - read;
+            report.bytes_read += rest;
+            if let Some(table) = current_table {
+                report.tables[table].bytes_read += rest;
+            }
+        }
+        // Applied even to dropped statements, as scrubs learn the column
+        // order from the CREATE TABLE
+        let text = options.transforms.apply(&statement.text)?;
+        let dropped = match &reader.sections()[index].kind {
+            SectionKind::Table(_) if options.data_only => !is_data && !statement.is_trivia(),
+            SectionKind::Table(_) => schema_only && is_data,
+            SectionKind::View(_) | SectionKind::Routine(_) => options.data_only,
+            _ => false,
+        };
+        let text = text.filter(|_| !dropped);
+        if statement.continued {
+            rest = Some(text.is_some());
+        }
+        let shared = matches!(
+            reader.sections()[index].kind,
+            SectionKind::Header | SectionKind::Database(_) | SectionKind::Footer
+        );
+        if dropped || shared {
+            options.write_rest(&statement.text)?;
+            rest_to_rest = statement.continued;
+        }
+        match text {
+            Some(text) => {
+                // Only around the rows written, so there's no empty wrapper
+                // for rows that are all dropped
+                let data = options.transforms.fast_restore && is_data;
+                if in_data && !data {
+                    emit(output, &mut report, FAST_RESTORE_END)?;
+                    in_data = false;
+                } else if data && !in_data {
+                    emit(output, &mut report, FAST_RESTORE_START)?;
+                    in_data = true;
+                }
+                emit(output, &mut report, &text)?;
+                if let Some(table) = current_table {
+                    report.tables[table].bytes_written += text.len() as u64;
+                    if options.count_rows && is_data {
+                        if statement.continued {
+                            counter = TupleCounter::new(&text);
+                        } else {
+                            report.tables[table].rows += count_tuples(&text).map_or(0, |rows| rows as u64);
+                        }
+                    }
+                }
+            }
+            None => {
+                tracing::trace!(offset = statement.offset, "dropped a statement");
+                report.statements_skipped += 1;
+            }
+        }
+    }
+
+    if resume_at.is_some_and(|at| at != (report.bytes_read, report.bytes_written)) {
+        return Err(resume_mismatch());
+    }
+    if let Some(table) = current_table {
+        options.report_event(ExtractEvent::TableFinished(&report.tables[table]));
+    }
+    if in_data {
+        emit(output, &mut report, FAST_RESTORE_END)?;
+    }
+    if let (Some(table), Some(_)) = (current_table, &options.annotate) {
+        let note = table_note(&reader.sections()[current_section].kind, &report.tables[table], &section_start, true);
+        emit(output, &mut report, note.as_bytes())?;
+    }
+    if options.transforms.fk_checks == FkChecks::Disable {
+        emit(output, &mut report, FK_CHECKS_RESTORE)?;
+    }
+    output.close()?;
+    if let Some(RestWriter(rest)) = &mut options.rest_to {
+        rest.flush()?;
+    }
+    options.report_progress(report.bytes_read, None, statements);
+    report.duration = started.elapsed();
+    Ok(report)
+}
+
+/// The `CREATE TABLE` of the table whose section `index` starts with
+/// `first`, reading ahead to it and holding back what's read in `held`, up
+/// to the first statement of the next section. It's not looked for past the
+/// table's rows or anything read in pieces.
+fn read_create_table<R: BufRead>(
+    reader: &mut SectionReader<R>,
+    index: usize,
+    first: &Statement,
+    held: &mut VecDeque<(usize, Statement)>,
+) -> io::Result<Option<TableSchema>> {
+    let schema = |statement: &Statement| match classify_statement(&statement.text) {
+        StatementKind::CreateTable(_) if !statement.continued => Some(parse_create_table(&statement.text)),
+        _ if statement.continued || is_data_statement(&statement.text) => Some(None),
+        _ => None,
+    };
+    if let Some(found) = schema(first) {
+        return Ok(found);
+    }
+    while let Some((section, statement)) = reader.next().transpose()? {
+        let found = if section == index { schema(&statement) } else { Some(None) };
+        held.push_back((section, statement));
+        if let Some(found) = found {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
+
+/// The comment [`ExtractOptions::annotate`] writes at the start of a kept
+/// table's (or view's) section, or at its `end`, which for a skipped one is
+/// the only one.
+fn table_note(kind: &SectionKind, table: &TableOutcome, start: &Checkpoint, end: bool) -> String {
+    let noun = if matches!(kind, SectionKind::View(_)) { "view" } else { "table" };
+    let offset = start.section.bytes_read;
+    match (table.kept, end) {
+        (true, false) => format!("-- mysqltrim: {} {} (kept) from offset 0x{:X}\n", noun, table.name, offset),
+        (true, true) if noun == "view" => {
+            format!("-- mysqltrim: end of view {} (kept, {})\n", table.name, format_size(table.bytes_read as f64))
+        }
+        (true, true) => format!(
+            "-- mysqltrim: end of table {} (kept, {}, {} row{})\n",
+            table.name,
+            format_size(table.bytes_read as f64),
+            thousands(table.rows),
+            if table.rows == 1 { "" } else { "s" }
+        ),
+        (false, _) => format!(
+            "-- mysqltrim: {} {} (skipped, {}) from offset 0x{:X}\n",
+            noun,
+            table.name,
+            format_size(table.bytes_read as f64),
+            offset
+        ),
+    }
+}
+
+/// A number with its thousands separated, such as `3,401,223`.
+fn thousands(number: u64) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn emit<O: Output>(output: &mut O, report: &mut ExtractReport, text: &[u8]) -> io::Result<()> {
+    report.bytes_written += text.len() as u64;
+    output.emit(text)
+}
+
+fn emit_rest<O: Output>(output: &mut O, report: &mut ExtractReport, text: &[u8]) -> io::Result<()> {
+    report.bytes_written += text.len() as u64;
+    output.emit_rest(text)
+}
+
+fn resume_mismatch() -> io::Error {
+    io::Error::other("the extract doesn't match its checkpoint, as the dump or the options have changed since")
+}
+
+/// Where [`extract`] writes: one writer for everything, or a writer for each
+/// table from a [`TableSink`].
+trait Output {
+    /// Start writing a section, whose statements are all dropped if it's
+    /// `skipped`.
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()>;
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()>;
+
+    /// Write a piece of a statement read in pieces, after its start, which
+    /// mustn't be split from it.
+    fn emit_rest(&mut self, text: &[u8]) -> io::Result<()> {
+        self.emit(text)
+    }
+
+    /// Flush what's been written, for a checkpoint.
+    fn checkpoint(&mut self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "checkpoints are only taken writing to one writer"))
+    }
+
+    /// Finish writing, after the last section.
+    fn close(&mut self) -> io::Result<()>;
+}
+
+impl<W: Write> Output for W {
+    fn enter(&mut self, _kind: &SectionKind, _skipped: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        self.write_all(text)
+    }
+
+    fn checkpoint(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// Writes to another output once `skip` bytes have been, which were written
+/// before a checkpoint.
+struct Resumed<'a, O> {
+    inner: &'a mut O,
+    skip: u64,
+}
+
+impl<O: Output> Output for Resumed<'_, O> {
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()> {
+        self.inner.enter(kind, skipped)
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        let skipped = self.skip.min(text.len() as u64);
+        self.skip -= skipped;
+        match &text[skipped as usize..] {
+            [] => Ok(()),
+            text => self.inner.emit(text),
+        }
+    }
+
+    fn emit_rest(&mut self, text: &[u8]) -> io::Result<()> {
+        let skipped = self.skip.min(text.len() as u64);
+        self.skip -= skipped;
+        match &text[skipped as usize..] {
+            [] => Ok(()),
+            text => self.inner.emit_rest(text),
+        }
+    }
+
+    fn checkpoint(&mut self) -> io::Result<()> {
+        self.inner.checkpoint()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.inner.close()
+    }
+}
+
+/// Writes each table's and view's section to its own writer from `sink`,
+/// after the dump's header. Other sections aren't written.
+struct PerTable<'a, S: TableSink> {
+    sink: &'a mut S,
+    header: Vec<u8>,
+    in_header: bool,
+    current: Option<TableFile<S::Writer>>,
+    /// The size to split each table's output into parts at
+    max_file_size: Option<u64>,
+}
+
+/// The file a table's being written to.
+struct TableFile<W> {
+    table: String,
+    writer: W,
+    /// Its part of the table, when split into parts
+    part: Option<usize>,
+    /// The bytes written to it, including the header
+    bytes: u64,
+    /// Whether it's being added to, so only the table's rows are written
+    appending: bool,
+    /// Whether the statement being written is dropped, as it isn't rows
+    dropping: bool,
+}
+
+impl<S: TableSink> PerTable<'_, S> {
+    fn open(&mut self, table: String, part: Option<usize>) -> io::Result<()> {
+        let appending = part.is_none() && self.sink.appends(&table);
+        let mut writer = match part {
+            Some(part) => self.sink.open_part(&table, part)?,
+            None => self.sink.open(&table)?,
+        };
+        if !appending {
+            writer.write_all(&self.header)?;
+        }
+        let bytes = if appending { 0 } else { self.header.len() as u64 };
+        self.current = Some(TableFile { table, writer, part, bytes, appending, dropping: false });
+        Ok(())
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(TableFile { table, writer, part: Some(part), .. }) => self.sink.close_part(&table, part, writer),
+            Some(TableFile { table, writer, part: None, .. }) => self.sink.close(&table, writer),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&mut self, text: &[u8]) -> io::Result<()> {
+        match &mut self.current {
+            Some(file) if file.dropping => Ok(()),
+            Some(file) => {
+                file.bytes += text.len() as u64;
+                file.writer.write_all(text)
+            }
+            None if self.in_header => {
+                self.header.extend_from_slice(text);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: TableSink> Output for PerTable<'_, S> {
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()> {
+        self.close_current()?;
+        self.in_header = *kind == SectionKind::Header;
+        if let SectionKind::Database(name) = kind {
+            self.sink.use_database(name)?;
+        }
+        if let (SectionKind::Table(name) | SectionKind::View(name), false) = (kind, skipped) {
+            self.open(name.clone(), self.max_file_size.map(|_| 1))?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        // Start the next part if this statement would take the file over,
+        // unless the file has nothing but the header
+        let header = self.header.len() as u64;
+        if let (Some(max), Some(file)) = (self.max_file_size, &self.current) {
+            if file.bytes > header && file.bytes + text.len() as u64 > max {
+                let (table, part) = (file.table.clone(), file.part.map_or(1, |part| part + 1));
+                self.close_current()?;
+                self.open(table, Some(part))?;
+            }
+        }
+        if let Some(file) = self.current.as_mut().filter(|file| file.appending) {
+            file.dropping = !is_data_statement(text);
+        }
+        self.write(text)
+    }
+
+    fn emit_rest(&mut self, text: &[u8]) -> io::Result<()> {
+        self.write(text)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.close_current()
+    }
+}
+
+/// Writes each database's sections to its own writer from `sink`, after the
+/// dump's header, and the footer to each once the dump's read.
+struct PerDatabase<'a, S: TableSink> {
+    sink: &'a mut S,
+    header: Vec<u8>,
+    footer: Vec<u8>,
+    section: SectionKind,
+    /// Each database's writer, in the order they were first seen
+    databases: Vec<(String, S::Writer)>,
+    /// The current database's index in `databases`
+    current: Option<usize>,
+}
+
+impl<S: TableSink> Output for PerDatabase<'_, S> {
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()> {
+        self.section = kind.clone();
+        match kind {
+            SectionKind::Database(name) => {
+                let index = match self.databases.iter().position(|(database, _)| database == name) {
+                    Some(index) => index,
+                    None => {
+                        self.sink.use_database(name)?;
+                        let mut writer = self.sink.open(name)?;
+                        writer.write_all(&self.header)?;
+                        self.databases.push((name.clone(), writer));
+                        self.databases.len() - 1
+                    }
+                };
+                self.current = Some(index);
+            }
+            SectionKind::Table(name) | SectionKind::View(name) | SectionKind::Routine(name)
+                if self.current.is_none() && !skipped =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("`{}` comes before any CREATE DATABASE or USE, so the dump can't be split by database", name),
+                ));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        match (&self.section, self.current) {
+            (SectionKind::Header, _) => self.header.extend_from_slice(text),
+            (SectionKind::Footer, _) => self.footer.extend_from_slice(text),
+            (_, Some(current)) => self.databases[current].1.write_all(text)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        for (database, mut writer) in self.databases.drain(..) {
+            writer.write_all(&self.footer)?;
+            self.sink.use_database(&database)?;
+            self.sink.close(&database, writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes each section to its own writer from `sink`, opened on its first
+/// statement and named with how many have been opened, for
+/// [`extract_sql_numbered`].
+struct Numbered<'a, S: TableSink> {
+    sink: &'a mut S,
+    digits: usize,
+    /// How many writers have been opened
+    files: usize,
+    /// What the section being written is, if it's kept
+    section: Option<String>,
+    /// The writer being written to, and its name
+    current: Option<(String, S::Writer)>,
+    /// What's been written outside a section kept, for the next writer
+    pending: Vec<u8>,
+}
+
+impl<S: TableSink> Numbered<'_, S> {
+    fn open(&mut self, section: &str) -> io::Result<()> {
+        self.files += 1;
+        if self.files >= 10usize.saturating_pow(self.digits as u32) {
+            return Err(io::Error::other(format!(
+                "there are more sections than can be numbered in {} digits",
+                self.digits
+            )));
+        }
+        let name = format!("{:0width$}_{}", self.files, section, width = self.digits);
+        let mut writer = self.sink.open(&name)?;
+        writer.write_all(&std::mem::take(&mut self.pending))?;
+        self.current = Some((name, writer));
+        Ok(())
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some((name, writer)) => self.sink.close(&name, writer),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: TableSink> Output for Numbered<'_, S> {
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()> {
+        self.close_current()?;
+        self.section = match kind {
+            _ if skipped => None,
+            SectionKind::Header => Some("header".to_string()),
+            SectionKind::Database(name) => Some(format!("database_{}", name)),
+            SectionKind::Table(name) | SectionKind::View(name) => Some(name.clone()),
+            SectionKind::Routine(name) => Some(format!("routine_{}", name)),
+            SectionKind::Footer => Some("footer".to_string()),
+        };
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        if self.current.is_none() {
+            match self.section.take() {
+                Some(section) => self.open(&section)?,
+                None => {
+                    self.pending.extend_from_slice(text);
+                    return Ok(());
+                }
+            }
+        }
+        match &mut self.current {
+            Some((_, writer)) => writer.write_all(text),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        // Such as what restores the session after a last table that's
+        // skipped, in a dump with no footer
+        if !self.pending.is_empty() && self.current.is_none() {
+            self.open("footer")?;
+        }
+        self.close_current()
+    }
+}
+
+/// How much of a table is gathered before it's sent to its writing thread.
+const WRITE_BATCH: usize = 64 << 10;
+
+/// How many batches can be waiting for each writing thread.
+const WRITE_QUEUE: usize = 16;
+
+/// What [`PooledPerTable`] sends a writing thread.
+enum WriteTable {
+    /// Open a writer for the table, or the part of it, in the database the
+    /// dump last switched to, and write the header
+    Open { table: String, part: Option<usize>, database: Option<String>, header: Vec<u8> },
+    Write(Vec<u8>),
+    Close,
+}
+
+/// Writes each table's and view's section as [`PerTable`] does, but by
+/// sending it to the writing threads in turn.
+struct PooledPerTable {
+    writers: Vec<mpsc::SyncSender<WriteTable>>,
+    /// How many tables have been sent, to pick the next one's thread
+    tables: usize,
+    header: Vec<u8>,
+    in_header: bool,
+    current: Option<PooledTable>,
+    /// The database the dump last switched to
+    database: Option<String>,
+    /// The size to split each table's output into parts at
+    max_file_size: Option<u64>,
+}
+
+/// The table being sent to a writing thread.
+struct PooledTable {
+    table: String,
+    /// The thread writing it
+    writer: usize,
+    /// What's not been sent to it yet
+    pending: Vec<u8>,
+    /// The part of the table being written, when split into parts
+    part: Option<usize>,
+    /// The bytes of the part, including the header
+    bytes: u64,
+}
+
+impl PooledPerTable {
+    fn send(&self, writer: usize, message: WriteTable) -> io::Result<()> {
+        self.writers[writer].send(message).map_err(|_| io::Error::other("a writing thread stopped"))
+    }
+
+    fn open(&mut self, table: String, writer: usize, part: Option<usize>) -> io::Result<()> {
+        let (database, header) = (self.database.clone(), self.header.clone());
+        self.send(writer, WriteTable::Open { table: table.clone(), part, database, header })?;
+        let (pending, bytes) = (Vec::with_capacity(WRITE_BATCH), self.header.len() as u64);
+        self.current = Some(PooledTable { table, writer, pending, part, bytes });
+        Ok(())
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(PooledTable { writer, pending, .. }) => {
+                if !pending.is_empty() {
+                    self.send(writer, WriteTable::Write(pending))?;
+                }
+                self.send(writer, WriteTable::Close)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn write(&mut self, text: &[u8]) -> io::Result<()> {
+        match &mut self.current {
+            Some(current) => {
+                current.bytes += text.len() as u64;
+                current.pending.extend_from_slice(text);
+                if current.pending.len() >= WRITE_BATCH {
+                    let (writer, batch) =
+                        (current.writer, std::mem::replace(&mut current.pending, Vec::with_capacity(WRITE_BATCH)));
+                    self.send(writer, WriteTable::Write(batch))?;
+                }
+                Ok(())
+            }
+            None if self.in_header => {
+                self.header.extend_from_slice(text);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Output for PooledPerTable {
+    fn enter(&mut self, kind: &SectionKind, skipped: bool) -> io::Result<()> {
+        self.close_current()?;
+        self.in_header = *kind == SectionKind::Header;
+        if let SectionKind::Database(name) = kind {
+            self.database = Some(name.clone());
+        }
+        if let (SectionKind::Table(name) | SectionKind::View(name), false) = (kind, skipped) {
+            let writer = self.tables % self.writers.len();
+            self.tables += 1;
+            self.open(name.clone(), writer, self.max_file_size.map(|_| 1))?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        // The next part goes to the same thread, to be written after this one
+        let header = self.header.len() as u64;
+        if let (Some(max), Some(current)) = (self.max_file_size, &self.current) {
+            if current.bytes > header && current.bytes + text.len() as u64 > max {
+                let (table, writer) = (current.table.clone(), current.writer);
+                let part = current.part.map_or(1, |part| part + 1);
+                self.close_current()?;
+                self.open(table, writer, Some(part))?;
+            }
+        }
+        self.write(text)
+    }
+
+    fn emit_rest(&mut self, text: &[u8]) -> io::Result<()> {
+        self.write(text)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.close_current()
+    }
+}
+
+/// A writing thread of [`extract_sql_per_table_threaded`]: write the tables
+/// it's sent until the extract hangs up.
+fn write_tables<S: TableSink>(sink: &Mutex<S>, tables: mpsc::Receiver<WriteTable>) -> io::Result<()> {
+    // Switching to the table's database under the same lock as opening or
+    // closing it, so another thread's table can't switch it in between
+    let lock = |database: &Option<String>| -> io::Result<MutexGuard<S>> {
+        let mut sink = sink.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(database) = database {
+            sink.use_database(database)?;
+        }
+        Ok(sink)
+    };
+    let mut current = None;
+    for message in tables {
+        match message {
+            WriteTable::Open { table, part, database, header } => {
+                let mut writer = match part {
+                    Some(part) => lock(&database)?.open_part(&table, part)?,
+                    None => lock(&database)?.open(&table)?,
+                };
+                writer.write_all(&header)?;
+                current = Some((table, part, database, writer));
+            }
+            WriteTable::Write(text) => {
+                if let Some((_, _, _, writer)) = &mut current {
+                    writer.write_all(&text)?;
+                }
+            }
+            WriteTable::Close => match current.take() {
+                Some((table, Some(part), database, writer)) => lock(&database)?.close_part(&table, part, writer)?,
+                Some((table, None, database, writer)) => lock(&database)?.close(&table, writer)?,
+                None => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Whether a statement is part of loading a table's rows: the `INSERT`s and
+/// the `LOCK TABLES` and `DISABLE KEYS` around them.
+fn is_data_statement(text: &[u8]) -> bool {
+    let text = strip_conditional_comment(text.trim_ascii_start());
+    alters_keys(text)
+        || matches!(
+            classify_statement(text),
+            StatementKind::Insert(_) | StatementKind::Replace(_) | StatementKind::Lock(_) | StatementKind::Unlock
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymize::Fake;
+    use crate::scrub::{Replacement, ScrubColumn, ScrubIps};
+
+    fn dump() -> String {
+        let mut dump = String::from("/*!40101 SET NAMES utf8mb4 */;\n");
+        for table in ["posts", "comments", "users"] {
+            dump.push_str(&format!(
+                "DROP TABLE IF EXISTS `{table}`;\nCREATE TABLE `{table}` (\n  `id` int NOT NULL,\n  `body` text,\n  PRIMARY KEY (`id`)\n) ENGINE=InnoDB;\n"
+            ));
+            for id in 0..50 {
+                dump.push_str(&format!("INSERT INTO `{table}` VALUES ({id},'row {id}');\n"));
+            }
+        }
+        dump
+    }
+
+    fn fast_restore() -> ExtractOptions {
+        let transforms = Transforms { fast_restore: true, ..Default::default() };
+        ExtractOptions::new().exclude(Regex::new("^comments$").unwrap()).transforms(transforms)
+    }
+
+    /// Extract `dump` with checkpoints, resume from the `nth` taken in the
+    /// middle of a section after the header, and check the resumed output's
+    /// the same as the whole run's.
+    fn resume_matches(dump: &str, options: fn() -> ExtractOptions, nth: usize) {
+        let mut fresh = Vec::new();
+        let checkpoints = Arc::new(Mutex::new(Vec::new()));
+        let saved = checkpoints.clone();
+        let mut whole = options().checkpoint(64, move |at| {
+            // Through JSON, as the command saves them
+            #[cfg(feature = "serde")]
+            let at = &Checkpoint::from_json(&at.to_json()).unwrap();
+            saved.lock().unwrap().push(at.clone());
+            Ok(())
+        });
+        extract_sql_with(dump.as_bytes(), &mut fresh, &mut whole).unwrap();
+
+        let checkpoints = checkpoints.lock().unwrap();
+        let resumed_from = checkpoints
+            .iter()
+            .filter(|at| at.bytes_read > at.section.bytes_read && at.section.bytes_read > 0)
+            .nth(nth)
+            .cloned()
+            .expect("a checkpoint in the middle of a section");
+
+        let section = resumed_from.section.bytes_read as usize;
+        let mut resumed = fresh[..resumed_from.bytes_written as usize].to_vec();
+        let mut options = options().resume(resumed_from);
+        extract_sql_with(&dump.as_bytes()[section..], &mut resumed, &mut options).unwrap();
+        assert_eq!(String::from_utf8(resumed).unwrap(), String::from_utf8(fresh).unwrap());
+    }
+
+    /// Reads a little at a time, setting `cancel` once `after` bytes have
+    /// been read, as another thread might partway through a dump.
+    struct CancelAfter<'a> {
+        input: &'a [u8],
+        after: usize,
+        cancel: Arc<AtomicBool>,
+    }
+
+    impl Read for CancelAfter<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf.len().min(256);
+            let read = self.input.read(&mut buf[..length])?;
+            self.after = self.after.saturating_sub(read);
+            if self.after == 0 {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn cancelling_mid_dump() {
+        let dump = dump();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let input = CancelAfter { input: dump.as_bytes(), after: 2000, cancel: cancel.clone() };
+        let mut output = Vec::new();
+        let mut options = ExtractOptions::new().read_buffer_size(256).cancel_when(cancel);
+        let error = extract_sql_with(input, &mut output, &mut options).unwrap_err();
+        assert!(is_cancelled(&error));
+        // Not Interrupted, which read_exact and write_all would retry after
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert!(output.len() < dump.len());
+        assert!(output.ends_with(b";\n"));
+    }
+
+    #[test]
+    fn resuming_in_a_section() {
+        resume_matches(&dump(), fast_restore, 10);
+    }
+
+    #[test]
+    fn resuming_with_what_the_transforms_learned() {
+        let mut dump = String::from("CREATE DATABASE /*!32312 IF NOT EXISTS*/ `shop`;\nUSE `shop`;\n");
+        for table in ["users", "logins"] {
+            dump.push_str(&format!(
+                "DROP TABLE IF EXISTS `{table}`;\nCREATE TABLE `{table}` (\n  `id` int NOT NULL,\n  `email` varchar(100),\n  `ip` varchar(40),\n  `created` datetime,\n  PRIMARY KEY (`id`)\n) ENGINE=InnoDB;\n"
+            ));
+            for id in 0..50 {
+                dump.push_str(&format!(
+                    "INSERT INTO `{table}` VALUES ({id},'user{}@example.com','192.168.1.{id}','2024-01-{:02} 12:00:00');\n",
+                    id % 10,
+                    id % 28 + 1
+                ));
+            }
+        }
+        dump.push_str("/*!50001 DROP VIEW IF EXISTS `recent`*/;\n/*!50001 CREATE VIEW `recent` AS select `id` from `shop`.`Users` */;\n");
+
+        fn options() -> ExtractOptions {
+            let mut transforms = Transforms::default();
+            transforms.renames.strip_databases();
+            transforms.renames.lowercase();
+            for table in ["users", "logins"] {
+                transforms.scrubs.insert(ScrubColumn {
+                    table: table.to_string(),
+                    column: "email".to_string(),
+                    replacement: Replacement::Fake(Fake::Email),
+                    rows: None,
+                });
+            }
+            transforms.scrubs.scrub_ips(ScrubIps::Hash);
+            transforms.scrubs.shift_dates("random:P1Y".parse().unwrap());
+            ExtractOptions::new().transforms(transforms)
+        }
+
+        // In the second table, after the first's fakes and shifts were picked
+        resume_matches(&dump, options, 60);
+    }
+
+    /// Each file's name and what was written to it
+    type Written = Vec<(String, Vec<u8>)>;
+
+    /// The files written to it, in the order they were closed.
+    #[derive(Clone, Default)]
+    struct Files(Arc<Mutex<Written>>);
+
+    impl TableSink for Files {
+        type Writer = Vec<u8>;
+
+        fn open(&mut self, _table: &str) -> io::Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn close(&mut self, table: &str, writer: Vec<u8>) -> io::Result<()> {
+            self.0.lock().unwrap().push((table.to_string(), writer));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parts_with_a_statement_over_the_size() {
+        let header = "/*!40101 SET NAMES utf8mb4 */;\n";
+        let create = "DROP TABLE IF EXISTS `posts`;\nCREATE TABLE `posts` (\n  `id` int NOT NULL,\n  `body` text\n);\n";
+        let small = "INSERT INTO `posts` VALUES (1,'small');\n";
+        let large = format!("INSERT INTO `posts` VALUES (2,'{}');\n", "x".repeat(300));
+        let dump = [header, create, small, &large, small].concat();
+
+        for threads in [None, Some(2)] {
+            let files = Files::default();
+            let mut options = ExtractOptions::new().max_file_size(200);
+            match threads {
+                Some(threads) => extract_sql_per_table_threaded(dump.as_bytes(), files.clone(), &mut options, threads),
+                None => extract_sql_per_table(dump.as_bytes(), files.clone(), &mut options),
+            }
+            .unwrap();
+            let files = files.0.lock().unwrap();
+            let files: Vec<(&str, String)> =
+                files.iter().map(|(name, file)| (name.as_str(), String::from_utf8_lossy(file).into_owned())).collect();
+            // Written whole in a part of its own, between the parts before
+            // and after it, each with the header
+            assert_eq!(
+                files,
+                [
+                    ("posts.001", [header, create, small].concat()),
+                    ("posts.002", [header, &large].concat()),
+                    ("posts.003", [header, small].concat()),
+                ]
+            );
+        }
+    }
+}
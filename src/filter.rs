@@ -0,0 +1,219 @@
+//! Dropping selected rows as a dump is written out.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use regex::Regex;
+
+use crate::insert::{insert_table, parse_insert, parse_value, split_values};
+use crate::schema::parse_create_table;
+
+/// Drop the rows of the tables matching `tables` whose `column` starts with
+/// one of `prefixes`.
+#[derive(Debug, Clone)]
+pub struct DropRows {
+    pub tables: Regex,
+    pub column: String,
+    pub prefixes: Vec<String>,
+}
+
+impl DropRows {
+    /// WordPress's transients: cached values kept in `wp_options` (and each
+    /// multisite site's options table) and a network's `wp_sitemeta`, for
+    /// tables named with `table_prefix`.
+    pub fn wordpress_transients(table_prefix: &str) -> Vec<DropRows> {
+        let prefix = regex::escape(table_prefix);
+        let transients = || vec!["_transient_".to_string(), "_site_transient_".to_string()];
+        vec![
+            DropRows {
+                tables: Regex::new(&format!("^{}([0-9]+_)?options$", prefix)).unwrap(),
+                column: "option_name".to_string(),
+                prefixes: transients(),
+            },
+            DropRows {
+                tables: Regex::new(&format!("^{}sitemeta$", prefix)).unwrap(),
+                column: "meta_key".to_string(),
+                prefixes: transients(),
+            },
+        ]
+    }
+
+    fn matches(&self, value: &[u8]) -> bool {
+        parse_value(value)
+            .as_bytes()
+            .is_some_and(|value| self.prefixes.iter().any(|prefix| value.starts_with(prefix.as_bytes())))
+    }
+}
+
+/// How many of a table's rows to keep, and how many have been.
+#[derive(Debug, Clone, Default)]
+struct RowLimit {
+    /// The most rows to keep
+    limit: Option<u64>,
+    /// The share of the rows to keep, spread evenly through the table
+    sample: Option<f64>,
+    seen: u64,
+    kept: u64,
+}
+
+impl RowLimit {
+    /// Whether the table's next row is kept.
+    fn keep(&mut self) -> bool {
+        self.seen += 1;
+        if let Some(rate) = self.sample {
+            // Each row whose share brings the total up to another whole row
+            if (self.seen as f64 * rate).floor() <= ((self.seen - 1) as f64 * rate).floor() {
+                return false;
+            }
+        }
+        if self.limit.is_some_and(|limit| self.kept >= limit) {
+            return false;
+        }
+        self.kept += 1;
+        true
+    }
+}
+
+/// Parse a `table=N` row limit.
+pub fn parse_limit_rows(s: &str) -> Result<(String, u64), String> {
+    match s.rsplit_once('=') {
+        Some((table, rows)) if !table.trim().is_empty() => match rows.trim().parse() {
+            Ok(rows) => Ok((table.trim().to_string(), rows)),
+            Err(_) => Err(format!("expected table=N, got `{}`", s)),
+        },
+        _ => Err(format!("expected table=N, got `{}`", s)),
+    }
+}
+
+/// Parse a `table=RATE` sample, such as `wp_posts=0.1` for one row in ten.
+pub fn parse_sample_rows(s: &str) -> Result<(String, f64), String> {
+    match s.rsplit_once('=') {
+        Some((table, rate)) if !table.trim().is_empty() => match rate.trim().parse::<f64>() {
+            Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok((table.trim().to_string(), rate)),
+            _ => Err(format!("expected table=RATE, with a rate above 0 and up to 1, got `{}`", s)),
+        },
+        _ => Err(format!("expected table=RATE, with a rate above 0 and up to 1, got `{}`", s)),
+    }
+}
+
+/// The rows to drop, and what's been learned of their tables' schemas.
+#[derive(Debug, Clone, Default)]
+pub struct RowFilters {
+    filters: Vec<DropRows>,
+    /// How many rows of each table to keep
+    limits: HashMap<String, RowLimit>,
+    /// Column names of each filtered table, from its `CREATE TABLE`
+    columns: HashMap<String, Vec<String>>,
+    /// The number of rows dropped from each table, in the order they came in
+    dropped: Vec<(String, u64)>,
+}
+
+impl RowFilters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, filter: DropRows) {
+        self.filters.push(filter);
+    }
+
+    /// Keep only the first `rows` rows of `table`, or of those `sample`
+    /// keeps. A later limit for the same table replaces it.
+    pub fn limit(&mut self, table: &str, rows: u64) {
+        self.limits.entry(table.to_string()).or_default().limit = Some(rows);
+    }
+
+    /// Keep a share of `table`'s rows, from 0 to 1, spread evenly through
+    /// it, such as every tenth row for 0.1. A later sample for the same
+    /// table replaces it.
+    pub fn sample(&mut self, table: &str, rate: f64) {
+        self.limits.entry(table.to_string()).or_default().sample = Some(rate);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.limits.is_empty()
+    }
+
+    /// The number of rows dropped from each table that had any dropped.
+    pub fn dropped(&self) -> &[(String, u64)] {
+        &self.dropped
+    }
+
+    /// Drop the filtered rows from an `INSERT`, or the whole statement if
+    /// none are left, and learn the column order of filtered tables from
+    /// their `CREATE TABLE`.
+    ///
+    /// Fails if a filtered table doesn't have the filter's column, or its
+    /// rows come before its `CREATE TABLE` without listing their columns.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> io::Result<Option<Cow<'a, [u8]>>> {
+        if self.is_empty() {
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+
+        if let Some(schema) = parse_create_table(text) {
+            let filters = self.filters.iter().filter(|filter| filter.tables.is_match(&schema.name));
+            let mut filtered = false;
+            for filter in filters {
+                if schema.column_index(&filter.column).is_none() {
+                    return Err(invalid_input(format!("table `{}` has no column `{}`", schema.name, filter.column)));
+                }
+                filtered = true;
+            }
+            if filtered {
+                let columns = schema.columns.into_iter().map(|column| column.name).collect();
+                self.columns.insert(schema.name, columns);
+            }
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+
+        let table = match insert_table(text) {
+            Some(table)
+                if self.limits.contains_key(&table) || self.filters.iter().any(|filter| filter.tables.is_match(&table)) =>
+            {
+                table
+            }
+            _ => return Ok(Some(Cow::Borrowed(text))),
+        };
+        let Some(insert) = parse_insert(text) else { return Ok(Some(Cow::Borrowed(text))) };
+        let mut filters = Vec::new();
+        for filter in self.filters.iter().filter(|filter| filter.tables.is_match(&table)) {
+            let columns = match (&insert.columns, self.columns.get(&table)) {
+                (Some(columns), _) | (None, Some(columns)) => columns,
+                (None, None) => return Err(invalid_input(format!("found rows for `{}` before its CREATE TABLE", table))),
+            };
+            let index = columns.iter().position(|column| column.eq_ignore_ascii_case(&filter.column)).ok_or_else(|| {
+                invalid_input(format!("INSERT into `{}` does not include column `{}`", table, filter.column))
+            })?;
+            filters.push((index, filter));
+        }
+
+        let mut limit = self.limits.get_mut(&table);
+        let kept: Vec<&[u8]> = insert
+            .tuples
+            .iter()
+            .copied()
+            .filter(|tuple| {
+                let values = split_values(tuple);
+                !filters.iter().any(|(index, filter)| values.get(*index).is_some_and(|value| filter.matches(value)))
+                    && limit.as_mut().is_none_or(|limit| limit.keep())
+            })
+            .collect();
+        let dropped = (insert.tuples.len() - kept.len()) as u64;
+        if dropped == 0 {
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+        match self.dropped.iter_mut().find(|(name, _)| *name == table) {
+            Some((_, count)) => *count += dropped,
+            None => self.dropped.push((table, dropped)),
+        }
+        if kept.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Cow::Owned(insert.with_tuples(kept))))
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
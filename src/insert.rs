@@ -0,0 +1,368 @@
+//! Splitting extended `INSERT` statements into their row tuples and values.
+
+use crate::dump::{parse_identifier, skip_identifier, strip_keywords};
+use crate::schema::parse_identifier_list;
+
+/// An `INSERT` (or `REPLACE`) statement, borrowing from the statement text.
+#[derive(Debug, Clone)]
+pub struct Insert<'a> {
+    pub table: String,
+    /// Column list, when the dump was made with `--complete-insert`
+    pub columns: Option<Vec<String>>,
+    /// Everything up to and including `VALUES`
+    pub prefix: &'a [u8],
+    /// Each row, including its parentheses
+    pub tuples: Vec<&'a [u8]>,
+    /// Everything after the last row, usually `;\n`
+    pub suffix: &'a [u8],
+}
+
+impl Insert<'_> {
+    /// Rebuild the statement with a subset (or rewritten set) of tuples.
+    pub fn with_tuples<'t>(&self, tuples: impl IntoIterator<Item = &'t [u8]>) -> Vec<u8> {
+        let mut text = self.prefix.to_vec();
+        text.push(b' ');
+        for (i, tuple) in tuples.into_iter().enumerate() {
+            if i > 0 {
+                text.push(b',');
+            }
+            text.extend_from_slice(tuple);
+        }
+        text.extend_from_slice(self.suffix);
+        text
+    }
+}
+
+/// Parse `INSERT [IGNORE] INTO tbl [(cols)] VALUES (...),(...);` or the
+/// `REPLACE INTO` equivalent. Returns `None` for anything else, including
+/// `INSERT ... SELECT`.
+pub fn parse_insert(text: &[u8]) -> Option<Insert<'_>> {
+    let start = text.len() - text.trim_ascii_start().len();
+    let (table, rest) = parse_identifier(insert_target(text)?)?;
+    let (columns, rest) = match parse_identifier_list(rest) {
+        Some((columns, rest)) => (Some(columns), rest),
+        None => (None, rest),
+    };
+    let values = strip_keywords(rest, &["VALUES"]).or_else(|| strip_keywords(rest, &["VALUE"]))?;
+    let prefix_end = text.len() - values.len();
+    let prefix = text[start..prefix_end].trim_ascii_end();
+
+    let mut tuples = Vec::new();
+    let end = for_each_tuple(text, prefix_end, |tuple| tuples.push(tuple))?;
+    Some(Insert { table, columns, prefix, tuples, suffix: &text[end..] })
+}
+
+/// The number of rows an `INSERT` (or `REPLACE`) writes, as
+/// `parse_insert(text).map(|insert| insert.tuples.len())` but without
+/// allocating.
+pub fn count_tuples(text: &[u8]) -> Option<usize> {
+    let rest = skip_identifier(insert_target(text)?)?.trim_ascii_start();
+    // A column list is parenthesised like a tuple
+    let rest = match tuple_length(rest) {
+        Some(length) => &rest[length..],
+        None => rest,
+    };
+    let values = strip_keywords(rest, &["VALUES"]).or_else(|| strip_keywords(rest, &["VALUE"]))?;
+    let mut count = 0;
+    for_each_tuple(text, text.len() - values.len(), |_| count += 1)?;
+    Some(count)
+}
+
+/// Counts the rows of an `INSERT` (or `REPLACE`) read in pieces, as
+/// [`count_tuples`] does for a whole one.
+#[derive(Debug, Clone, Default)]
+pub struct TupleCounter {
+    count: usize,
+    depth: usize,
+    quote: Option<u8>,
+    escaped: bool,
+    /// Whether a row has just ended, so a comma or the end of the rows
+    /// comes next
+    after_row: bool,
+    done: bool,
+}
+
+impl TupleCounter {
+    /// Start counting with the first piece of a statement, or `None` if it
+    /// isn't an `INSERT` whose rows start in it.
+    pub fn new(head: &[u8]) -> Option<Self> {
+        let rest = skip_identifier(insert_target(head)?)?.trim_ascii_start();
+        let rest = match tuple_length(rest) {
+            Some(length) => &rest[length..],
+            None => rest,
+        };
+        let values = strip_keywords(rest, &["VALUES"]).or_else(|| strip_keywords(rest, &["VALUE"]))?;
+        let mut counter = TupleCounter::default();
+        counter.push(values);
+        Some(counter)
+    }
+
+    /// Count the rows in the next piece of the statement.
+    pub fn push(&mut self, mut piece: &[u8]) {
+        while !self.done && !piece.is_empty() {
+            if let Some(quote) = self.quote {
+                if self.escaped {
+                    self.escaped = false;
+                    piece = &piece[1..];
+                    continue;
+                }
+                // A doubled quote closes the string and opens it again
+                let Some(at) = memchr::memchr2(b'\\', quote, piece) else { return };
+                if piece[at] == b'\\' {
+                    self.escaped = true;
+                } else {
+                    self.quote = None;
+                }
+                piece = &piece[at + 1..];
+                continue;
+            }
+
+            let b = piece[0];
+            piece = &piece[1..];
+            if self.after_row && !b.is_ascii_whitespace() {
+                self.after_row = false;
+                self.done = b != b',';
+                continue;
+            }
+            match b {
+                b'\'' | b'"' => self.quote = Some(b),
+                b'(' => {
+                    if self.depth == 0 {
+                        self.count += 1;
+                    }
+                    self.depth += 1;
+                }
+                b')' if self.depth > 0 => {
+                    self.depth -= 1;
+                    self.after_row = self.depth == 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// The table an `INSERT` (or `REPLACE`) statement writes to, without parsing
+/// its rows.
+pub fn insert_table(text: &[u8]) -> Option<String> {
+    parse_identifier(insert_target(text)?).map(|(table, _)| table)
+}
+
+/// The rest of an `INSERT` (or `REPLACE`) statement, from the table's name.
+pub fn insert_target(text: &[u8]) -> Option<&[u8]> {
+    strip_keywords(text, &["INSERT", "IGNORE", "INTO"])
+        .or_else(|| strip_keywords(text, &["INSERT", "INTO"]))
+        .or_else(|| strip_keywords(text, &["REPLACE", "INTO"]))
+}
+
+/// Call `f` with each comma-separated tuple of `text` from `position`,
+/// returning where the last one ends, or `None` if one doesn't.
+fn for_each_tuple<'a>(text: &'a [u8], mut position: usize, mut f: impl FnMut(&'a [u8])) -> Option<usize> {
+    loop {
+        position += text[position..].len() - text[position..].trim_ascii_start().len();
+        let length = tuple_length(&text[position..])?;
+        f(&text[position..position + length]);
+        position += length;
+        let after = text[position..].trim_ascii_start();
+        match after.first() {
+            Some(b',') => position = text.len() - after.len() + 1,
+            _ => return Some(position),
+        }
+    }
+}
+
+/// How many bytes are looked at one by one before skipping ahead with
+/// memchr, which only pays off over longer runs. Most values are short.
+const SHORT_RUN: usize = 32;
+
+/// Length of the parenthesised tuple at the start of `text`, respecting quoted
+/// strings and nested parentheses.
+fn tuple_length(text: &[u8]) -> Option<usize> {
+    if text.first() != Some(&b'(') {
+        return None;
+    }
+    let mut depth = 1;
+    let mut i = 1;
+    loop {
+        let rest = &text[i..];
+        let short = &rest[..rest.len().min(SHORT_RUN)];
+        let found = match short.iter().position(|b| matches!(b, b'\'' | b'"' | b'(' | b')')) {
+            Some(at) => i + at,
+            None => {
+                let at = i + short.len() + memchr::memchr3(b'\'', b'"', b')', &rest[short.len()..])?;
+                // Outside quotes, so each opens a nested pair
+                depth += memchr::memchr_iter(b'(', &text[i + short.len()..at]).count();
+                at
+            }
+        };
+        match text[found] {
+            b'(' => {
+                depth += 1;
+                i = found + 1;
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(found + 1);
+                }
+                i = found + 1;
+            }
+            _ => i = found + quoted_length(&text[found..])?,
+        }
+    }
+}
+
+/// Length of the quoted string at the start of `text`, including both quotes.
+/// Handles backslash escapes and doubled quotes.
+fn quoted_length(text: &[u8]) -> Option<usize> {
+    let quote = text[0];
+    let mut i = 1;
+    loop {
+        let rest = text.get(i..)?;
+        let short = &rest[..rest.len().min(SHORT_RUN)];
+        i += match short.iter().position(|&b| b == b'\\' || b == quote) {
+            Some(at) => at,
+            None => short.len() + memchr::memchr2(b'\\', quote, &rest[short.len()..])?,
+        };
+        // Skip an escaped character or a doubled quote
+        if text[i] == b'\\' || text.get(i + 1) == Some(&quote) {
+            i += 2;
+        } else {
+            return Some(i + 1);
+        }
+    }
+}
+
+/// Split a tuple such as `(1,'a,b',NULL)` into its values, as written.
+pub fn split_values(tuple: &[u8]) -> Vec<&[u8]> {
+    let inner = tuple.strip_prefix(b"(").unwrap_or(tuple);
+    let inner = inner.strip_suffix(b")").unwrap_or(inner);
+    let mut values = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < inner.len() {
+        match inner[i] {
+            b'\'' | b'"' => i += quoted_length(&inner[i..]).unwrap_or(inner.len() - i) - 1,
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b',' if depth == 0 => {
+                values.push(inner[start..i].trim_ascii());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    values.push(inner[start..].trim_ascii());
+    values
+}
+
+/// A single value from a row, as parsed from its SQL literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<'a> {
+    Null,
+    /// A quoted string, with escapes resolved
+    String(Vec<u8>),
+    /// Anything else (numbers, hex literals, expressions), as written
+    Literal(&'a [u8]),
+}
+
+impl Value<'_> {
+    /// The value's bytes, for comparing values regardless of how they were
+    /// quoted. `None` for NULL.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Null => None,
+            Value::String(bytes) => Some(bytes),
+            Value::Literal(bytes) => Some(bytes),
+        }
+    }
+}
+
+/// Parse a value as returned by [`split_values`].
+pub fn parse_value(raw: &[u8]) -> Value<'_> {
+    let raw = raw.trim_ascii();
+    if raw.eq_ignore_ascii_case(b"NULL") {
+        return Value::Null;
+    }
+    let quoted = raw.strip_prefix(b"_binary").map(|rest| rest.trim_ascii_start()).unwrap_or(raw);
+    match quoted.first() {
+        Some(&quote @ (b'\'' | b'"')) if quoted.len() >= 2 && quoted.ends_with(&[quote]) => {
+            Value::String(unescape(&quoted[1..quoted.len() - 1], quote))
+        }
+        _ => Value::Literal(raw),
+    }
+}
+
+/// Quote `bytes` as a string literal, escaped the way mysqldump does.
+pub fn quote_string(bytes: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for &b in bytes {
+        match b {
+            0 => quoted.extend_from_slice(b"\\0"),
+            b'\n' => quoted.extend_from_slice(b"\\n"),
+            b'\r' => quoted.extend_from_slice(b"\\r"),
+            0x1a => quoted.extend_from_slice(b"\\Z"),
+            b'\\' | b'\'' | b'"' => quoted.extend_from_slice(&[b'\\', b]),
+            b => quoted.push(b),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Quote `bytes` as a string literal in place of the literal `raw`, keeping
+/// its `_binary` introducer if it had one.
+pub fn requote(raw: &[u8], bytes: &[u8]) -> Vec<u8> {
+    let mut literal = if raw.trim_ascii_start().starts_with(b"_binary") { b"_binary ".to_vec() } else { Vec::new() };
+    literal.extend_from_slice(&quote_string(bytes));
+    literal
+}
+
+/// Build a tuple from its values, as written.
+pub fn join_values<'v>(values: impl IntoIterator<Item = &'v [u8]>) -> Vec<u8> {
+    let mut tuple = vec![b'('];
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            tuple.push(b',');
+        }
+        tuple.extend_from_slice(value);
+    }
+    tuple.push(b')');
+    tuple
+}
+
+/// Resolve the backslash escapes mysqldump writes, and doubled quotes.
+fn unescape(text: &[u8], quote: u8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'\\' if i + 1 < text.len() => {
+                i += 1;
+                bytes.push(match text[i] {
+                    b'0' => 0,
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'Z' => 0x1a,
+                    b'b' => 0x08,
+                    other => other,
+                });
+            }
+            b if b == quote && text.get(i + 1) == Some(&quote) => {
+                bytes.push(quote);
+                i += 1;
+            }
+            b => bytes.push(b),
+        }
+        i += 1;
+    }
+    bytes
+}
@@ -0,0 +1,210 @@
+//! Keeping only the newest rows of each table, those with the highest keys
+//! of its auto-increment primary key, for trimming a dump to recent data.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, BufRead};
+use std::sync::atomic::AtomicBool;
+
+use crate::dump::Statement;
+use crate::insert::{insert_table, parse_insert, parse_value, split_values};
+use crate::schema::{parse_create_table, TableSchema};
+use crate::visit::{scan_dump, StatementVisitor, StopWhen};
+
+/// The rows of each table to keep, found by [`find_latest`], and what's been
+/// learned of the tables' columns while keeping them.
+#[derive(Debug, Clone, Default)]
+pub struct KeepLatest {
+    /// Each table with more rows than are kept: its key column, and the
+    /// lowest key kept
+    tables: HashMap<String, (String, i128)>,
+    /// Column names of each of them, from its `CREATE TABLE`
+    columns: HashMap<String, Vec<String>>,
+    /// The number of rows dropped from each table, in the order they came in
+    dropped: Vec<(String, u64)>,
+}
+
+/// Read the dump from `reader` to find the `rows` rows of each table with
+/// the highest keys, by the single-column `AUTO_INCREMENT` primary key its
+/// `CREATE TABLE` has. Tables without one, or without more rows than that,
+/// are kept whole. Statements are read whole, however long. It stops with a
+/// [`Cancelled`](crate::extract::Cancelled) error once `cancel` is set.
+pub fn find_latest<R: BufRead>(reader: R, rows: usize, cancel: Option<&AtomicBool>) -> io::Result<KeepLatest> {
+    struct Latest {
+        rows: usize,
+        /// The key column of each table with one, and its position
+        keys: HashMap<String, (String, usize)>,
+        /// The highest keys of each table so far, lowest first, and how many
+        /// rows it has
+        highest: HashMap<String, (BinaryHeap<Reverse<i128>>, u64)>,
+    }
+
+    impl StatementVisitor for Latest {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            let Some(schema) = parse_create_table(&statement.text) else { return Ok(()) };
+            if let Some(key) = auto_increment_key(&schema) {
+                self.keys.insert(schema.name, key);
+            }
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            let Some((key, position)) = self.keys.get(table) else { return Ok(()) };
+            let Some(insert) = parse_insert(&statement.text) else { return Ok(()) };
+            let position = match &insert.columns {
+                Some(columns) => match columns.iter().position(|column| column.eq_ignore_ascii_case(key)) {
+                    Some(position) => position,
+                    None => return Ok(()),
+                },
+                None => *position,
+            };
+            let (highest, count) = self.highest.entry(table.to_string()).or_default();
+            for tuple in &insert.tuples {
+                *count += 1;
+                let Some(key) = split_values(tuple).get(position).and_then(|value| parse_key(value)) else { continue };
+                if highest.len() < self.rows {
+                    highest.push(Reverse(key));
+                } else if highest.peek().is_some_and(|Reverse(lowest)| key > *lowest) {
+                    highest.pop();
+                    highest.push(Reverse(key));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut latest = Latest { rows, keys: HashMap::new(), highest: HashMap::new() };
+    scan_dump(reader, &mut StopWhen::new(&mut latest, cancel))?;
+
+    let Latest { keys, highest, .. } = latest;
+    let tables = highest
+        .into_iter()
+        .filter(|(_, (_, count))| *count > rows as u64)
+        .map(|(table, (highest, _))| {
+            let lowest = highest.peek().map_or(i128::MAX, |Reverse(lowest)| *lowest);
+            let key = keys[&table].0.clone();
+            (table, (key, lowest))
+        })
+        .collect();
+    Ok(KeepLatest { tables, ..Default::default() })
+}
+
+/// The column of a table's primary key and its position, if it's a single
+/// `AUTO_INCREMENT` column.
+fn auto_increment_key(schema: &TableSchema) -> Option<(String, usize)> {
+    let [key] = &schema.primary_key[..] else { return None };
+    let position = schema.column_index(key)?;
+    let column = &schema.columns[position];
+    column.auto_increment().then(|| (column.name.clone(), position))
+}
+
+fn parse_key(value: &[u8]) -> Option<i128> {
+    std::str::from_utf8(parse_value(value).as_bytes()?).ok()?.parse().ok()
+}
+
+impl KeepLatest {
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// The number of rows dropped from each table that had any dropped.
+    pub fn dropped(&self) -> &[(String, u64)] {
+        &self.dropped
+    }
+
+    /// Drop the rows of an `INSERT` older than the ones kept, or the whole
+    /// statement if none are left, and learn the column order of the tables
+    /// from their `CREATE TABLE`.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if self.is_empty() {
+            return Some(Cow::Borrowed(text));
+        }
+
+        if let Some(schema) = parse_create_table(text) {
+            if self.tables.contains_key(&schema.name) {
+                let columns = schema.columns.into_iter().map(|column| column.name).collect();
+                self.columns.insert(schema.name, columns);
+            }
+            return Some(Cow::Borrowed(text));
+        }
+
+        let table = match insert_table(text) {
+            Some(table) if self.tables.contains_key(&table) => table,
+            _ => return Some(Cow::Borrowed(text)),
+        };
+        let Some(insert) = parse_insert(text) else { return Some(Cow::Borrowed(text)) };
+        let (key, lowest) = &self.tables[&table];
+        let position = match (&insert.columns, self.columns.get(&table)) {
+            (Some(columns), _) | (None, Some(columns)) => {
+                columns.iter().position(|column| column.eq_ignore_ascii_case(key))
+            }
+            (None, None) => None,
+        };
+        let Some(position) = position else { return Some(Cow::Borrowed(text)) };
+
+        let kept: Vec<&[u8]> = insert
+            .tuples
+            .iter()
+            .copied()
+            .filter(|tuple| {
+                let key = split_values(tuple).get(position).and_then(|value| parse_key(value));
+                key.is_none_or(|key| key >= *lowest)
+            })
+            .collect();
+        let dropped = (insert.tuples.len() - kept.len()) as u64;
+        if dropped == 0 {
+            return Some(Cow::Borrowed(text));
+        }
+        match self.dropped.iter_mut().find(|(name, _)| *name == table) {
+            Some((_, count)) => *count += dropped,
+            None => self.dropped.push((table, dropped)),
+        }
+        if kept.is_empty() {
+            return None;
+        }
+        Some(Cow::Owned(insert.with_tuples(kept)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENTS: &[&str] = &[
+        "CREATE TABLE `logs` (\n  `id` bigint NOT NULL AUTO_INCREMENT,\n  `message` text,\n  PRIMARY KEY (`id`)\n);\n",
+        "INSERT INTO `logs` VALUES (1,'one'),(2,'two'),(3,'three');\n",
+        "INSERT INTO `logs` VALUES (4,'four'),(9,'nine'),(5,'five'),(8,'eight');\n",
+        "INSERT INTO `logs` (`message`, `id`) VALUES ('seven',7),('six',6),('ten',10);\n",
+        "CREATE TABLE `options` (\n  `id` int NOT NULL AUTO_INCREMENT,\n  PRIMARY KEY (`id`)\n);\n",
+        "INSERT INTO `options` VALUES (1),(2),(3);\n",
+        "CREATE TABLE `meta` (\n  `name` varchar(64) NOT NULL,\n  PRIMARY KEY (`name`)\n);\n",
+        "INSERT INTO `meta` VALUES ('a'),('b'),('c'),('d'),('e');\n",
+    ];
+
+    #[test]
+    fn straddling_the_lowest_kept() {
+        let mut latest = find_latest(STATEMENTS.concat().as_bytes(), 4, None).unwrap();
+        let kept: Vec<String> = STATEMENTS
+            .iter()
+            .filter_map(|statement| latest.rewrite(statement.as_bytes()))
+            .map(|text| String::from_utf8(text.into_owned()).unwrap())
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                STATEMENTS[0],
+                // The older rows of a statement are dropped and the newer
+                // kept, and a statement with only older rows is dropped
+                "INSERT INTO `logs` VALUES (9,'nine'),(8,'eight');\n",
+                "INSERT INTO `logs` (`message`, `id`) VALUES ('seven',7),('ten',10);\n",
+                // No more rows than are kept, and no auto-increment key
+                STATEMENTS[4],
+                STATEMENTS[5],
+                STATEMENTS[6],
+                STATEMENTS[7],
+            ]
+        );
+        assert_eq!(latest.dropped(), [("logs".to_string(), 6)]);
+    }
+}
@@ -0,0 +1,39 @@
+pub mod anonymize;
+pub mod bench;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checksum;
+pub mod convert;
+pub mod date;
+pub mod dump;
+pub mod estimate;
+pub mod extract;
+pub mod filter;
+pub mod insert;
+pub mod latest;
+pub mod lint;
+pub mod orphans;
+pub mod parallel;
+pub mod pii;
+pub mod preset;
+pub mod read_ahead;
+pub mod rename;
+pub mod reorder;
+pub mod row;
+pub mod rules;
+pub mod schema;
+pub mod schema_diff;
+pub mod scrub;
+pub mod search_replace;
+pub mod shard;
+pub mod sink;
+pub mod stats;
+pub mod strict;
+pub mod subset;
+pub mod transform;
+pub mod trend;
+pub mod verify;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wordpress;
@@ -0,0 +1,378 @@
+//! Checking a dump's rows for values that won't restore as they are, such as
+//! emoji in a `utf8` column, or the zero dates and overlong strings a server
+//! in strict SQL mode rejects, without restoring it anywhere.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::date::{is_date_type, is_valid_date};
+use crate::dump::Statement;
+use crate::extract::{ExtractOptions, TableContext};
+use crate::insert::{parse_insert, parse_value, split_values, Value};
+use crate::schema::{parse_create_table, Column, TableSchema};
+use crate::visit::{scan_dump, StatementVisitor};
+
+/// A problem with a column's values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LintKind {
+    /// Characters outside the Basic Multilingual Plane, such as emoji, which
+    /// take 4 bytes in UTF-8, in a column whose charset is `utf8` (that is,
+    /// `utf8mb3`), which only holds up to 3. MySQL cuts the value short at
+    /// the first one, or rejects the row in strict mode.
+    FourByteUtf8,
+    /// Dates MySQL's `NO_ZERO_DATE` and `NO_ZERO_IN_DATE` modes reject, such
+    /// as the zero date `0000-00-00`, `2020-00-00`, or a string that isn't a
+    /// date at all
+    InvalidDate,
+    /// Numbers outside the range of the column's integer type, or with more
+    /// digits before the point than its `decimal` type has
+    OutOfRange,
+    /// Strings with more characters than a `char` or `varchar` column is
+    /// declared with, or more bytes than a `binary`, `varbinary`, `blob` or
+    /// `text` column holds
+    TooLong,
+    /// Strings that aren't one of an `enum` column's values, including the
+    /// empty string MySQL stores invalid values as outside strict mode
+    InvalidEnum,
+}
+
+impl LintKind {
+    /// The problem's name, as it's serialized, e.g. `four_byte_utf8`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintKind::FourByteUtf8 => "four_byte_utf8",
+            LintKind::InvalidDate => "invalid_date",
+            LintKind::OutOfRange => "out_of_range",
+            LintKind::TooLong => "too_long",
+            LintKind::InvalidEnum => "invalid_enum",
+        }
+    }
+
+    /// What the rows have, e.g. `4-byte UTF-8 characters, which utf8
+    /// (utf8mb3) can't hold`.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LintKind::FourByteUtf8 => "4-byte UTF-8 characters, which utf8 (utf8mb3) can't hold",
+            LintKind::InvalidDate => "zero or invalid dates, which strict mode rejects",
+            LintKind::OutOfRange => "numbers out of the range of the column's type",
+            LintKind::TooLong => "values longer than the column holds",
+            LintKind::InvalidEnum => "values that aren't one of the enum's",
+        }
+    }
+}
+
+/// A column of a table with rows [`lint_dump`] found a problem with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LintFinding {
+    pub table: String,
+    pub column: String,
+    pub kind: LintKind,
+    /// The rows with the problem in the column
+    pub rows: u64,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.rows == 1 { "" } else { "s" };
+        write!(f, "{}.{}: {} row{} with {}", self.table, self.column, self.rows, plural, self.kind.describe())
+    }
+}
+
+/// What a column's type allows its values to be.
+#[derive(Debug, Clone)]
+enum TypeCheck {
+    Date,
+    /// An integer, from `min` to `max`
+    Integer { min: i128, max: i128 },
+    /// A `decimal` with this many digits before the point
+    Decimal { digits: usize },
+    /// A string of up to this many characters
+    Chars(usize),
+    /// A string of up to this many bytes
+    Bytes(usize),
+    /// One of these strings, matched case-insensitively as MySQL does
+    Enum(Vec<Vec<u8>>),
+}
+
+impl TypeCheck {
+    /// The check for a column of type `data_type`, as in
+    /// [`Column::data_type`], whose strings are in `charset`, if its type
+    /// limits its values.
+    fn new(data_type: &str, charset: Option<&str>) -> Option<Self> {
+        if is_date_type(data_type) {
+            return Some(TypeCheck::Date);
+        }
+        let end = data_type.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(data_type.len());
+        let ty = data_type[..end].to_ascii_lowercase();
+        let arguments = data_type[end..]
+            .trim_start()
+            .strip_prefix('(')
+            .and_then(|rest| rest.rfind(')').map(|close| &rest[..close]));
+        let number = |i: usize| arguments?.split(',').nth(i)?.trim().parse::<usize>().ok();
+        let unsigned = data_type.split_whitespace().any(|word| word.eq_ignore_ascii_case("unsigned"));
+        let utf8 = charset.is_some_and(|charset| charset.to_ascii_lowercase().starts_with("utf8"));
+
+        let bits = match ty.as_str() {
+            "tinyint" => Some(8),
+            "smallint" => Some(16),
+            "mediumint" => Some(24),
+            "int" | "integer" => Some(32),
+            "bigint" => Some(64),
+            _ => None,
+        };
+        if let Some(bits) = bits {
+            let (min, max) = if unsigned { (0, (1i128 << bits) - 1) } else { (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1) };
+            return Some(TypeCheck::Integer { min, max });
+        }
+        match ty.as_str() {
+            // decimal(M,D), where M defaults to 10 and D to 0
+            "decimal" | "dec" | "numeric" => {
+                let precision = number(0).unwrap_or(10);
+                Some(TypeCheck::Decimal { digits: precision.saturating_sub(number(1).unwrap_or(0)) })
+            }
+            "char" => Some(TypeCheck::Chars(number(0).unwrap_or(1))),
+            "varchar" => number(0).map(TypeCheck::Chars),
+            "binary" => Some(TypeCheck::Bytes(number(0).unwrap_or(1))),
+            "varbinary" => number(0).map(TypeCheck::Bytes),
+            "tinyblob" => Some(TypeCheck::Bytes(255)),
+            "blob" => Some(TypeCheck::Bytes(65535)),
+            "mediumblob" => Some(TypeCheck::Bytes(16777215)),
+            // Dumps are written in UTF-8, so only hold the bytes a text
+            // column's values take if that's its charset too
+            "tinytext" if utf8 => Some(TypeCheck::Bytes(255)),
+            "text" if utf8 => Some(TypeCheck::Bytes(65535)),
+            "mediumtext" if utf8 => Some(TypeCheck::Bytes(16777215)),
+            "enum" => {
+                let values = split_values(arguments?.as_bytes()).into_iter().map(|value| match parse_value(value) {
+                    Value::String(bytes) => bytes.to_ascii_lowercase(),
+                    other => other.as_bytes().unwrap_or_default().to_vec(),
+                });
+                Some(TypeCheck::Enum(values.collect()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether a value of the column is allowed.
+    fn allows(&self, value: &Value) -> bool {
+        let bytes = match value {
+            Value::Null => return true,
+            Value::String(bytes) => bytes.as_slice(),
+            Value::Literal(literal) => literal,
+        };
+        match self {
+            TypeCheck::Date => !matches!(value, Value::String(_)) || is_valid_date(bytes),
+            TypeCheck::Integer { min, max } => {
+                let Some(text) = std::str::from_utf8(bytes).ok().map(str::trim) else { return true };
+                match text.parse::<i128>() {
+                    Ok(number) => (*min..=*max).contains(&number),
+                    // Rounded to an integer, as MySQL does
+                    Err(_) => text.parse::<f64>().map_or(true, |number| {
+                        !number.is_finite() || (*min as f64..=*max as f64).contains(&number.round())
+                    }),
+                }
+            }
+            TypeCheck::Decimal { digits } => {
+                let Some(text) = std::str::from_utf8(bytes).ok().map(str::trim) else { return true };
+                let integer = text.trim_start_matches(['-', '+']).split('.').next().unwrap_or_default();
+                if integer.is_empty() || !integer.bytes().all(|b| b.is_ascii_digit()) {
+                    return true;
+                }
+                integer.trim_start_matches('0').len() <= *digits
+            }
+            TypeCheck::Chars(length) => {
+                let Value::String(bytes) = value else { return true };
+                match std::str::from_utf8(bytes) {
+                    Ok(text) => text.chars().count() <= *length,
+                    Err(_) => bytes.len() <= *length,
+                }
+            }
+            TypeCheck::Bytes(length) => match value {
+                Value::String(bytes) => bytes.len() <= *length,
+                // A hex literal, as mysqldump writes with --hex-blob
+                _ => bytes
+                    .strip_prefix(b"0x")
+                    .or_else(|| bytes.strip_prefix(b"0X"))
+                    .is_none_or(|digits| digits.len() / 2 <= *length),
+            },
+            TypeCheck::Enum(values) => match value {
+                // Trailing spaces are dropped from enum values
+                Value::String(bytes) => {
+                    let bytes = bytes.to_ascii_lowercase();
+                    let trimmed = bytes.trim_ascii_end();
+                    values.iter().any(|value| value.trim_ascii_end() == trimmed)
+                }
+                // The number of a value, counting from 1
+                _ => std::str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|text| text.trim().parse::<usize>().ok())
+                    .is_none_or(|index| (1..=values.len()).contains(&index)),
+            },
+        }
+    }
+
+    /// The problem with a value this doesn't allow.
+    fn kind(&self) -> LintKind {
+        match self {
+            TypeCheck::Date => LintKind::InvalidDate,
+            TypeCheck::Integer { .. } | TypeCheck::Decimal { .. } => LintKind::OutOfRange,
+            TypeCheck::Chars(_) | TypeCheck::Bytes(_) => LintKind::TooLong,
+            TypeCheck::Enum(_) => LintKind::InvalidEnum,
+        }
+    }
+}
+
+/// A column whose values are worth checking.
+#[derive(Debug, Clone)]
+struct ColumnCheck {
+    name: String,
+    /// The column's charset is `utf8mb3`
+    utf8mb3: bool,
+    /// What its type allows, if it limits its values
+    data_type: Option<TypeCheck>,
+}
+
+impl ColumnCheck {
+    /// The check for `column` of `table`, if there's anything to check.
+    fn new(column: &Column, table: &TableSchema) -> Option<Self> {
+        let charset = column.charset().or_else(|| table.charset());
+        let utf8mb3 = is_text_type(&column.data_type)
+            && charset.is_some_and(|charset| charset.eq_ignore_ascii_case("utf8") || charset.eq_ignore_ascii_case("utf8mb3"));
+        let data_type = TypeCheck::new(&column.data_type, charset);
+        (utf8mb3 || data_type.is_some()).then(|| ColumnCheck { name: column.name.clone(), utf8mb3, data_type })
+    }
+
+    /// The problem with a value of the column, if it has one. A value too
+    /// long to hold is only counted as that, whatever else is wrong with it.
+    fn check(&self, value: &Value) -> Option<LintKind> {
+        if let Some(data_type) = self.data_type.as_ref().filter(|data_type| !data_type.allows(value)) {
+            return Some(data_type.kind());
+        }
+        let Value::String(bytes) = value else { return None };
+        // In UTF-8, these only ever start a 4-byte character
+        if self.utf8mb3 && bytes.iter().any(|b| (0xF0..=0xF4).contains(b)) {
+            return Some(LintKind::FourByteUtf8);
+        }
+        None
+    }
+}
+
+/// What's checked of a table, from its `CREATE TABLE`.
+#[derive(Debug, Clone)]
+struct TableChecks {
+    /// Every column, to find the values of an `INSERT` without a column list
+    columns: Vec<String>,
+    checks: Vec<ColumnCheck>,
+}
+
+/// The problems with the rows of each table (and view) `options` includes,
+/// found from their `INSERT`s and the `CREATE TABLE`s before them, a finding
+/// per table, column and problem in the order they're first found. Only
+/// `options`' filters are used, and they're passed no database and nothing
+/// written. Statements are read whole, however long.
+///
+/// The rows of a table whose `CREATE TABLE` isn't in the dump aren't checked.
+pub fn lint_dump<R: BufRead>(reader: R, options: &ExtractOptions) -> io::Result<Vec<LintFinding>> {
+    struct Lint<'a> {
+        options: &'a ExtractOptions,
+        /// The checks of each table with any, by name
+        tables: HashMap<String, TableChecks>,
+        findings: Vec<LintFinding>,
+        /// The index of each finding, by table, column and problem
+        indexes: HashMap<(String, String, LintKind), usize>,
+        bytes_read: u64,
+    }
+
+    impl StatementVisitor for Lint<'_> {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            let Some(schema) = parse_create_table(&statement.text) else {
+                return Ok(());
+            };
+            let context = TableContext {
+                name: &schema.name,
+                database: None,
+                bytes_read: self.bytes_read,
+                bytes_written: 0,
+                engine: schema.option("ENGINE"),
+                foreign_keys: Some(&schema.foreign_keys),
+            };
+            let checks: Vec<ColumnCheck> = schema.columns.iter().filter_map(|column| ColumnCheck::new(column, &schema)).collect();
+            if checks.is_empty() || !self.options.keeps(&context) {
+                self.tables.remove(&schema.name);
+                return Ok(());
+            }
+            let columns = schema.columns.iter().map(|column| column.name.clone()).collect();
+            self.tables.insert(schema.name, TableChecks { columns, checks });
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            let Some(checks) = self.tables.get(table) else {
+                return Ok(());
+            };
+            let Some(insert) = parse_insert(&statement.text) else {
+                return Ok(());
+            };
+            let names = insert.columns.as_ref().unwrap_or(&checks.columns);
+            let positions: Vec<(usize, &ColumnCheck)> = checks
+                .checks
+                .iter()
+                .filter_map(|check| names.iter().position(|name| name.eq_ignore_ascii_case(&check.name)).map(|i| (i, check)))
+                .collect();
+
+            let mut counts: Vec<(&str, LintKind, u64)> = Vec::new();
+            for tuple in insert.tuples {
+                let values = split_values(tuple);
+                for (index, check) in &positions {
+                    let Some(kind) = values.get(*index).and_then(|value| check.check(&parse_value(value))) else {
+                        continue;
+                    };
+                    match counts.iter_mut().find(|(column, k, _)| *column == check.name && *k == kind) {
+                        Some((_, _, rows)) => *rows += 1,
+                        None => counts.push((&check.name, kind, 1)),
+                    }
+                }
+            }
+            for (column, kind, rows) in counts {
+                let key = (table.to_string(), column.to_string(), kind);
+                match self.indexes.get(&key) {
+                    Some(&index) => self.findings[index].rows += rows,
+                    None => {
+                        self.findings.push(LintFinding { table: key.0.clone(), column: key.1.clone(), kind, rows });
+                        self.indexes.insert(key, self.findings.len() - 1);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn on_other(&mut self, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            Ok(())
+        }
+
+        fn should_stop(&self) -> bool {
+            self.options.cancel_requested()
+        }
+    }
+
+    let mut lint = Lint { options, tables: HashMap::new(), findings: Vec::new(), indexes: HashMap::new(), bytes_read: 0 };
+    scan_dump(reader, &mut lint)?;
+    Ok(lint.findings)
+}
+
+/// Whether a column's type, as in [`Column::data_type`], holds text in a
+/// charset.
+fn is_text_type(data_type: &str) -> bool {
+    let end = data_type.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(data_type.len());
+    let ty = &data_type[..end];
+    ["char", "varchar", "tinytext", "text", "mediumtext", "longtext", "enum", "set"]
+        .iter()
+        .any(|text| ty.eq_ignore_ascii_case(text))
+}
@@ -0,0 +1,438 @@
+//! Finding rows whose foreign keys reference rows the dump doesn't have, as
+//! trimming tables or rows can leave behind.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::atomic::AtomicBool;
+
+use crate::dump::Statement;
+use crate::extract::{ExtractOptions, TableContext};
+use crate::insert::{count_tuples, parse_insert, parse_value, split_values, TupleCounter, Value};
+use crate::schema::{parse_create_table, TableSchema};
+use crate::shard::{fnv1a, mix};
+use crate::visit::{scan_dump, scan_dump_in_pieces, StatementVisitor, StopWhen};
+
+/// The share of missing parent rows an approximate check takes to be there.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// How many of the keys without a parent row are kept, as examples.
+const EXAMPLES: usize = 3;
+
+/// A foreign key's rows, checked by [`find_orphans`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ForeignKeyCheck {
+    pub table: String,
+    /// The foreign key's name, or its columns if it has none
+    pub foreign_key: String,
+    pub columns: Vec<String>,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+    /// Whether the referenced table's `CREATE TABLE` is in the dump. If it
+    /// isn't, every row checked is an orphan.
+    pub referenced_table_found: bool,
+    /// The rows checked: those whose key has no NULL in it
+    pub rows: u64,
+    /// The rows whose key no row of the referenced table has
+    pub orphans: u64,
+    /// The first few keys without a parent row, as written, e.g. `42` or
+    /// `(42, 'draft')`
+    pub examples: Vec<String>,
+}
+
+impl fmt::Display for ForeignKeyCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) -> {} ({}): {} of {} rows have no parent",
+            self.table,
+            self.columns.join(", "),
+            self.referenced_table,
+            self.referenced_columns.join(", "),
+            self.orphans,
+            self.rows
+        )?;
+        if !self.referenced_table_found {
+            write!(f, ", as {} isn't in the dump", self.referenced_table)?;
+        } else if !self.examples.is_empty() {
+            write!(f, ", such as {}", self.examples.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The keys of a referenced table's rows, to look child rows' up in.
+#[derive(Debug, Clone)]
+enum KeySet {
+    Exact(HashSet<Vec<u8>>),
+    Approximate(BloomFilter),
+}
+
+impl KeySet {
+    fn insert(&mut self, key: Vec<u8>) {
+        match self {
+            KeySet::Exact(keys) => {
+                keys.insert(key);
+            }
+            KeySet::Approximate(filter) => filter.insert(&key),
+        }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        match self {
+            KeySet::Exact(keys) => keys.contains(key),
+            KeySet::Approximate(filter) => filter.contains(key),
+        }
+    }
+}
+
+/// A Bloom filter, which may say it has a key it wasn't given, but never
+/// that it hasn't one it was.
+#[derive(Debug, Clone)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u64,
+}
+
+impl BloomFilter {
+    /// A filter for `keys` keys, wrong about the ones it wasn't given at
+    /// `rate`.
+    fn new(keys: u64, rate: f64) -> Self {
+        let keys = keys.max(1) as f64;
+        let bits = (-keys * rate.ln() / std::f64::consts::LN_2.powi(2)).ceil().max(64.0);
+        let hashes = (bits / keys * std::f64::consts::LN_2).round().max(1.0) as u64;
+        BloomFilter { bits: vec![0; (bits as usize).div_ceil(64)], hashes }
+    }
+
+    /// The bits of `key`, by double hashing.
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let first = mix(fnv1a(key));
+        let second = mix(first ^ 0x9e3779b97f4a7c15) | 1;
+        let length = self.bits.len() as u64 * 64;
+        (0..self.hashes).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % length) as usize)
+    }
+
+    fn insert(&mut self, key: &[u8]) {
+        for position in self.positions(key).collect::<Vec<_>>() {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key).all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// Check the foreign keys of each table `options` includes, finding the rows
+/// that reference a row the dump doesn't have. `open` is called to read the
+/// dump three times: for its tables' `CREATE TABLE`s and rows counts, for
+/// the keys the foreign keys reference, and to check the rows against them.
+/// Only `options`' filters are used, and they're passed no database, no
+/// offset and nothing written.
+///
+/// `approximate` keeps the referenced keys in Bloom filters rather than
+/// whole, for tables too big to hold the keys of in memory, so around 1% of
+/// orphans go unfound, but no row's wrongly taken to be one.
+///
+/// Keys are compared as written, apart from quoting, so a string key only
+/// matches in the same case. Keys with a NULL in them aren't checked, as
+/// MySQL doesn't, and nor are foreign keys referencing another database.
+pub fn find_orphans<R: BufRead>(
+    mut open: impl FnMut() -> io::Result<R>,
+    options: &ExtractOptions,
+    approximate: bool,
+) -> io::Result<Vec<ForeignKeyCheck>> {
+    let cancel = options.cancel_flag().map(|cancel| &**cancel);
+    let tables = read_tables(open()?, cancel)?;
+    let schemas: HashMap<&str, &TableSchema> = tables.iter().map(|(schema, _)| (schema.name.as_str(), schema)).collect();
+    let rows: HashMap<&str, u64> = tables.iter().map(|(schema, rows)| (schema.name.as_str(), *rows)).collect();
+
+    let mut checks = Vec::new();
+    let mut parents: HashMap<(String, Vec<String>), KeySet> = HashMap::new();
+    for (schema, _) in &tables {
+        let context = TableContext {
+            name: &schema.name,
+            database: None,
+            bytes_read: 0,
+            bytes_written: 0,
+            engine: schema.option("ENGINE"),
+            foreign_keys: Some(&schema.foreign_keys),
+        };
+        if !options.keeps(&context) {
+            continue;
+        }
+        for foreign_key in schema.foreign_keys.iter().filter(|foreign_key| foreign_key.referenced_database.is_none()) {
+            let referenced = schemas.contains_key(foreign_key.referenced_table.as_str());
+            if referenced {
+                let rows = rows[foreign_key.referenced_table.as_str()];
+                let key = parent_key(&foreign_key.referenced_table, &foreign_key.referenced_columns);
+                parents.entry(key).or_insert_with(|| {
+                    if approximate {
+                        KeySet::Approximate(BloomFilter::new(rows, FALSE_POSITIVE_RATE))
+                    } else {
+                        KeySet::Exact(HashSet::new())
+                    }
+                });
+            }
+            checks.push(ForeignKeyCheck {
+                table: schema.name.clone(),
+                foreign_key: match &foreign_key.name {
+                    Some(name) => name.clone(),
+                    None => format!("({})", foreign_key.columns.join(", ")),
+                },
+                columns: foreign_key.columns.clone(),
+                referenced_table: foreign_key.referenced_table.clone(),
+                referenced_columns: foreign_key.referenced_columns.clone(),
+                referenced_table_found: referenced,
+                ..Default::default()
+            });
+        }
+    }
+    if checks.is_empty() {
+        return Ok(checks);
+    }
+
+    struct Parents<'a> {
+        schemas: &'a HashMap<&'a str, &'a TableSchema>,
+        parents: &'a mut HashMap<(String, Vec<String>), KeySet>,
+    }
+
+    impl StatementVisitor for Parents<'_> {
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            let keys: Vec<_> = self.parents.iter_mut().filter(|((parent, _), _)| parent == table).collect();
+            if keys.is_empty() {
+                return Ok(());
+            }
+            let Some(insert) = parse_insert(&statement.text) else { return Ok(()) };
+            for ((_, columns), set) in keys {
+                let Some(positions) = positions(self.schemas.get(table).copied(), insert.columns.as_deref(), columns) else {
+                    continue;
+                };
+                for tuple in &insert.tuples {
+                    if let Some(key) = row_key(tuple, &positions) {
+                        set.insert(key);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    scan_dump(open()?, &mut StopWhen::new(&mut Parents { schemas: &schemas, parents: &mut parents }, cancel))?;
+
+    struct Children<'a> {
+        schemas: &'a HashMap<&'a str, &'a TableSchema>,
+        parents: &'a HashMap<(String, Vec<String>), KeySet>,
+        checks: &'a mut Vec<ForeignKeyCheck>,
+    }
+
+    impl StatementVisitor for Children<'_> {
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            if !self.checks.iter().any(|check| check.table == table) {
+                return Ok(());
+            }
+            let Some(insert) = parse_insert(&statement.text) else { return Ok(()) };
+            for check in self.checks.iter_mut().filter(|check| check.table == table) {
+                let Some(positions) = positions(self.schemas.get(table).copied(), insert.columns.as_deref(), &check.columns) else {
+                    continue;
+                };
+                let parent = self.parents.get(&parent_key(&check.referenced_table, &check.referenced_columns));
+                for tuple in &insert.tuples {
+                    let Some(key) = row_key(tuple, &positions) else { continue };
+                    check.rows += 1;
+                    if parent.is_some_and(|parent| parent.contains(&key)) {
+                        continue;
+                    }
+                    check.orphans += 1;
+                    if check.examples.len() < EXAMPLES {
+                        let written = written_key(tuple, &positions);
+                        if !check.examples.contains(&written) {
+                            check.examples.push(written);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut children = Children { schemas: &schemas, parents: &parents, checks: &mut checks };
+    scan_dump(open()?, &mut StopWhen::new(&mut children, cancel))?;
+    Ok(checks)
+}
+
+/// Each table's schema, and how many rows it has, in the order of the dump.
+/// Rows are counted in pieces, so aren't held in memory.
+fn read_tables<R: BufRead>(reader: R, cancel: Option<&AtomicBool>) -> io::Result<Vec<(TableSchema, u64)>> {
+    struct Tables {
+        tables: Vec<(TableSchema, u64)>,
+        rows: HashMap<String, u64>,
+        /// Counting the rows of an `INSERT` read in pieces, and its table
+        counter: Option<(String, TupleCounter)>,
+    }
+
+    impl StatementVisitor for Tables {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            if let Some(schema) = parse_create_table(&statement.text) {
+                self.tables.push((schema, 0));
+            }
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            if statement.continued {
+                self.counter = TupleCounter::new(&statement.text).map(|counter| (table.to_string(), counter));
+            } else if let Some(rows) = count_tuples(&statement.text) {
+                *self.rows.entry(table.to_string()).or_default() += rows as u64;
+            }
+            Ok(())
+        }
+
+        fn on_more(&mut self, piece: &Statement) -> io::Result<()> {
+            if let Some((_, counter)) = &mut self.counter {
+                counter.push(&piece.text);
+            }
+            if !piece.continued {
+                if let Some((table, counter)) = self.counter.take() {
+                    *self.rows.entry(table).or_default() += counter.count() as u64;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut tables = Tables { tables: Vec::new(), rows: HashMap::new(), counter: None };
+    scan_dump_in_pieces(reader, 1 << 20, &mut StopWhen::new(&mut tables, cancel))?;
+    let mut rows = tables.rows;
+    Ok(tables
+        .tables
+        .into_iter()
+        .map(|(schema, _)| {
+            let count = rows.remove(&schema.name).unwrap_or(0);
+            (schema, count)
+        })
+        .collect())
+}
+
+/// What a referenced table's keys are found by: its name, and the
+/// referenced columns, lowercased as MySQL matches them case-insensitively.
+fn parent_key(table: &str, columns: &[String]) -> (String, Vec<String>) {
+    (table.to_string(), columns.iter().map(|column| column.to_ascii_lowercase()).collect())
+}
+
+/// The positions of `columns` among an `INSERT`'s values: by its column list
+/// if it has one, otherwise by its table's `CREATE TABLE`. `None` if any
+/// can't be found.
+fn positions(schema: Option<&TableSchema>, names: Option<&[String]>, columns: &[String]) -> Option<Vec<usize>> {
+    columns
+        .iter()
+        .map(|column| match names {
+            Some(names) => names.iter().position(|name| name.eq_ignore_ascii_case(column)),
+            None => schema?.column_index(column),
+        })
+        .collect()
+}
+
+/// A row's key, from the values at `positions`, encoded to look up. `None`
+/// if any is NULL, or the row hasn't that many values.
+fn row_key(tuple: &[u8], positions: &[usize]) -> Option<Vec<u8>> {
+    let values = split_values(tuple);
+    let mut key = Vec::new();
+    for &position in positions {
+        let value = parse_value(values.get(position)?);
+        if value == Value::Null {
+            return None;
+        }
+        let bytes = value.as_bytes().unwrap_or_default();
+        key.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        key.extend_from_slice(bytes);
+    }
+    Some(key)
+}
+
+/// A row's key as written, e.g. `42` or `(42, 'draft')`.
+fn written_key(tuple: &[u8], positions: &[usize]) -> String {
+    let values = split_values(tuple);
+    let written: Vec<_> = positions
+        .iter()
+        .map(|&position| String::from_utf8_lossy(values.get(position).copied().unwrap_or_default()))
+        .collect();
+    match written.as_slice() {
+        [value] => value.to_string(),
+        _ => format!("({})", written.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    const DUMP: &str = "CREATE TABLE `posts` (\n  `ID` bigint NOT NULL,\n  PRIMARY KEY (`ID`)\n);\n\
+        INSERT INTO `posts` VALUES (1),(2),(3);\n\
+        CREATE TABLE `postmeta` (\n  `meta_id` bigint NOT NULL,\n  `post_id` bigint,\n  \
+        CONSTRAINT `meta_post` FOREIGN KEY (`post_id`) REFERENCES `posts` (`ID`)\n);\n\
+        INSERT INTO `postmeta` VALUES (10,1),(11,9),(12,NULL);\n\
+        INSERT INTO `postmeta` (`post_id`, `meta_id`) VALUES (2,13),(8,14),(9,15);\n\
+        CREATE TABLE `comments` (\n  `id` bigint NOT NULL,\n  `author_id` bigint,\n  \
+        FOREIGN KEY (`author_id`) REFERENCES `authors` (`id`)\n);\n\
+        INSERT INTO `comments` VALUES (1,1),(2,2);\n";
+
+    fn orphans(options: &ExtractOptions, approximate: bool) -> Vec<ForeignKeyCheck> {
+        find_orphans(|| Ok(DUMP.as_bytes()), options, approximate).unwrap()
+    }
+
+    #[test]
+    fn with_and_without_parents() {
+        let checks = orphans(&ExtractOptions::new(), false);
+        assert_eq!(
+            checks,
+            [
+                ForeignKeyCheck {
+                    table: "postmeta".to_string(),
+                    foreign_key: "meta_post".to_string(),
+                    columns: vec!["post_id".to_string()],
+                    referenced_table: "posts".to_string(),
+                    referenced_columns: vec!["ID".to_string()],
+                    referenced_table_found: true,
+                    // Not the row with a NULL key
+                    rows: 5,
+                    orphans: 3,
+                    examples: vec!["9".to_string(), "8".to_string()],
+                },
+                ForeignKeyCheck {
+                    table: "comments".to_string(),
+                    foreign_key: "(author_id)".to_string(),
+                    columns: vec!["author_id".to_string()],
+                    referenced_table: "authors".to_string(),
+                    referenced_columns: vec!["id".to_string()],
+                    referenced_table_found: false,
+                    rows: 2,
+                    orphans: 2,
+                    examples: vec!["1".to_string(), "2".to_string()],
+                },
+            ]
+        );
+
+        // Only the tables the options keep are checked
+        let options = ExtractOptions::new().exclude(Regex::new("^comments$").unwrap());
+        let checks = orphans(&options, false);
+        assert_eq!(checks.iter().map(|check| check.table.as_str()).collect::<Vec<_>>(), ["postmeta"]);
+        // Rows with a parent are never taken for orphans
+        let approximate = orphans(&options, true);
+        assert_eq!(approximate[0].rows, 5);
+        assert!(approximate[0].orphans <= 3);
+    }
+
+    #[test]
+    fn bloom_filter() {
+        let mut filter = BloomFilter::new(1000, FALSE_POSITIVE_RATE);
+        for key in 0..1000u32 {
+            filter.insert(&key.to_le_bytes());
+        }
+        assert!((0..1000u32).all(|key| filter.contains(&key.to_le_bytes())));
+        let wrong = (1000..11000u32).filter(|key| filter.contains(&key.to_le_bytes())).count();
+        assert!(wrong < 300, "{} of 10000 keys wrongly found", wrong);
+    }
+}
@@ -0,0 +1,211 @@
+//! Sizing up and splitting a dump's tables on several threads, each reading
+//! the regions of the tables it's given, from an index of the dump's
+//! sections.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::dump::{Section, SectionKind, SectionReader};
+use crate::extract::{extract_sql_per_table, ExtractOptions, ExtractReport, TableContext};
+use crate::sink::TableSink;
+use crate::stats::{compute_table_stats, TableStats};
+
+/// Index the sections of the dump `reader` reads, with the byte range of
+/// each, for the parallel functions to find the tables by.
+pub fn index_sections<R: BufRead>(reader: R) -> io::Result<Vec<Section>> {
+    // Only the sections' ranges are kept, so long statements needn't be read
+    // all at once
+    let mut sections = SectionReader::new(reader).max_statement_size(1 << 20);
+    for item in &mut sections {
+        item?;
+    }
+    Ok(sections.into_sections())
+}
+
+/// [`compute_table_stats`] on `threads` threads, for the dump `open` opens
+/// a reader of for each thread, with the sections `index` found.
+///
+/// Only the tables' and views' sections are read, so the rare `INSERT` into a
+/// table from elsewhere in the dump isn't counted. `options`' filters are
+/// passed no database and the offset of the table's section.
+pub fn compute_table_stats_parallel<R: Read + Seek>(
+    open: impl Fn() -> io::Result<R> + Sync,
+    index: &[Section],
+    options: &ExtractOptions,
+    threads: usize,
+) -> io::Result<Vec<TableStats>> {
+    let max_statement_size = options.max_statement_size;
+    let cancel = options.cancel_flag();
+    let tables = table_sections(index);
+    let results = run(&open, tables.len(), threads, |file, job| {
+        let section = &index[tables[job]];
+        let mut options = ExtractOptions::new();
+        if let Some(size) = max_statement_size {
+            options = options.max_statement_size(size);
+        }
+        if let Some(cancel) = cancel {
+            options = options.cancel_when(cancel.clone());
+        }
+        compute_table_stats(BufReader::new(region(file, section)?), &options)
+    })?;
+
+    // Tables' stats in the order they were first seen, added up where a
+    // table's in more than one section
+    let mut stats: Vec<(u64, TableStats)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (job, found) in results.into_iter().enumerate() {
+        for table in found {
+            match positions.get(&table.name) {
+                Some(&position) => {
+                    let total = &mut stats[position].1;
+                    total.data_bytes += table.data_bytes;
+                    total.ddl_bytes += table.ddl_bytes;
+                    total.rows += table.rows;
+                    total.statements += table.statements;
+                    total.indexes = total.indexes.max(table.indexes);
+                    total.fulltext_indexes = total.fulltext_indexes.max(table.fulltext_indexes);
+                }
+                None => {
+                    positions.insert(table.name.clone(), stats.len());
+                    stats.push((index[tables[job]].start, table));
+                }
+            }
+        }
+    }
+    Ok(stats
+        .into_iter()
+        .filter(|(offset, table)| {
+            options.keeps(&TableContext {
+                name: &table.name,
+                database: None,
+                bytes_read: *offset,
+                bytes_written: 0,
+                engine: None,
+                foreign_keys: None,
+            })
+        })
+        .map(|(_, table)| table)
+        .collect())
+}
+
+/// [`extract_sql_per_table`] on `threads` threads, for the dump `open` opens
+/// a reader of for each thread, with the sections `index` found.
+///
+/// Each table is extracted on its own, after the dump's header and the
+/// `USE` it comes after, with options from `options` and a clone of `sink`
+/// that's finished once the table's written. So transforms don't share what
+/// they learn between tables, such as random fakes, and the filters are
+/// passed offsets within what's extracted. The report's totals only count
+/// the tables' sections.
+pub fn extract_sql_per_table_parallel<R, S, F>(
+    open: impl Fn() -> io::Result<R> + Sync,
+    index: &[Section],
+    sink: S,
+    options: F,
+    threads: usize,
+) -> io::Result<ExtractReport>
+where
+    R: Read + Seek,
+    S: TableSink + Clone + Send + Sync,
+    F: Fn() -> ExtractOptions + Sync,
+{
+    let started = Instant::now();
+    let mut file = open()?;
+    let mut header = Vec::new();
+    region(&mut file, &index[0])?.read_to_end(&mut header)?;
+    let mut databases = HashMap::new();
+    for (position, section) in index.iter().enumerate() {
+        if let SectionKind::Database(_) = section.kind {
+            let mut text = Vec::new();
+            region(&mut file, section)?.read_to_end(&mut text)?;
+            databases.insert(position, text);
+        }
+    }
+
+    let tables = table_sections(index);
+    let reports = run(&open, tables.len(), threads, |file, job| {
+        let position = tables[job];
+        let database = index[..position]
+            .iter()
+            .rposition(|section| matches!(section.kind, SectionKind::Database(_)))
+            .map_or(&[][..], |database| &databases[&database][..]);
+        let input = header.as_slice().chain(database).chain(region(file, &index[position])?);
+        extract_sql_per_table(input, sink.clone(), &mut options())
+    })?;
+
+    let mut report = ExtractReport::default();
+    for job in reports {
+        report.statements_skipped += job.statements_skipped;
+        report.tables.extend(job.tables);
+    }
+    report.bytes_read = report.tables.iter().map(|table| table.bytes_read).sum();
+    report.bytes_written = report.tables.iter().map(|table| table.bytes_written).sum();
+    report.duration = started.elapsed();
+    Ok(report)
+}
+
+/// The positions of the tables' and views' sections.
+fn table_sections(index: &[Section]) -> Vec<usize> {
+    index
+        .iter()
+        .enumerate()
+        .filter(|(_, section)| matches!(section.kind, SectionKind::Table(_) | SectionKind::View(_)))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+/// A reader of the bytes of `section`.
+fn region<'a, R: Read + Seek>(file: &'a mut R, section: &Section) -> io::Result<io::Take<&'a mut R>> {
+    file.seek(SeekFrom::Start(section.start))?;
+    Ok(file.take(section.end - section.start))
+}
+
+/// Run `work` for each of `jobs` on up to `threads` threads, each with its
+/// own reader of the dump from `open`, returning the results in the order of
+/// the jobs. The first error stops the rest.
+fn run<O, R, T, F>(open: &O, jobs: usize, threads: usize, work: F) -> io::Result<Vec<T>>
+where
+    O: Fn() -> io::Result<R> + Sync,
+    R: Read + Seek,
+    T: Send,
+    F: Fn(&mut R, usize) -> io::Result<T> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, jobs.max(1)))
+            .map(|_| {
+                scope.spawn(|| -> io::Result<Vec<(usize, T)>> {
+                    let mut file = open()?;
+                    let mut done = Vec::new();
+                    loop {
+                        let job = next.fetch_add(1, Ordering::Relaxed);
+                        if job >= jobs || failed.load(Ordering::Relaxed) {
+                            return Ok(done);
+                        }
+                        match work(&mut file, job) {
+                            Ok(result) => done.push((job, result)),
+                            Err(e) => {
+                                failed.store(true, Ordering::Relaxed);
+                                return Err(e);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect::<Vec<_>>()
+    });
+
+    let mut done = Vec::with_capacity(jobs);
+    for result in results {
+        done.extend(result?);
+    }
+    done.sort_by_key(|(job, _)| *job);
+    Ok(done.into_iter().map(|(_, result)| result).collect())
+}
@@ -0,0 +1,365 @@
+//! Finding the columns likely to hold personal data, from their names and a
+//! sample of their values, to know which need anonymizing rules.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::anonymize::Fake;
+use crate::dump::Statement;
+use crate::extract::{ExtractOptions, TableContext};
+use crate::insert::{parse_insert, parse_value, split_values};
+use crate::schema::parse_create_table;
+use crate::visit::{scan_dump, StatementVisitor};
+
+/// The values of a column sampled by default.
+pub const DEFAULT_SAMPLE: u64 = 1000;
+
+/// A kind of personal data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PiiKind {
+    Email,
+    /// Phone numbers, written with a leading `+` or `0`, or with the spaces,
+    /// dashes or parentheses between their digits
+    Phone,
+    /// US social security numbers, as `123-45-6789`, and UK national
+    /// insurance numbers, as `QQ123456C`
+    NationalId,
+    /// Numbers of 13 to 19 digits that pass the Luhn check, as payment card
+    /// numbers do
+    CardNumber,
+}
+
+const KINDS: [PiiKind; 4] = [PiiKind::Email, PiiKind::Phone, PiiKind::NationalId, PiiKind::CardNumber];
+
+impl PiiKind {
+    /// The kind's name, as it's serialized, e.g. `national_id`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "email",
+            PiiKind::Phone => "phone",
+            PiiKind::NationalId => "national_id",
+            PiiKind::CardNumber => "card_number",
+        }
+    }
+
+    /// The generator of an anonymize rules file line replacing it, e.g.
+    /// `null` for an ID there's no fake of.
+    pub fn generator(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "email",
+            PiiKind::Phone => "phone",
+            PiiKind::NationalId | PiiKind::CardNumber => "null",
+        }
+    }
+
+    /// Guess the kind of personal data a column holds from its name.
+    fn guess(table: &str, column: &str) -> Option<PiiKind> {
+        match Fake::guess(table, column) {
+            Some(Fake::Email) => return Some(PiiKind::Email),
+            Some(Fake::Phone) => return Some(PiiKind::Phone),
+            _ => {}
+        }
+        let column = column.to_ascii_lowercase();
+        let words: Vec<&str> = column.split(['_', '-']).collect();
+        let has = |word: &str| words.contains(&word);
+        let contains = |names: &[&str]| names.iter().any(|name| column.contains(name));
+        if has("ssn") || has("nino") || has("passport") || contains(&["national_id", "nationalid", "social_security", "tax_id", "taxid"]) {
+            Some(PiiKind::NationalId)
+        } else if has("pan") || contains(&["card_number", "cardnumber", "credit_card", "creditcard", "cc_number", "ccnumber"]) {
+            Some(PiiKind::CardNumber)
+        } else {
+            None
+        }
+    }
+
+    /// The kind of personal data a value looks like. IDs are tried first, as
+    /// they'd also pass for phone numbers.
+    fn of(value: &[u8]) -> Option<PiiKind> {
+        let value = value.trim_ascii();
+        if is_national_id(value) {
+            Some(PiiKind::NationalId)
+        } else if is_card_number(value) {
+            Some(PiiKind::CardNumber)
+        } else if is_email(value) {
+            Some(PiiKind::Email)
+        } else if is_phone(value) {
+            Some(PiiKind::Phone)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for PiiKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A column [`scan_pii`] found likely to hold personal data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PiiColumn {
+    pub column: String,
+    pub kind: PiiKind,
+    /// Whether its name suggested the kind
+    pub by_name: bool,
+    /// The values sampled, leaving out NULLs and empty strings
+    pub sampled: u64,
+    /// The sampled values that look like the kind
+    pub matched: u64,
+}
+
+impl fmt::Display for PiiColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.sampled == 1 { "" } else { "s" };
+        let values = format!("{} of {} sampled value{}", self.matched, self.sampled, plural);
+        match (self.by_name, self.matched) {
+            (true, 0) => write!(f, "{}: {}, from its name", self.column, self.kind),
+            (true, _) => write!(f, "{}: {}, from its name and {}", self.column, self.kind, values),
+            (false, _) => write!(f, "{}: {}, from {}", self.column, self.kind, values),
+        }
+    }
+}
+
+/// A table with columns likely to hold personal data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TablePii {
+    pub name: String,
+    pub rows: u64,
+    pub columns: Vec<PiiColumn>,
+}
+
+/// A column's sampled values so far.
+#[derive(Debug, Clone)]
+struct ColumnSample {
+    name: String,
+    by_name: Option<PiiKind>,
+    sampled: u64,
+    /// The values matching each of [`KINDS`]
+    matched: [u64; 4],
+}
+
+impl ColumnSample {
+    /// The kind of personal data the column likely holds: the one its name
+    /// suggests, or else the one at least half its values look like.
+    fn finding(&self) -> Option<PiiColumn> {
+        let matched = |kind: PiiKind| self.matched[KINDS.iter().position(|k| *k == kind).expect("every kind is listed")];
+        let (kind, by_name) = match self.by_name {
+            Some(kind) => (kind, true),
+            None => {
+                let kind = KINDS.into_iter().max_by_key(|kind| matched(*kind)).expect("there are kinds");
+                if matched(kind) == 0 || matched(kind) * 2 < self.sampled {
+                    return None;
+                }
+                (kind, false)
+            }
+        };
+        Some(PiiColumn { column: self.name.clone(), kind, by_name, sampled: self.sampled, matched: matched(kind) })
+    }
+}
+
+/// Find the columns of the dump `reader` reads likely to hold personal data,
+/// of the tables `options` keeps, by their names and the first `sample`
+/// values of each, which are matched against the patterns of each
+/// [`PiiKind`]. Only tables with such columns are returned, in the dump's
+/// order. Statements are read whole, however long.
+pub fn scan_pii<R: BufRead>(reader: R, options: &ExtractOptions, sample: u64) -> io::Result<Vec<TablePii>> {
+    struct Scan<'a> {
+        options: &'a ExtractOptions,
+        sample: u64,
+        /// The index of each table in `tables`, by name
+        indexes: HashMap<String, usize>,
+        tables: Vec<(u64, Vec<ColumnSample>)>,
+        names: Vec<String>,
+        bytes_read: u64,
+    }
+
+    impl StatementVisitor for Scan<'_> {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            let Some(schema) = parse_create_table(&statement.text) else {
+                return Ok(());
+            };
+            let context = TableContext {
+                name: &schema.name,
+                database: None,
+                bytes_read: self.bytes_read,
+                bytes_written: 0,
+                engine: schema.option("ENGINE"),
+                foreign_keys: Some(&schema.foreign_keys),
+            };
+            if !self.options.keeps(&context) {
+                return Ok(());
+            }
+            let columns = schema
+                .columns
+                .iter()
+                .map(|column| ColumnSample {
+                    name: column.name.clone(),
+                    by_name: PiiKind::guess(&schema.name, &column.name),
+                    sampled: 0,
+                    matched: [0; 4],
+                })
+                .collect();
+            match self.indexes.get(&schema.name) {
+                Some(&index) => self.tables[index] = (0, columns),
+                None => {
+                    self.indexes.insert(schema.name.clone(), self.tables.len());
+                    self.tables.push((0, columns));
+                    self.names.push(schema.name);
+                }
+            }
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            let Some(&index) = self.indexes.get(table) else {
+                return Ok(());
+            };
+            let Some(insert) = parse_insert(&statement.text) else {
+                return Ok(());
+            };
+            let (rows, columns) = &mut self.tables[index];
+            *rows += insert.tuples.len() as u64;
+            let positions: Vec<(usize, usize)> = columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| column.sampled < self.sample)
+                .filter_map(|(i, column)| match &insert.columns {
+                    Some(names) => names.iter().position(|name| name.eq_ignore_ascii_case(&column.name)).map(|at| (i, at)),
+                    None => Some((i, i)),
+                })
+                .collect();
+            if positions.is_empty() {
+                return Ok(());
+            }
+            for tuple in insert.tuples {
+                let values = split_values(tuple);
+                let mut sampling = false;
+                for &(i, at) in &positions {
+                    let column = &mut columns[i];
+                    if column.sampled >= self.sample {
+                        continue;
+                    }
+                    sampling = true;
+                    let Some(value) = values.get(at).map(|value| parse_value(value)) else {
+                        continue;
+                    };
+                    let Some(bytes) = value.as_bytes().filter(|bytes| !bytes.trim_ascii().is_empty()) else {
+                        continue;
+                    };
+                    column.sampled += 1;
+                    if let Some(kind) = PiiKind::of(bytes) {
+                        column.matched[KINDS.iter().position(|k| *k == kind).expect("every kind is listed")] += 1;
+                    }
+                }
+                if !sampling {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        fn on_other(&mut self, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            Ok(())
+        }
+
+        fn should_stop(&self) -> bool {
+            self.options.cancel_requested()
+        }
+    }
+
+    let mut scan =
+        Scan { options, sample, indexes: HashMap::new(), tables: Vec::new(), names: Vec::new(), bytes_read: 0 };
+    scan_dump(reader, &mut scan)?;
+    Ok(scan
+        .names
+        .into_iter()
+        .zip(scan.tables)
+        .filter_map(|(name, (rows, columns))| {
+            let columns: Vec<PiiColumn> = columns.iter().filter_map(ColumnSample::finding).collect();
+            (!columns.is_empty()).then_some(TablePii { name, rows, columns })
+        })
+        .collect())
+}
+
+/// Whether a value looks like an email address: something, an `@`, and a
+/// domain ending in a dot and letters, with no spaces.
+fn is_email(value: &[u8]) -> bool {
+    let Some(at) = value.iter().position(|&b| b == b'@') else {
+        return false;
+    };
+    let (local, domain) = (&value[..at], &value[at + 1..]);
+    let tld = domain.rsplit(|&b| b == b'.').next().unwrap_or_default();
+    !local.is_empty()
+        && domain.contains(&b'.')
+        && tld.len() >= 2
+        && tld.iter().all(u8::is_ascii_alphabetic)
+        && !domain.contains(&b'@')
+        && !value.iter().any(u8::is_ascii_whitespace)
+}
+
+/// Whether a value looks like a phone number: 7 to 15 digits, between
+/// spaces, dashes, dots or parentheses, starting with `+`, `(` or `0` or
+/// with at least two breaks between its digits, as plain numbers, dates and
+/// IPs don't.
+fn is_phone(value: &[u8]) -> bool {
+    let groups: Vec<&[u8]> = value.split(|b| !b.is_ascii_digit()).filter(|group| !group.is_empty()).collect();
+    let digits: usize = groups.iter().map(|group| group.len()).sum();
+    if !(7..=15).contains(&digits) || !value.iter().all(|&b| b.is_ascii_digit() || b" +-.()".contains(&b)) {
+        return false;
+    }
+    if value.iter().filter(|&&b| b == b'+').count() > usize::from(value.first() == Some(&b'+')) {
+        return false;
+    }
+    let date = groups.len() == 3 && groups[0].len() == 4 && groups[1].len() <= 2 && groups[2].len() <= 2;
+    let ip = groups.len() == 4 && value.contains(&b'.') && groups.iter().all(|group| group.len() <= 3);
+    if date || ip {
+        return false;
+    }
+    matches!(value.first(), Some(b'+' | b'(' | b'0')) || groups.len() >= 3
+}
+
+/// Whether a value looks like a US social security number, `123-45-6789`,
+/// or a UK national insurance number, `QQ123456C`, which may be written in
+/// pairs.
+fn is_national_id(value: &[u8]) -> bool {
+    let ssn = value.len() == 11
+        && value.iter().enumerate().all(|(i, b)| if i == 3 || i == 6 { *b == b'-' } else { b.is_ascii_digit() });
+    let compact: Vec<u8> = value.iter().copied().filter(|&b| b != b' ').collect();
+    let nino = compact.len() == 9
+        && compact[..2].iter().all(u8::is_ascii_uppercase)
+        && compact[2..8].iter().all(u8::is_ascii_digit)
+        && (b'A'..=b'D').contains(&compact[8]);
+    ssn || nino
+}
+
+/// Whether a value looks like a payment card number: 13 to 19 digits, maybe
+/// in groups split by spaces or dashes, passing the Luhn check.
+fn is_card_number(value: &[u8]) -> bool {
+    if !value.iter().all(|&b| b.is_ascii_digit() || b == b' ' || b == b'-') {
+        return false;
+    }
+    let digits: Vec<u32> = value.iter().filter(|b| b.is_ascii_digit()).map(|b| u32::from(b - b'0')).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
@@ -0,0 +1,86 @@
+//! Built-in sets of anonymization rules for well-known schemas.
+
+use std::str::FromStr;
+
+use crate::anonymize::Fake;
+use crate::scrub::{Replacement, RowMatch, ScrubColumn};
+
+/// `wp_options` rows holding an email address
+const WP_EMAIL_OPTIONS: &[&str] = &["admin_email", "new_admin_email", "mailserver_login"];
+/// `wp_options` rows holding a password or secret, which are blanked. WordPress
+/// makes new salts for itself when they're empty.
+const WP_SECRET_OPTIONS: &[&str] = &[
+    "mailserver_pass",
+    "auth_key",
+    "auth_salt",
+    "secure_auth_key",
+    "secure_auth_salt",
+    "logged_in_key",
+    "logged_in_salt",
+    "nonce_key",
+    "nonce_salt",
+];
+/// `wp_usermeta` rows holding logged-in sessions, with their IPs and browsers
+const WP_SESSION_META: &[&str] = &["session_tokens"];
+
+/// A named set of rules for anonymizing one application's tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// WordPress's users' emails, passwords and sessions, commenters' emails
+    /// and IPs, and the personal data and secrets in its options.
+    WordpressGdpr,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wordpress-gdpr" => Ok(Preset::WordpressGdpr),
+            _ => Err(format!("unknown preset `{}`, expected wordpress-gdpr", s)),
+        }
+    }
+}
+
+impl Preset {
+    /// The rules for `table`, whose name starts with `table_prefix` (such as
+    /// `wp_`) if it's one of the preset's. Multisite's per-site tables, like
+    /// `wp_2_comments`, get the same rules as the main site's.
+    pub fn rules(&self, table: &str, table_prefix: &str) -> Vec<ScrubColumn> {
+        let Some(name) = table.strip_prefix(table_prefix) else { return Vec::new() };
+        let site_table = match name.split_once('_') {
+            Some((site, rest)) if !site.is_empty() && site.bytes().all(|b| b.is_ascii_digit()) => rest,
+            _ => name,
+        };
+
+        let rule = |column: &str, replacement: Replacement, rows: Option<(&str, &[&str])>| ScrubColumn {
+            table: table.to_string(),
+            column: column.to_string(),
+            replacement,
+            rows: rows.map(|(column, values)| RowMatch {
+                column: column.to_string(),
+                values: values.iter().map(|value| value.to_string()).collect(),
+            }),
+        };
+        let blank = || Replacement::Constant(String::new());
+        match (self, name, site_table) {
+            (Preset::WordpressGdpr, "users", _) => vec![
+                rule("user_email", Replacement::Fake(Fake::Email), None),
+                rule("user_pass", blank(), None),
+                rule("user_activation_key", blank(), None),
+            ],
+            (Preset::WordpressGdpr, "usermeta", _) => {
+                vec![rule("meta_value", Replacement::Null, Some(("meta_key", WP_SESSION_META)))]
+            }
+            (Preset::WordpressGdpr, _, "comments") => vec![
+                rule("comment_author_email", Replacement::Fake(Fake::Email), None),
+                rule("comment_author_IP", Replacement::Fake(Fake::Ip), None),
+            ],
+            (Preset::WordpressGdpr, _, "options") => vec![
+                rule("option_value", Replacement::Fake(Fake::Email), Some(("option_name", WP_EMAIL_OPTIONS))),
+                rule("option_value", blank(), Some(("option_name", WP_SECRET_OPTIONS))),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Reading the dump on a thread of its own, into big buffers handed over as
+//! they're filled, so reading from disk overlaps with parsing rather than
+//! taking turns with it on one core.
+
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// The size of the buffers, unless another's given.
+const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Reads from another reader on a thread, a buffer ahead of what's been
+/// consumed. One buffer is filled while the last is parsed, and they're
+/// handed back to be filled again, so no more than three are ever
+/// allocated.
+///
+/// ```no_run
+/// # use mysqltrim::read_ahead::ReadAhead;
+/// let file = std::fs::File::open("dump.sql")?;
+/// let tables = mysqltrim::stats::compute_table_stats(ReadAhead::new(file), &Default::default())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct ReadAhead {
+    filled: mpsc::Receiver<io::Result<Vec<u8>>>,
+    /// Where buffers go back to be filled again
+    empty: mpsc::Sender<Vec<u8>>,
+    current: Vec<u8>,
+    position: usize,
+    /// The reading thread, until it's finished
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ReadAhead {
+    pub fn new<R: Read + Send + 'static>(input: R) -> Self {
+        Self::with_capacity(DEFAULT_BUFFER_SIZE, input)
+    }
+
+    /// A reader filling buffers of `capacity` bytes.
+    pub fn with_capacity<R: Read + Send + 'static>(capacity: usize, mut input: R) -> Self {
+        let capacity = capacity.max(1);
+        // Only one filled buffer waits, while the next is filled
+        let (send_filled, filled) = mpsc::sync_channel(1);
+        let (empty, receive_empty) = mpsc::channel::<Vec<u8>>();
+        let thread = std::thread::spawn(move || loop {
+            let mut buffer = receive_empty.try_recv().unwrap_or_else(|_| Vec::with_capacity(capacity));
+            buffer.clear();
+            let filled = match (&mut input).take(capacity as u64).read_to_end(&mut buffer) {
+                Ok(0) => return,
+                Ok(_) => Ok(buffer),
+                Err(e) => Err(e),
+            };
+            let failed = filled.is_err();
+            // Stop at an error, or once the reader's dropped
+            if send_filled.send(filled).is_err() || failed {
+                return;
+            }
+        });
+        ReadAhead { filled, empty, current: Vec::new(), position: 0, thread: Some(thread) }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl BufRead for ReadAhead {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.current.len() && self.thread.is_some() {
+            match self.filled.recv() {
+                Ok(Ok(buffer)) => {
+                    let _ = self.empty.send(std::mem::replace(&mut self.current, buffer));
+                    self.position = 0;
+                }
+                Ok(Err(e)) => return Err(e),
+                // The thread's finished, having read to the end, unless it
+                // panicked
+                Err(_) => {
+                    let thread = self.thread.take().expect("checked above");
+                    if thread.join().is_err() {
+                        return Err(io::Error::other("the reading thread panicked"));
+                    }
+                }
+            }
+        }
+        Ok(&self.current[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.current.len());
+    }
+}
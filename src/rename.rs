@@ -0,0 +1,488 @@
+//! Renaming tables as a dump is written out.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::dump::{find, parse_identifier, quote_identifier, strip_conditional_comment, strip_keywords};
+
+/// Table and database renames to apply as a dump is written out.
+#[derive(Debug, Clone, Default)]
+pub struct Renames {
+    names: HashMap<String, String>,
+    prefixes: Vec<(String, String)>,
+    databases: HashMap<String, String>,
+    strip_databases: bool,
+    /// Databases named by the dump so far, whose qualifiers are stripped
+    seen_databases: HashSet<String>,
+    lowercase: bool,
+    /// Tables and views named by the dump so far, which are lowercased
+    seen_tables: HashSet<String>,
+}
+
+impl Renames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, old: String, new: String) {
+        self.names.insert(old, new);
+    }
+
+    /// Rename every table starting with `old` to start with `new` instead.
+    /// Exact renames take precedence, then the longest matching prefix.
+    pub fn insert_prefix(&mut self, old: String, new: String) {
+        self.prefixes.push((old, new));
+        self.prefixes.sort_by_key(|(old, _)| std::cmp::Reverse(old.len()));
+    }
+
+    pub fn insert_database(&mut self, old: String, new: String) {
+        self.databases.insert(old, new);
+    }
+
+    /// Drop `CREATE DATABASE` and `USE` statements, and the database
+    /// qualifier from `` `db`.`table` `` references to databases in the dump,
+    /// so the output restores into whichever database is current.
+    pub fn strip_databases(&mut self) {
+        self.strip_databases = true;
+    }
+
+    /// Lowercase table, view and database names (after any other renames),
+    /// for restoring onto a server with `lower_case_table_names=1`.
+    pub fn lowercase(&mut self) {
+        self.lowercase = true;
+    }
+
+    /// The databases and tables named so far, for resuming from a
+    /// checkpoint.
+    pub(crate) fn seen(&self) -> (Vec<String>, Vec<String>) {
+        let mut databases: Vec<_> = self.seen_databases.iter().cloned().collect();
+        let mut tables: Vec<_> = self.seen_tables.iter().cloned().collect();
+        databases.sort();
+        tables.sort();
+        (databases, tables)
+    }
+
+    /// Carry on knowing the databases and tables [`seen`](Self::seen) had.
+    pub(crate) fn resume(&mut self, databases: Vec<String>, tables: Vec<String>) {
+        self.seen_databases = databases.into_iter().collect();
+        self.seen_tables = tables.into_iter().collect();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+            && self.prefixes.is_empty()
+            && self.databases.is_empty()
+            && !self.strip_databases
+            && !self.lowercase
+    }
+
+    /// The new name for `table`, if it is renamed.
+    pub fn get(&self, table: &str) -> Option<String> {
+        let renamed = self.names.get(table).cloned().or_else(|| {
+            self.prefixes
+                .iter()
+                .find_map(|(old, new)| table.strip_prefix(old.as_str()).map(|rest| format!("{}{}", new, rest)))
+        });
+        if self.lowercase && self.seen_tables.contains(table) {
+            return lowercased(renamed.as_deref().unwrap_or(table)).or(renamed);
+        }
+        renamed
+    }
+
+    /// The new name for `database`, if it is renamed.
+    fn get_database(&self, database: &str) -> Option<String> {
+        let renamed = self.databases.get(database).cloned();
+        if self.lowercase {
+            return lowercased(renamed.as_deref().unwrap_or(database)).or(renamed);
+        }
+        renamed
+    }
+
+    /// Rewrite the table and database names in a statement, or return `None`
+    /// if the statement should be dropped.
+    ///
+    /// Only the table name of `INSERT` and `CREATE TABLE` statements (and the
+    /// tables referenced by foreign keys) are touched, so data and columns
+    /// that happen to share a table's name are left alone. In everything else
+    /// (`DROP`, `LOCK TABLES`, `ALTER TABLE` in conditional comments, views,
+    /// triggers and the dump's own comments) every quoted identifier that
+    /// names a renamed table is rewritten.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if self.is_empty() {
+            return Some(Cow::Borrowed(text));
+        }
+
+        let body = strip_conditional_comment(text.trim_ascii_start());
+        let mut replacements = Vec::new();
+        if let Some(rest) = strip_keywords(body, &["CREATE", "DATABASE"])
+            .or_else(|| strip_keywords(body, &["ALTER", "DATABASE"]))
+            .or_else(|| strip_keywords(body, &["USE"]))
+            .or_else(|| body.strip_prefix(b"-- Current Database:"))
+        {
+            let rest = rest.strip_prefix(b"/*!32312 IF NOT EXISTS*/").unwrap_or(rest);
+            let at = text.len() - rest.len();
+            if let Some((range, name)) = quoted_identifiers_from(text, at).next() {
+                self.seen_databases.insert(name.clone());
+                if self.strip_databases {
+                    return if body.starts_with(b"--") { Some(Cow::Borrowed(text)) } else { None };
+                }
+                if let Some(new) = self.get_database(&name) {
+                    replacements.push((range, quote_identifier(&new)));
+                }
+            }
+        } else if let Some(rest) = strip_keywords(body, &["INSERT", "IGNORE", "INTO"])
+            .or_else(|| strip_keywords(body, &["INSERT", "INTO"]))
+            .or_else(|| strip_keywords(body, &["REPLACE", "INTO"]))
+        {
+            replacements.extend(self.rename_at(text, text.len() - rest.len()));
+        } else if let Some(rest) = strip_keywords(body, &["CREATE", "TABLE", "IF", "NOT", "EXISTS"])
+            .or_else(|| strip_keywords(body, &["CREATE", "TABLE"]))
+        {
+            replacements.extend(self.rename_at(text, text.len() - rest.len()));
+            let mut line_start = 0;
+            for line in text.split_inclusive(|b| *b == b'\n') {
+                if let Some(at) = find_keyword(line, b"REFERENCES") {
+                    replacements.extend(self.rename_at(text, line_start + at + b"REFERENCES".len()));
+                }
+                line_start += line.len();
+            }
+        } else {
+            if self.strip_databases || self.lowercase {
+                // mysqldump's header names the database for single-database dumps
+                if let Some(database) = find(text, b"Database: ").and_then(|at| parse_identifier(&text[at + 10..])) {
+                    self.seen_databases.insert(database.0);
+                }
+            }
+            if self.lowercase {
+                if let Some(rest) = ["TABLE", "VIEW"].iter().find_map(|object| {
+                    strip_keywords(body, &["DROP", object, "IF", "EXISTS"]).or_else(|| strip_keywords(body, &["DROP", object]))
+                }) {
+                    if let Some((name, _)) = parse_identifier(rest) {
+                        self.seen_tables.insert(name);
+                    }
+                }
+            }
+            // Skip the user and host of `DEFINER=`user`@`host``
+            for (range, name) in quoted_identifiers(text) {
+                if text.get(range.end) == Some(&b'@') || text[..range.start].ends_with(b"@") {
+                    continue;
+                }
+                if text.get(range.end) == Some(&b'.') {
+                    if let Some(replacement) = self.rename_database(&name, range.clone()) {
+                        replacements.push(replacement);
+                        continue;
+                    }
+                }
+                if let Some(new) = self.get(&name) {
+                    replacements.push((range, quote_identifier(&new)));
+                }
+            }
+        }
+
+        if replacements.is_empty() {
+            return Some(Cow::Borrowed(text));
+        }
+        let mut rewritten = Vec::with_capacity(text.len());
+        let mut position = 0;
+        for (range, name) in replacements {
+            rewritten.extend_from_slice(&text[position..range.start]);
+            rewritten.extend_from_slice(name.as_bytes());
+            position = range.end;
+        }
+        rewritten.extend_from_slice(&text[position..]);
+        Some(Cow::Owned(rewritten))
+    }
+
+    /// The replacements for the (possibly database-qualified) table name at
+    /// byte `at` of `text`.
+    fn rename_at(&mut self, text: &[u8], at: usize) -> Vec<(Range<usize>, String)> {
+        let mut replacements = Vec::new();
+        let mut identifiers = quoted_identifiers_from(text, at);
+        let (mut range, mut name) = match identifiers.next() {
+            Some(first) if text[at..first.0.start].trim_ascii().is_empty() => first,
+            _ => return replacements,
+        };
+        if text.get(range.end) == Some(&b'.') {
+            let qualifier = (range.clone(), name);
+            match identifiers.next() {
+                Some(next) if next.0.start == range.end + 1 => (range, name) = next,
+                _ => return replacements,
+            }
+            replacements.extend(self.rename_database(&qualifier.1, qualifier.0));
+        }
+        if self.lowercase {
+            self.seen_tables.insert(name.clone());
+        }
+        if let Some(new) = self.get(&name) {
+            replacements.push((range, quote_identifier(&new)));
+        }
+        replacements
+    }
+
+    /// The replacement for a `` `database`. `` qualifier at `range`, which
+    /// covers the identifier but not the dot after it.
+    fn rename_database(&self, name: &str, range: Range<usize>) -> Option<(Range<usize>, String)> {
+        let known = self.seen_databases.contains(name) || self.databases.contains_key(name);
+        if self.strip_databases && known {
+            return Some((range.start..range.end + 1, String::new()));
+        }
+        if !known {
+            return None;
+        }
+        self.get_database(name).map(|new| (range, quote_identifier(&new)))
+    }
+}
+
+/// `name` in lowercase, if that's different.
+fn lowercased(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    (lower != name).then_some(lower)
+}
+
+/// Parse a `old=new` rename.
+pub fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.trim().is_empty() && !new.trim().is_empty() => {
+            Ok((old.trim().to_string(), new.trim().to_string()))
+        }
+        _ => Err(format!("expected old_table=new_table, got `{}`", s)),
+    }
+}
+
+/// Parse a `old_=new_` prefix rename. The new prefix may be empty, to strip
+/// the old one.
+pub fn parse_prefix_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.trim().is_empty() => Ok((old.trim().to_string(), new.trim().to_string())),
+        _ => Err(format!("expected old_prefix=new_prefix, got `{}`", s)),
+    }
+}
+
+/// Parse a `old=new` database rename.
+pub fn parse_database_rename(s: &str) -> Result<(String, String), String> {
+    parse_rename(s).map_err(|_| format!("expected old_database=new_database, got `{}`", s))
+}
+
+/// Parse a rename mapping file: one `old=new` per line, with blank lines and
+/// lines starting with `#` ignored.
+pub fn parse_rename_file(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_rename)
+        .collect()
+}
+
+/// Every backtick-quoted identifier in `text` outside string literals, with
+/// the byte range it occupies (including the backticks).
+pub fn quoted_identifiers(text: &[u8]) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    quoted_identifiers_from(text, 0)
+}
+
+fn quoted_identifiers_from(text: &[u8], start: usize) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut i = start;
+    std::iter::from_fn(move || {
+        while i < text.len() {
+            match text[i] {
+                quote @ (b'\'' | b'"') => {
+                    i += 1;
+                    while i < text.len() && text[i] != quote {
+                        if text[i] == b'\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                b'`' => {
+                    let start = i;
+                    let mut name = Vec::new();
+                    i += 1;
+                    while i < text.len() {
+                        if text[i] == b'`' {
+                            if text.get(i + 1) != Some(&b'`') {
+                                break;
+                            }
+                            i += 1;
+                        }
+                        name.push(text[i]);
+                        i += 1;
+                    }
+                    i += 1;
+                    return Some((start..i.min(text.len()), String::from_utf8_lossy(&name).into_owned()));
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    })
+}
+
+/// Position of `keyword` as a whole word in `line`, outside quotes.
+fn find_keyword(line: &[u8], keyword: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    for i in 0..line.len() {
+        match (quote, line[i]) {
+            (None, q @ (b'\'' | b'"' | b'`')) => quote = Some(q),
+            (Some(q), b) if b == q => quote = None,
+            (None, _) if line[i..].len() >= keyword.len() && line[i..i + keyword.len()].eq_ignore_ascii_case(keyword) => {
+                let before = i.checked_sub(1).map(|j| line[j]);
+                let after = line.get(i + keyword.len());
+                let boundary = |b: Option<&u8>| !b.is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_');
+                if boundary(before.as_ref()) && boundary(after) {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(renames: &mut Renames, text: &str) -> Option<String> {
+        renames.rewrite(text.as_bytes()).map(|text| String::from_utf8(text.into_owned()).unwrap())
+    }
+
+    fn renames(names: &[(&str, &str)]) -> Renames {
+        let mut renames = Renames::new();
+        for (old, new) in names {
+            renames.insert(old.to_string(), new.to_string());
+        }
+        renames
+    }
+
+    #[test]
+    fn tables() {
+        let mut renames = renames(&[("wp_posts", "posts"), ("wp_users", "users")]);
+        let mut rewrite = |text| rewrite(&mut renames, text).unwrap();
+        assert_eq!(
+            rewrite(
+                "CREATE TABLE `wp_posts` (\n  `ID` bigint,\n  `wp_users` bigint,\n  \
+                CONSTRAINT `author` FOREIGN KEY (`wp_users`) REFERENCES `wp_users` (`ID`)\n);\n"
+            ),
+            // Not the column named like a table
+            "CREATE TABLE `posts` (\n  `ID` bigint,\n  `wp_users` bigint,\n  \
+            CONSTRAINT `author` FOREIGN KEY (`wp_users`) REFERENCES `users` (`ID`)\n);\n"
+        );
+        assert_eq!(
+            rewrite("INSERT INTO `wp_posts` VALUES (1,'`wp_posts`');\n"),
+            "INSERT INTO `posts` VALUES (1,'`wp_posts`');\n"
+        );
+        assert_eq!(rewrite("LOCK TABLES `wp_posts` WRITE;\n"), "LOCK TABLES `posts` WRITE;\n");
+    }
+
+    #[test]
+    fn a_prefix_of_another_name() {
+        let mut renames = renames(&[("wp_posts", "posts")]);
+        let mut rewrite = |text| rewrite(&mut renames, text).unwrap();
+        for text in [
+            "DROP TABLE IF EXISTS `wp_postmeta`;\n",
+            "CREATE TABLE `wp_postmeta` (\n  `post_id` bigint\n);\n",
+            "INSERT INTO `wp_postmeta` VALUES (1);\n",
+            "LOCK TABLES `wp_posts_archive` WRITE;\n",
+        ] {
+            assert_eq!(rewrite(text), text);
+        }
+    }
+
+    #[test]
+    fn views_triggers_and_routines() {
+        let mut renames = renames(&[("wp_posts", "posts"), ("wp_log", "log")]);
+        let mut rewrite = |text| rewrite(&mut renames, text).unwrap();
+        assert_eq!(
+            rewrite(
+                "/*!50001 CREATE ALGORITHM=UNDEFINED */\n\
+                /*!50013 DEFINER=`wp_posts`@`localhost` SQL SECURITY DEFINER */\n\
+                /*!50001 VIEW `recent` AS select `wp_posts`.`ID` AS `ID` from `wp_posts` \
+                where (`wp_posts`.`post_status` = 'wp_posts') */;\n"
+            ),
+            // Not a user or a string named like a table
+            "/*!50001 CREATE ALGORITHM=UNDEFINED */\n\
+            /*!50013 DEFINER=`wp_posts`@`localhost` SQL SECURITY DEFINER */\n\
+            /*!50001 VIEW `recent` AS select `posts`.`ID` AS `ID` from `posts` \
+            where (`posts`.`post_status` = 'wp_posts') */;\n"
+        );
+        assert_eq!(
+            rewrite(
+                "/*!50003 CREATE*/ /*!50017 DEFINER=`root`@`localhost`*/ /*!50003 TRIGGER `logged` \
+                AFTER INSERT ON `wp_posts` FOR EACH ROW INSERT INTO `wp_log` VALUES (NEW.ID) */;;\n"
+            ),
+            "/*!50003 CREATE*/ /*!50017 DEFINER=`root`@`localhost`*/ /*!50003 TRIGGER `logged` \
+            AFTER INSERT ON `posts` FOR EACH ROW INSERT INTO `log` VALUES (NEW.ID) */;;\n"
+        );
+        assert_eq!(
+            rewrite(
+                "CREATE DEFINER=`root`@`%` PROCEDURE `clean_up`()\nBEGIN\n  \
+                DELETE FROM `wp_log` WHERE `id` IN (SELECT `ID` FROM `wp_posts`);\nEND ;;\n"
+            ),
+            "CREATE DEFINER=`root`@`%` PROCEDURE `clean_up`()\nBEGIN\n  \
+            DELETE FROM `log` WHERE `id` IN (SELECT `ID` FROM `posts`);\nEND ;;\n"
+        );
+    }
+
+    #[test]
+    fn prefixes() {
+        let mut renames = renames(&[("wp_users", "users")]);
+        renames.insert_prefix("wp_".to_string(), "site_".to_string());
+        renames.insert_prefix("wp_2_".to_string(), "site2_".to_string());
+        // The exact rename, then the longest prefix
+        assert_eq!(renames.get("wp_users").as_deref(), Some("users"));
+        assert_eq!(renames.get("wp_2_posts").as_deref(), Some("site2_posts"));
+        assert_eq!(renames.get("wp_posts").as_deref(), Some("site_posts"));
+        assert_eq!(renames.get("old_wp_posts"), None);
+        assert_eq!(
+            rewrite(&mut renames, "INSERT INTO `wp_2_options` VALUES ('wp_2_options');\n").unwrap(),
+            "INSERT INTO `site2_options` VALUES ('wp_2_options');\n"
+        );
+
+        let mut renames = Renames::new();
+        let (old, new) = parse_prefix_rename("wp_=").unwrap();
+        renames.insert_prefix(old, new);
+        assert_eq!(rewrite(&mut renames, "DROP TABLE IF EXISTS `wp_posts`;\n").unwrap(), "DROP TABLE IF EXISTS `posts`;\n");
+        assert!(parse_prefix_rename("=site_").is_err());
+    }
+
+    #[test]
+    fn qualified_and_unqualified() {
+        let mut renames = renames(&[("wp_posts", "posts")]);
+        renames.insert_database("shop".to_string(), "store".to_string());
+        let mut rewrite = |text| rewrite(&mut renames, text).unwrap();
+        assert_eq!(rewrite("-- Current Database: `shop`\n"), "-- Current Database: `store`\n");
+        assert_eq!(
+            rewrite("CREATE DATABASE /*!32312 IF NOT EXISTS*/ `shop` /*!40100 DEFAULT CHARACTER SET utf8mb4 */;\n"),
+            "CREATE DATABASE /*!32312 IF NOT EXISTS*/ `store` /*!40100 DEFAULT CHARACTER SET utf8mb4 */;\n"
+        );
+        assert_eq!(rewrite("USE `shop`;\n"), "USE `store`;\n");
+        assert_eq!(rewrite("INSERT INTO `shop`.`wp_posts` VALUES (1);\n"), "INSERT INTO `store`.`posts` VALUES (1);\n");
+        // Only databases the dump has are qualifiers to rename, and a
+        // table's renamed however it's referenced
+        assert_eq!(
+            rewrite("CREATE VIEW `v` AS select `shop`.`wp_posts`.`ID` from `shop`.`wp_posts`, `wp_posts`, `other`.`t`;"),
+            "CREATE VIEW `v` AS select `store`.`posts`.`ID` from `store`.`posts`, `posts`, `other`.`t`;"
+        );
+    }
+
+    #[test]
+    fn stripping_databases() {
+        let mut renames = Renames::new();
+        renames.strip_databases();
+        let mut rewrite = |text| rewrite(&mut renames, text);
+        let comment = "-- Current Database: `shop`\n";
+        assert_eq!(rewrite(comment).as_deref(), Some(comment));
+        assert_eq!(rewrite("CREATE DATABASE `shop`;\n"), None);
+        assert_eq!(rewrite("USE `shop`;\n"), None);
+        assert_eq!(
+            rewrite("CREATE TABLE `shop`.`orders` (\n  `id` int REFERENCES `shop`.`customers` (`id`)\n);\n").as_deref(),
+            Some("CREATE TABLE `orders` (\n  `id` int REFERENCES `customers` (`id`)\n);\n")
+        );
+        assert_eq!(
+            rewrite("CREATE VIEW `v` AS select `shop`.`orders`.`id` from `shop`.`orders`, `mysql`.`user`;").as_deref(),
+            Some("CREATE VIEW `v` AS select `orders`.`id` from `orders`, `mysql`.`user`;")
+        );
+    }
+}
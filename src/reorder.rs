@@ -0,0 +1,334 @@
+//! Reordering a dump so that tables referenced by foreign keys are created
+//! (and filled) before the tables that reference them, or into another
+//! order that doesn't depend on how the dump was made.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+use crate::dump::{Section, SectionKind, SectionReader};
+use crate::schema::parse_create_table;
+
+/// How to order a dump's tables, among the positions tables occupied in
+/// each database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableOrder {
+    /// As they are in the dump
+    #[default]
+    Original,
+    /// By name
+    Alpha,
+    /// Largest first, by the bytes of their sections, and then by name
+    Size,
+    /// Parents before the tables whose foreign keys reference them, and then
+    /// by name
+    Dependency,
+}
+
+impl FromStr for TableOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" => Ok(TableOrder::Original),
+            "alpha" => Ok(TableOrder::Alpha),
+            "size" => Ok(TableOrder::Size),
+            "dependency" => Ok(TableOrder::Dependency),
+            _ => Err(format!("unknown order `{}`, expected alpha, size, dependency or original", s)),
+        }
+    }
+}
+
+/// Write `input` to `output` with table sections sorted parents-first.
+///
+/// Everything that isn't a table (header, views, routines, footer) stays where
+/// it was; tables are only shuffled among the positions tables occupied, and
+/// never across a `USE` in multi-database dumps. Tables with no ordering
+/// constraint between them keep their original relative order.
+///
+/// Returns the foreign key cycles that had to be broken, each listed starting
+/// with the table that was emitted before its parents.
+pub fn reorder_dump<F: Read + Seek, W: Write>(mut input: F, output: &mut W) -> io::Result<Vec<Vec<String>>> {
+    let (sections, parents) = read_sections(&mut input)?;
+    let mut cycles = Vec::new();
+    let order = arrange(&sections, |slots| sort_segment(&sections, slots, &parents, &mut cycles));
+    for index in order {
+        let section = &sections[index];
+        input.seek(SeekFrom::Start(section.start))?;
+        io::copy(&mut (&mut input).take(section.end - section.start), output)?;
+    }
+    Ok(cycles)
+}
+
+/// A reader of the dump in `input` with its tables in `order`, moved as
+/// [`reorder_dump`] moves them, for trimming them in that order. With
+/// [`TableOrder::Dependency`], also the foreign key cycles that had to be
+/// broken.
+///
+/// The dump's read through once first, to find its tables.
+pub fn reordered<F: Read + Seek>(mut input: F, order: TableOrder) -> io::Result<(Reordered<F>, Vec<Vec<String>>)> {
+    let (sections, parents) = read_sections(&mut input)?;
+    let name = |slot: &usize| sections[*slot].kind.name().unwrap_or_default();
+    let by_name = |slots: &[usize]| {
+        let mut sorted = slots.to_vec();
+        sorted.sort_by(|a, b| name(a).cmp(name(b)));
+        sorted
+    };
+    let mut cycles = Vec::new();
+    let order = arrange(&sections, |slots| match order {
+        TableOrder::Original => slots.to_vec(),
+        TableOrder::Alpha => by_name(slots),
+        TableOrder::Size => {
+            let mut sorted = by_name(slots);
+            sorted.sort_by_key(|&slot| Reverse(sections[slot].end - sections[slot].start));
+            sorted
+        }
+        // Tables that could go in either order are picked by name
+        TableOrder::Dependency => sort_segment(&sections, &by_name(slots), &parents, &mut cycles),
+    });
+    Ok((Reordered { input, sections, order, next: 0, left: 0 }, cycles))
+}
+
+/// A dump with its tables reordered, from [`reordered`].
+pub struct Reordered<F> {
+    input: F,
+    sections: Vec<Section>,
+    /// The sections, in the order they're read
+    order: Vec<usize>,
+    /// The position in `order` of the next section
+    next: usize,
+    /// What's left to read of the current section
+    left: u64,
+}
+
+impl<F: Read + Seek> Read for Reordered<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.left == 0 {
+            let Some(&index) = self.order.get(self.next) else {
+                return Ok(0);
+            };
+            self.next += 1;
+            let section = &self.sections[index];
+            self.input.seek(SeekFrom::Start(section.start))?;
+            self.left = section.end - section.start;
+        }
+        let len = buf.len().min(usize::try_from(self.left).unwrap_or(usize::MAX));
+        let read = self.input.read(&mut buf[..len])?;
+        if read == 0 && len > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the dump changed while it was being read"));
+        }
+        self.left -= read as u64;
+        Ok(read)
+    }
+}
+
+/// The tables each table's foreign keys reference in the same database, by
+/// the table's section.
+type Parents = HashMap<usize, Vec<String>>;
+
+/// The sections of the dump in `input`, and their tables' parents.
+fn read_sections<F: Read>(input: F) -> io::Result<(Vec<Section>, Parents)> {
+    let mut parents = Parents::new();
+    let mut reader = SectionReader::new(BufReader::new(input));
+    while let Some(item) = reader.next() {
+        let (index, statement) = item?;
+        if !matches!(reader.sections()[index].kind, SectionKind::Table(_)) {
+            continue;
+        }
+        if let Some(schema) = parse_create_table(&statement.text) {
+            let database = current_database(reader.sections(), index);
+            let entry = parents.entry(index).or_default();
+            for foreign_key in schema.foreign_keys {
+                let same_database = match (&foreign_key.referenced_database, database) {
+                    (Some(referenced), Some(current)) => referenced == current,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+                if same_database && foreign_key.referenced_table != schema.name {
+                    entry.push(foreign_key.referenced_table);
+                }
+            }
+        }
+    }
+    Ok((reader.into_sections(), parents))
+}
+
+/// The order to write `sections` in, with the tables of each database,
+/// between its `USE` and the next, put in the order `sort` gives the
+/// positions of them, and everything else where it was.
+fn arrange(sections: &[Section], mut sort: impl FnMut(&[usize]) -> Vec<usize>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sections.len()).collect();
+    let mut segment_start = 0;
+    for boundary in (0..=sections.len()).filter(|&i| i == sections.len() || matches!(sections[i].kind, SectionKind::Database(_))) {
+        let slots: Vec<usize> = (segment_start..boundary)
+            .filter(|&i| matches!(sections[i].kind, SectionKind::Table(_)))
+            .collect();
+        let sorted = sort(&slots);
+        for (slot, index) in slots.into_iter().zip(sorted) {
+            order[slot] = index;
+        }
+        segment_start = boundary;
+    }
+    order
+}
+
+/// The database a section belongs to, if the dump names one.
+fn current_database(sections: &[Section], index: usize) -> Option<&str> {
+    sections[..index].iter().rev().find_map(|section| match &section.kind {
+        SectionKind::Database(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Topologically sort the table sections at `slots` (Kahn's algorithm),
+/// always picking the earliest table in the dump that is ready.
+fn sort_segment(
+    sections: &[Section],
+    slots: &[usize],
+    parents: &Parents,
+    cycles: &mut Vec<Vec<String>>,
+) -> Vec<usize> {
+    let name = |position: usize| sections[slots[position]].kind.name().unwrap_or_default();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for position in 0..slots.len() {
+        positions.entry(name(position)).or_insert(position);
+    }
+
+    // Parents still to be emitted for each table, and children waiting on each
+    let mut waiting_on: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); slots.len()];
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); slots.len()];
+    for (position, slot) in slots.iter().enumerate() {
+        for parent in parents.get(slot).into_iter().flatten() {
+            if let Some(&parent) = positions.get(parent.as_str()) {
+                if parent != position && waiting_on[position].insert(parent) {
+                    children[parent].push(position);
+                }
+            }
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..slots.len()).filter(|&p| waiting_on[p].is_empty()).collect();
+    let mut emitted = vec![false; slots.len()];
+    let mut sorted = Vec::with_capacity(slots.len());
+    while sorted.len() < slots.len() {
+        let position = match ready.pop_first() {
+            Some(position) => position,
+            None => {
+                let cycle = find_cycle(&waiting_on, &emitted);
+                cycles.push(cycle.iter().map(|&p| name(p).to_string()).collect());
+                cycle[0]
+            }
+        };
+        emitted[position] = true;
+        sorted.push(slots[position]);
+        for &child in &children[position] {
+            waiting_on[child].remove(&position);
+            if waiting_on[child].is_empty() && !emitted[child] {
+                ready.insert(child);
+            }
+        }
+    }
+    sorted
+}
+
+/// Walk from the earliest table not yet emitted through its earliest waiting
+/// parent until a table repeats, returning the cycle that closes.
+fn find_cycle(waiting_on: &[BTreeSet<usize>], emitted: &[bool]) -> Vec<usize> {
+    let mut path = vec![emitted.iter().position(|e| !e).unwrap()];
+    loop {
+        let current = *path.last().unwrap();
+        let parent = *waiting_on[current].first().unwrap();
+        if let Some(start) = path.iter().position(|&p| p == parent) {
+            return path.split_off(start);
+        }
+        path.push(parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A table's section, with a foreign key to each of `parents`.
+    fn table(name: &str, parents: &[&str]) -> String {
+        let mut columns = vec!["  `id` int NOT NULL".to_string()];
+        for parent in parents {
+            columns.push(format!("  `{parent}_id` int"));
+        }
+        for parent in parents {
+            let key = format!("FOREIGN KEY (`{parent}_id`) REFERENCES `{parent}` (`id`)");
+            columns.push(format!("  CONSTRAINT `{name}_{parent}` {key}"));
+        }
+        format!(
+            "DROP TABLE IF EXISTS `{name}`;\nCREATE TABLE `{name}` (\n{}\n);\nINSERT INTO `{name}` VALUES (1{});\n",
+            columns.join(",\n"),
+            ",1".repeat(parents.len())
+        )
+    }
+
+    /// The tables created in `dump`, in order.
+    fn tables(dump: &str) -> Vec<String> {
+        dump.lines()
+            .filter_map(|line| Some(line.strip_prefix("CREATE TABLE `")?.split('`').next()?.to_string()))
+            .collect()
+    }
+
+    /// The tables of `dump` reordered, and the cycles broken.
+    fn reorder(dump: &str) -> (Vec<String>, Vec<Vec<String>>) {
+        let mut output = Vec::new();
+        let cycles = reorder_dump(io::Cursor::new(dump), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.len(), dump.len());
+        (tables(&output), cycles)
+    }
+
+    #[test]
+    fn parents_first() {
+        let dump = [
+            "/*!40101 SET NAMES utf8mb4 */;\n".to_string(),
+            table("comments", &["posts", "users"]),
+            table("logs", &[]),
+            table("posts", &["users"]),
+            table("users", &[]),
+            "/*!40101 SET SQL_MODE=@OLD_SQL_MODE */;\n".to_string(),
+        ]
+        .concat();
+        let (tables, cycles) = reorder(&dump);
+        // Otherwise in the order of the dump
+        assert_eq!(tables, ["logs", "users", "posts", "comments"]);
+        assert!(cycles.is_empty());
+    }
+
+    #[test]
+    fn cycles() {
+        let dump = [table("a", &["b"]), table("b", &["a"]), table("c", &["c", "a"])].concat();
+        let (tables, cycles) = reorder(&dump);
+        // A table referencing itself is no cycle
+        assert_eq!(tables, ["a", "b", "c"]);
+        assert_eq!(cycles, [["a", "b"]]);
+    }
+
+    #[test]
+    fn not_across_databases() {
+        let dump = ["USE `one`;\n", &table("child", &["parent"]), "USE `two`;\n", &table("parent", &[])].concat();
+        let (tables, _) = reorder(&dump);
+        assert_eq!(tables, ["child", "parent"]);
+    }
+
+    #[test]
+    fn orders() {
+        let dump = [table("b", &[]), table("c", &["b"]), table("a", &["c", "b"])].concat();
+        let order = |order| {
+            let (mut reordered, _) = reordered(io::Cursor::new(&dump), order).unwrap();
+            let mut output = String::new();
+            reordered.read_to_string(&mut output).unwrap();
+            tables(&output)
+        };
+        assert_eq!(order(TableOrder::Original), ["b", "c", "a"]);
+        assert_eq!(order(TableOrder::Alpha), ["a", "b", "c"]);
+        // The more foreign keys, the bigger the section
+        assert_eq!(order(TableOrder::Size), ["a", "c", "b"]);
+        assert_eq!(order(TableOrder::Dependency), ["b", "c", "a"]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Rewriting or dropping rows with a library user's own [`RowTransformer`]s.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use crate::insert::{insert_table, parse_insert};
+use crate::schema::{parse_create_table, Column};
+
+/// Rewrites or drops each row of every table, as a dump is extracted.
+///
+/// ```no_run
+/// # use std::borrow::Cow;
+/// # use std::io;
+/// # use mysqltrim::row::RowTransformer;
+/// # use mysqltrim::schema::Column;
+/// /// Keeps one row in ten of every table.
+/// struct Sample(u64);
+///
+/// impl RowTransformer for Sample {
+///     fn transform<'t>(&mut self, _table: &str, _columns: &[Column], tuple: &'t [u8]) -> io::Result<Option<Cow<'t, [u8]>>> {
+///         self.0 += 1;
+///         Ok((self.0 % 10 == 0).then_some(Cow::Borrowed(tuple)))
+///     }
+/// }
+/// ```
+pub trait RowTransformer {
+    /// Rewrite a row of `table`, or return `None` to drop it. `tuple` is the
+    /// row's parenthesised values as written in the dump, such as
+    /// `(1,'draft',NULL)`, and what's returned has to be too. `columns` are
+    /// the table's columns in the order of the values, from its
+    /// `CREATE TABLE`, or empty if that hasn't been seen.
+    fn transform<'t>(&mut self, table: &str, columns: &[Column], tuple: &'t [u8]) -> io::Result<Option<Cow<'t, [u8]>>>;
+}
+
+/// The row transformers to run, and the columns of the tables they've seen.
+#[derive(Default)]
+pub struct RowTransformers {
+    transformers: Vec<Box<dyn RowTransformer>>,
+    /// Columns of each table, from its `CREATE TABLE`
+    columns: HashMap<String, Vec<Column>>,
+}
+
+impl std::fmt::Debug for RowTransformers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RowTransformers").field("transformers", &self.transformers.len()).finish_non_exhaustive()
+    }
+}
+
+impl RowTransformers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `transformer` on every row, after the ones already inserted.
+    pub fn insert(&mut self, transformer: impl RowTransformer + 'static) {
+        self.transformers.push(Box::new(transformer));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transformers.is_empty()
+    }
+
+    /// Run the transformers on the rows of an `INSERT`, dropping the whole
+    /// statement if none are left, and learn each table's columns from its
+    /// `CREATE TABLE`.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> io::Result<Option<Cow<'a, [u8]>>> {
+        if self.transformers.is_empty() {
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+        if let Some(schema) = parse_create_table(text) {
+            self.columns.insert(schema.name, schema.columns);
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+
+        if insert_table(text).is_none() {
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+        let Some(insert) = parse_insert(text) else { return Ok(Some(Cow::Borrowed(text))) };
+        let schema = self.columns.get(&insert.table).map_or(&[][..], Vec::as_slice);
+        // Columns may be listed in another order, or left out
+        let listed: Vec<Column>;
+        let columns = match &insert.columns {
+            Some(names) if !schema.is_empty() => {
+                listed = names
+                    .iter()
+                    .map(|name| match schema.iter().find(|column| column.name.eq_ignore_ascii_case(name)) {
+                        Some(column) => column.clone(),
+                        None => Column::new(name.clone(), String::new()),
+                    })
+                    .collect();
+                &listed[..]
+            }
+            _ => schema,
+        };
+
+        let mut changed = false;
+        let mut tuples = Vec::with_capacity(insert.tuples.len());
+        'rows: for &tuple in &insert.tuples {
+            let mut row = Cow::Borrowed(tuple);
+            for transformer in &mut self.transformers {
+                let transformed = match transformer.transform(&insert.table, columns, &row)? {
+                    None => continue 'rows,
+                    Some(Cow::Borrowed(_)) => None,
+                    Some(Cow::Owned(transformed)) => Some(transformed),
+                };
+                if let Some(transformed) = transformed {
+                    row = Cow::Owned(transformed);
+                }
+            }
+            changed |= matches!(row, Cow::Owned(_));
+            tuples.push(row);
+        }
+        if !changed && tuples.len() == insert.tuples.len() {
+            return Ok(Some(Cow::Borrowed(text)));
+        }
+        if tuples.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Cow::Owned(insert.with_tuples(tuples.iter().map(|tuple| &tuple[..])))))
+    }
+}
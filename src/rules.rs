@@ -0,0 +1,234 @@
+//! Rules transforming the columns whose names match a pattern, read from a
+//! TOML file kept with a project.
+
+use regex::Regex;
+
+use crate::scrub::{Redact, Replacement, RowMatch, ScrubColumn};
+
+/// A rule replacing the values of the columns matching `column`, in the
+/// tables matching `table` (or every table). Both are regexes, matching whole
+/// names.
+#[derive(Debug, Clone)]
+pub struct ColumnRule {
+    pub table: Option<String>,
+    pub column: String,
+    pub replacement: Replacement,
+    /// Only transform the rows this matches, rather than every row
+    pub rows: Option<RowMatch>,
+    /// The redaction `replacement` is for, to suit to each column's type
+    pub redact: Option<Redact>,
+    tables: Option<Regex>,
+    columns: Regex,
+}
+
+impl ColumnRule {
+    pub fn new(
+        table: Option<&str>,
+        column: &str,
+        replacement: Replacement,
+        rows: Option<RowMatch>,
+    ) -> Result<Self, regex::Error> {
+        let whole = |pattern: &str| Regex::new(&format!("^(?:{})$", pattern));
+        Ok(ColumnRule {
+            table: table.map(str::to_string),
+            column: column.to_string(),
+            replacement,
+            rows,
+            redact: None,
+            tables: table.map(whole).transpose()?,
+            columns: whole(column)?,
+        })
+    }
+
+    /// A rule redacting the values of the columns whose names contain a match
+    /// of `column`, ignoring case, in every table, as suits each column's
+    /// type (see [`Redact::replacement_for`]).
+    pub fn redact(column: &str, redact: Redact) -> Result<Self, regex::Error> {
+        Ok(ColumnRule {
+            table: None,
+            column: column.to_string(),
+            replacement: redact.replacement(),
+            rows: None,
+            redact: Some(redact),
+            tables: None,
+            columns: Regex::new(&format!("(?i){}", column))?,
+        })
+    }
+
+    /// The rule for `column` of `table`, if this rule matches it.
+    pub fn for_column(&self, table: &str, column: &str) -> Option<ScrubColumn> {
+        let matched = self.tables.as_ref().is_none_or(|tables| tables.is_match(table)) && self.columns.is_match(column);
+        matched.then(|| ScrubColumn {
+            table: table.to_string(),
+            column: column.to_string(),
+            replacement: self.replacement.clone(),
+            rows: self.rows.clone(),
+        })
+    }
+}
+
+/// Parse a rules file: a `[[rule]]` table for each rule, naming the `table`
+/// and `column` patterns it matches, its `transform` and that transform's
+/// settings, and optionally `where`, the rows it's limited to.
+///
+/// ```toml
+/// [[rule]]
+/// table = "wp_(\\d+_)?comments"
+/// column = "comment_author_email"
+/// transform = "fake"
+/// fake = "email"
+///
+/// [[rule]]
+/// table = "wp_options"
+/// column = "option_value"
+/// transform = "constant"
+/// value = ""
+/// where = { column = "option_name", values = ["mailserver_pass"] }
+/// ```
+///
+/// The transforms are `null`, `constant` (with `value`), `hash`, `fake` (with
+/// `fake`, one of the anonymize generators), `truncate` (with `length`) and
+/// `regex-replace` (with `pattern` and `replacement`).
+///
+/// Any other key is an error, rather than being ignored, so a mistyped
+/// setting doesn't leave a column unscrubbed.
+pub fn parse_rules(text: &str) -> Result<Vec<ColumnRule>, String> {
+    let file: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let mut rules = Vec::new();
+    for (key, value) in file {
+        match (key.as_str(), value) {
+            ("rule", toml::Value::Array(entries)) => {
+                for (i, entry) in entries.iter().enumerate() {
+                    rules.push(parse_rule(entry).map_err(|e| format!("rule {}: {}", i + 1, e))?);
+                }
+            }
+            ("rule", _) => return Err("rule: expected [[rule]] tables".to_string()),
+            _ => return Err(format!("unknown key `{}`, expected [[rule]] tables", key)),
+        }
+    }
+    Ok(rules)
+}
+
+/// The keys every rule can have, besides its transform's settings
+const RULE_KEYS: &[&str] = &["table", "column", "transform", "where"];
+
+fn parse_rule(entry: &toml::Value) -> Result<ColumnRule, String> {
+    let entry = entry.as_table().ok_or("expected a table")?;
+    let string = |key: &str| match entry.get(key) {
+        Some(toml::Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(format!("{}: expected a string", key)),
+        None => Ok(None),
+    };
+    let required = |key: &str| string(key)?.ok_or_else(|| format!("missing `{}`", key));
+
+    let transform = required("transform")?;
+    let settings: &[&str] = match transform.as_str() {
+        "null" | "hash" => &[],
+        "constant" => &["value"],
+        "fake" => &["fake"],
+        "truncate" => &["length"],
+        "regex-replace" => &["pattern", "replacement"],
+        _ => {
+            return Err(format!(
+                "unknown transform `{}`, expected null, constant, hash, fake, truncate or regex-replace",
+                transform
+            ))
+        }
+    };
+    if let Some(key) = entry.keys().find(|key| !RULE_KEYS.contains(&key.as_str()) && !settings.contains(&key.as_str())) {
+        return Err(format!("unknown key `{}` for the {} transform", key, transform));
+    }
+    let replacement = match transform.as_str() {
+        "null" => Replacement::Null,
+        "hash" => Replacement::Hash,
+        "constant" => Replacement::Constant(required("value")?),
+        "fake" => Replacement::Fake(required("fake")?.parse()?),
+        "truncate" => match entry.get("length") {
+            Some(toml::Value::Integer(length)) if *length >= 0 => Replacement::Truncate(*length as usize),
+            Some(_) => return Err("length: expected a number of characters".to_string()),
+            None => return Err("missing `length`".to_string()),
+        },
+        "regex-replace" => Replacement::RegexReplace {
+            regex: regex::bytes::Regex::new(&required("pattern")?).map_err(|e| e.to_string())?,
+            replacement: required("replacement")?,
+        },
+        _ => unreachable!("the transform was checked above"),
+    };
+
+    let rows = match entry.get("where") {
+        Some(toml::Value::Table(rows)) => Some(parse_where(rows).map_err(|e| format!("where: {}", e))?),
+        Some(_) => return Err("where: expected a table".to_string()),
+        None => None,
+    };
+    ColumnRule::new(string("table")?.as_deref(), &required("column")?, replacement, rows).map_err(|e| e.to_string())
+}
+
+/// The rows a rule's limited to, as `{ column = "...", values = [...] }`.
+fn parse_where(rows: &toml::Table) -> Result<RowMatch, String> {
+    if let Some(key) = rows.keys().find(|key| !["column", "values"].contains(&key.as_str())) {
+        return Err(format!("unknown key `{}`, expected column and values", key));
+    }
+    let column = match rows.get("column") {
+        Some(toml::Value::String(column)) => column.clone(),
+        Some(_) => return Err("column: expected a string".to_string()),
+        None => return Err("missing `column`".to_string()),
+    };
+    let values = match rows.get("values") {
+        Some(toml::Value::Array(values)) => values
+            .iter()
+            .map(|value| value.as_str().map(str::to_string).ok_or("values: expected strings"))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("values: expected a list of strings".to_string()),
+        None => return Err("missing `values`".to_string()),
+    };
+    Ok(RowMatch { column, values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rules = parse_rules(
+            r#"
+            [[rule]]
+            table = "wp_(\\d+_)?comments"
+            column = "comment_author_email"
+            transform = "fake"
+            fake = "email"
+
+            [[rule]]
+            table = "wp_options"
+            column = "option_value"
+            transform = "truncate"
+            length = 10
+            where = { column = "option_name", values = ["blogdescription"] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert!(rules[0].for_column("wp_2_comments", "comment_author_email").is_some());
+        assert!(matches!(rules[1].replacement, Replacement::Truncate(10)));
+        assert_eq!(rules[1].rows.as_ref().unwrap().values, ["blogdescription"]);
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let rule = |settings: &str| parse_rules(&format!("[[rule]]\ncolumn = \"email\"\n{}", settings));
+        assert_eq!(
+            rule("transform = \"truncate\"\nlenght = 10").unwrap_err(),
+            "rule 1: unknown key `lenght` for the truncate transform"
+        );
+        assert_eq!(
+            rule("transform = \"fake\"\nfake = \"email\"\nfakee = \"name\"").unwrap_err(),
+            "rule 1: unknown key `fakee` for the fake transform"
+        );
+        // Another transform's setting
+        assert!(rule("transform = \"hash\"\nvalue = \"x\"").is_err());
+        assert!(rule("transform = \"hash\"\nwhere = { column = \"id\", value = [\"1\"] }").is_err());
+        assert!(rule("transform = \"nul\"").is_err());
+        assert!(parse_rules("[[rules]]\ncolumn = \"email\"\ntransform = \"null\"").is_err());
+        assert!(rule("transform = \"hash\"").is_ok());
+    }
+}
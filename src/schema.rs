@@ -0,0 +1,332 @@
+//! Parsing the table definitions mysqldump writes out with `SHOW CREATE TABLE`.
+
+use std::ops::Range;
+
+use crate::dump::{parse_identifier, parse_qualified_identifier, strip_conditional_comment, strip_keywords};
+
+/// A table's columns, keys and options, parsed from its `CREATE TABLE`.
+#[derive(Debug, Clone, Default)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    /// The primary key's columns, empty if there's none
+    pub primary_key: Vec<String>,
+    /// The keys other than the primary key
+    pub indexes: Vec<Index>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub options: Vec<TableOption>,
+}
+
+impl TableSchema {
+    /// The value of a table option, e.g. `option("ENGINE")`.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|option| option.name == name).map(|option| option.value.as_str())
+    }
+
+    /// Position of a column, matched case-insensitively as MySQL does.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The table's default charset, from `DEFAULT CHARSET`, or else the
+    /// charset its `COLLATE` is of, as written, e.g. `utf8mb4`.
+    pub fn charset(&self) -> Option<&str> {
+        let charset = self.option("DEFAULT CHARSET").or_else(|| self.option("CHARSET"));
+        charset.or_else(|| collation_charset(self.option("COLLATE").or_else(|| self.option("DEFAULT COLLATE"))?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    /// The rest of the column definition, e.g. `bigint unsigned NOT NULL`
+    pub definition: String,
+    /// The type, with its length or values and any `unsigned` or `zerofill`,
+    /// as written, e.g. `bigint unsigned` or `enum('draft','publish')`
+    pub data_type: String,
+    pub nullable: bool,
+    /// The default as written, e.g. `'0'`, `NULL` or `CURRENT_TIMESTAMP`
+    pub default: Option<String>,
+}
+
+impl Column {
+    pub(crate) fn new(name: String, definition: String) -> Self {
+        let tokens = definition_tokens(&definition);
+        let mut data_type = tokens.first().copied().unwrap_or_default().to_string();
+        for attribute in tokens.iter().skip(1).take_while(|token| {
+            ["UNSIGNED", "SIGNED", "ZEROFILL"].iter().any(|attribute| token.eq_ignore_ascii_case(attribute))
+        }) {
+            data_type.push(' ');
+            data_type.push_str(attribute);
+        }
+        let nullable = !tokens
+            .windows(2)
+            .any(|pair| pair[0].eq_ignore_ascii_case("NOT") && pair[1].eq_ignore_ascii_case("NULL"));
+        let default = tokens
+            .windows(2)
+            .find(|pair| pair[0].eq_ignore_ascii_case("DEFAULT"))
+            .map(|pair| pair[1].to_string());
+        Column { data_type, nullable, default, name, definition }
+    }
+
+    /// Whether the column's `AUTO_INCREMENT`.
+    pub fn auto_increment(&self) -> bool {
+        definition_tokens(&self.definition).iter().any(|token| token.eq_ignore_ascii_case("AUTO_INCREMENT"))
+    }
+
+    /// The charset the column's definition gives, from `CHARACTER SET`, or
+    /// else the charset its `COLLATE` is of, as written. `None` if it uses
+    /// the table's default.
+    pub fn charset(&self) -> Option<&str> {
+        let tokens = definition_tokens(&self.definition);
+        let after = |keywords: &[&str]| {
+            tokens
+                .windows(keywords.len() + 1)
+                .find(|window| window.iter().zip(keywords).all(|(token, keyword)| token.eq_ignore_ascii_case(keyword)))
+                .map(|window| window[keywords.len()])
+        };
+        after(&["CHARACTER", "SET"]).or_else(|| collation_charset(after(&["COLLATE"])?))
+    }
+}
+
+/// The charset a collation is of, e.g. `utf8mb4` for `utf8mb4_unicode_ci`.
+fn collation_charset(collation: &str) -> Option<&str> {
+    collation.split_once('_').map(|(charset, _)| charset)
+}
+
+/// The words of a column definition, with each quoted string and
+/// parenthesised list kept within the word it's part of, as in `'a b'`,
+/// `enum('a','b c')` or `CURRENT_TIMESTAMP(6)`.
+fn definition_tokens(definition: &str) -> Vec<&str> {
+    let bytes = definition.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut depth = 0;
+        while i < bytes.len() && (depth > 0 || !bytes[i].is_ascii_whitespace()) {
+            match bytes[i] {
+                quote @ (b'\'' | b'"') => {
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != quote {
+                        if bytes[i] == b'\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                }
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        tokens.push(&definition[start..i.min(bytes.len())]);
+    }
+    tokens
+}
+
+/// A key other than the primary key.
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub name: String,
+    pub kind: IndexKind,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    Plain,
+    Unique,
+    Fulltext,
+    Spatial,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub name: Option<String>,
+    pub columns: Vec<String>,
+    /// Database of the referenced table, if it lives in a different one
+    pub referenced_database: Option<String>,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+}
+
+/// Parse a `CREATE TABLE` statement. Returns `None` if `text` isn't one.
+///
+/// This relies on the `SHOW CREATE TABLE` layout mysqldump uses, where each
+/// column, key and constraint is on its own line.
+pub fn parse_create_table(text: &[u8]) -> Option<TableSchema> {
+    let text = strip_conditional_comment(text.trim_ascii());
+    let rest = strip_keywords(text, &["CREATE", "TABLE", "IF", "NOT", "EXISTS"])
+        .or_else(|| strip_keywords(text, &["CREATE", "TABLE"]))?;
+    let (name, _) = parse_identifier(rest)?;
+    let mut schema = TableSchema { name, ..Default::default() };
+
+    for line in text.split(|b| *b == b'\n').skip(1) {
+        let line = line.trim_ascii();
+        let line = line.strip_suffix(b",").unwrap_or(line);
+        if line.starts_with(b"`") {
+            if let Some((name, rest)) = parse_identifier(line) {
+                let definition = String::from_utf8_lossy(rest.trim_ascii()).into_owned();
+                schema.columns.push(Column::new(name, definition));
+            }
+            continue;
+        }
+        if let Some(rest) = strip_keywords(line, &["PRIMARY", "KEY"]) {
+            if let Some((columns, _)) = parse_identifier_list(rest) {
+                schema.primary_key = columns;
+            }
+            continue;
+        }
+        if let Some(index) = parse_index(line) {
+            schema.indexes.push(index);
+            continue;
+        }
+        let (constraint, definition) = match strip_keywords(line, &["CONSTRAINT"]) {
+            Some(rest) if strip_keywords(rest, &["FOREIGN", "KEY"]).is_some() => (None, rest),
+            Some(rest) => match parse_identifier(rest) {
+                Some((name, rest)) => (Some(name), rest),
+                None => continue,
+            },
+            None => (None, line),
+        };
+        if let Some(foreign_key) = parse_foreign_key(constraint, definition) {
+            schema.foreign_keys.push(foreign_key);
+        }
+    }
+    schema.options = table_options(text);
+    Some(schema)
+}
+
+/// Parse `[UNIQUE | FULLTEXT | SPATIAL] KEY name (cols) ...`.
+fn parse_index(line: &[u8]) -> Option<Index> {
+    let (kind, rest) = [
+        ("UNIQUE", IndexKind::Unique),
+        ("FULLTEXT", IndexKind::Fulltext),
+        ("SPATIAL", IndexKind::Spatial),
+    ]
+    .into_iter()
+    .find_map(|(keyword, kind)| Some((kind, strip_keywords(line, &[keyword])?)))
+    .unwrap_or((IndexKind::Plain, line));
+    let rest = strip_keywords(rest, &["KEY"]).or_else(|| strip_keywords(rest, &["INDEX"]))?;
+    let (name, rest) = parse_identifier(rest)?;
+    let (columns, _) = parse_identifier_list(rest)?;
+    Some(Index { name, kind, columns })
+}
+
+/// Parse `FOREIGN KEY (cols) REFERENCES table (cols) ...`.
+fn parse_foreign_key(name: Option<String>, text: &[u8]) -> Option<ForeignKey> {
+    let rest = strip_keywords(text, &["FOREIGN", "KEY"])?;
+    // An index name may precede the column list
+    let rest = match parse_identifier(rest) {
+        Some((_, after)) if after.trim_ascii_start().starts_with(b"(") => after,
+        _ => rest,
+    };
+    let (columns, rest) = parse_identifier_list(rest)?;
+    let rest = strip_keywords(rest, &["REFERENCES"])?;
+    let (referenced_database, referenced_table, rest) = parse_qualified_identifier(rest)?;
+    let (referenced_columns, _) = parse_identifier_list(rest)?;
+    Some(ForeignKey { name, columns, referenced_database, referenced_table, referenced_columns })
+}
+
+/// Parse a parenthesised, comma separated list of identifiers, such as the
+/// columns of a key. Prefix lengths like `` `col`(191) `` are dropped.
+pub fn parse_identifier_list(text: &[u8]) -> Option<(Vec<String>, &[u8])> {
+    let mut rest = text.trim_ascii_start().strip_prefix(b"(")?;
+    let mut names = Vec::new();
+    loop {
+        let (name, after) = parse_identifier(rest)?;
+        names.push(name);
+        let mut after = after.trim_ascii_start();
+        if let Some(length) = after.strip_prefix(b"(") {
+            after = length.splitn(2, |b| *b == b')').nth(1)?.trim_ascii_start();
+        }
+        // Key parts can carry a sort order
+        after = strip_keywords(after, &["ASC"]).or_else(|| strip_keywords(after, &["DESC"])).unwrap_or(after);
+        match after.first() {
+            Some(b',') => rest = &after[1..],
+            Some(b')') => return Some((names, &after[1..])),
+            _ => return None,
+        }
+    }
+}
+
+/// A table option from after the column list, such as `ENGINE=InnoDB`.
+#[derive(Debug, Clone)]
+pub struct TableOption {
+    /// Upper-cased option name, e.g. `ENGINE` or `DEFAULT CHARSET`
+    pub name: String,
+    pub value: String,
+    /// Where the option (name and value) is in the text it was parsed from
+    pub range: Range<usize>,
+}
+
+/// The table options of a `CREATE TABLE` statement, which mysqldump writes
+/// on the line closing the column list: `) ENGINE=InnoDB DEFAULT CHARSET=...;`
+pub fn table_options(text: &[u8]) -> Vec<TableOption> {
+    let mut options = Vec::new();
+    let line_start = match text.trim_ascii_end().iter().rposition(|b| *b == b'\n') {
+        Some(newline) => newline + 1,
+        None => return options,
+    };
+    if !text[line_start..].starts_with(b")") {
+        return options;
+    }
+
+    let mut i = line_start + 1;
+    loop {
+        while i < text.len() && text[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let start = i;
+        let word = |i: &mut usize| {
+            let from = *i;
+            while *i < text.len() && (text[*i].is_ascii_alphanumeric() || text[*i] == b'_') {
+                *i += 1;
+            }
+            String::from_utf8_lossy(&text[from..*i]).to_ascii_uppercase()
+        };
+        let mut name = word(&mut i);
+        if name.is_empty() {
+            break;
+        }
+        if name == "DEFAULT" {
+            while i < text.len() && text[i] == b' ' {
+                i += 1;
+            }
+            name = format!("DEFAULT {}", word(&mut i));
+        }
+        while i < text.len() && text[i] == b' ' {
+            i += 1;
+        }
+        if text.get(i) == Some(&b'=') {
+            i += 1;
+            while i < text.len() && text[i] == b' ' {
+                i += 1;
+            }
+        }
+        let value_start = i;
+        if text.get(i) == Some(&b'\'') {
+            i += 1;
+            while i < text.len() && text[i] != b'\'' {
+                if text[i] == b'\\' || (text[i] == b'\'' && text.get(i + 1) == Some(&b'\'')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(text.len());
+        } else {
+            while i < text.len() && !text[i].is_ascii_whitespace() && text[i] != b';' {
+                i += 1;
+            }
+        }
+        let value = String::from_utf8_lossy(&text[value_start..i]).into_owned();
+        options.push(TableOption { name, value, range: start..i });
+    }
+    options
+}
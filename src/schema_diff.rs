@@ -0,0 +1,287 @@
+//! Comparing the tables of two schemas, such as a dump's and a live
+//! database's, to find where they've drifted apart.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::dump::Statement;
+use crate::schema::{parse_create_table, ForeignKey, Index, IndexKind, TableSchema};
+use crate::visit::{scan_dump_in_pieces, StatementVisitor};
+
+/// Table options that change as rows are added, so differ without the
+/// schema having drifted.
+const VOLATILE_OPTIONS: &[&str] = &["AUTO_INCREMENT"];
+
+/// The schema of each table in the dump `reader` reads, in order. Rows are
+/// skipped over in pieces, so aren't held in memory.
+pub fn read_schemas<R: BufRead>(reader: R) -> io::Result<Vec<TableSchema>> {
+    struct Schemas(Vec<TableSchema>);
+
+    impl StatementVisitor for Schemas {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            if let Some(schema) = parse_create_table(&statement.text) {
+                self.0.push(schema);
+            }
+            Ok(())
+        }
+    }
+
+    let mut schemas = Schemas(Vec::new());
+    scan_dump_in_pieces(reader, 1 << 20, &mut schemas)?;
+    Ok(schemas.0)
+}
+
+/// Which of the two schemas compared something's in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A way two schemas differ, found by [`diff_schemas`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SchemaDifference {
+    /// A table only one side has
+    Table { table: String, only_in: Side },
+    /// A column only one side's table has
+    Column { table: String, column: String, only_in: Side },
+    /// A column both have, defined differently, e.g. `bigint unsigned NOT NULL`
+    ColumnDefinition { table: String, column: String, left: String, right: String },
+    /// The columns both tables have, in a different order
+    ColumnOrder { table: String, left: Vec<String>, right: Vec<String> },
+    /// The primary key's columns, empty if there's none
+    PrimaryKey { table: String, left: Vec<String>, right: Vec<String> },
+    /// A key only one side's table has, by name
+    Index { table: String, index: String, only_in: Side },
+    /// A key both have, on different columns or of a different kind
+    IndexDefinition { table: String, index: String, left: String, right: String },
+    /// A foreign key only one side's table has, by its name, or its columns
+    /// if it has none
+    ForeignKey { table: String, foreign_key: String, only_in: Side },
+    /// A foreign key both have, referencing something different
+    ForeignKeyDefinition { table: String, foreign_key: String, left: String, right: String },
+    /// A table option, such as `ENGINE`, that's different or only set on
+    /// one side
+    Option { table: String, option: String, left: Option<String>, right: Option<String> },
+}
+
+impl SchemaDifference {
+    /// The table that differs.
+    pub fn table(&self) -> &str {
+        match self {
+            SchemaDifference::Table { table, .. }
+            | SchemaDifference::Column { table, .. }
+            | SchemaDifference::ColumnDefinition { table, .. }
+            | SchemaDifference::ColumnOrder { table, .. }
+            | SchemaDifference::PrimaryKey { table, .. }
+            | SchemaDifference::Index { table, .. }
+            | SchemaDifference::IndexDefinition { table, .. }
+            | SchemaDifference::ForeignKey { table, .. }
+            | SchemaDifference::ForeignKeyDefinition { table, .. }
+            | SchemaDifference::Option { table, .. } => table,
+        }
+    }
+
+    /// A line describing the difference, with the sides called `left` and
+    /// `right`, such as `the dump` and `the database`.
+    pub fn describe(&self, left: &str, right: &str) -> String {
+        let side = |only_in: &Side| match only_in {
+            Side::Left => left,
+            Side::Right => right,
+        };
+        let set = |value: &Option<String>| value.as_deref().unwrap_or("not set").to_string();
+        match self {
+            SchemaDifference::Table { table, only_in } => format!("{}: only in {}", table, side(only_in)),
+            SchemaDifference::Column { table, column, only_in } => {
+                format!("{}: column {} only in {}", table, column, side(only_in))
+            }
+            SchemaDifference::ColumnDefinition { table, column, left: l, right: r } => {
+                format!("{}: column {} is {} in {}, {} in {}", table, column, l, left, r, right)
+            }
+            SchemaDifference::ColumnOrder { table, left: l, right: r } => {
+                format!("{}: columns are ordered {} in {}, {} in {}", table, l.join(", "), left, r.join(", "), right)
+            }
+            SchemaDifference::PrimaryKey { table, left: l, right: r } => {
+                let key = |columns: &Vec<String>| if columns.is_empty() { "none".to_string() } else { columns.join(", ") };
+                format!("{}: primary key is ({}) in {}, ({}) in {}", table, key(l), left, key(r), right)
+            }
+            SchemaDifference::Index { table, index, only_in } => {
+                format!("{}: key {} only in {}", table, index, side(only_in))
+            }
+            SchemaDifference::IndexDefinition { table, index, left: l, right: r } => {
+                format!("{}: key {} is {} in {}, {} in {}", table, index, l, left, r, right)
+            }
+            SchemaDifference::ForeignKey { table, foreign_key, only_in } => {
+                format!("{}: foreign key {} only in {}", table, foreign_key, side(only_in))
+            }
+            SchemaDifference::ForeignKeyDefinition { table, foreign_key, left: l, right: r } => {
+                format!("{}: foreign key {} references {} in {}, {} in {}", table, foreign_key, l, left, r, right)
+            }
+            SchemaDifference::Option { table, option, left: l, right: r } => {
+                format!("{}: {} is {} in {}, {} in {}", table, option, set(l), left, set(r), right)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe("left", "right"))
+    }
+}
+
+/// The differences between the tables of `left` and `right`, table by table
+/// in `left`'s order, then the tables only `right` has.
+///
+/// Columns are compared by name, case-insensitively as MySQL does, and by
+/// their definitions as written; keys and foreign keys by name. Options that
+/// change as rows are added, such as `AUTO_INCREMENT`, are left out.
+pub fn diff_schemas(left: &[TableSchema], right: &[TableSchema]) -> Vec<SchemaDifference> {
+    let rights: HashMap<&str, &TableSchema> = right.iter().map(|table| (table.name.as_str(), table)).collect();
+    let lefts: HashMap<&str, &TableSchema> = left.iter().map(|table| (table.name.as_str(), table)).collect();
+    let mut differences = Vec::new();
+    for table in left {
+        match rights.get(table.name.as_str()) {
+            Some(other) => diff_table(table, other, &mut differences),
+            None => differences.push(SchemaDifference::Table { table: table.name.clone(), only_in: Side::Left }),
+        }
+    }
+    for table in right.iter().filter(|table| !lefts.contains_key(table.name.as_str())) {
+        differences.push(SchemaDifference::Table { table: table.name.clone(), only_in: Side::Right });
+    }
+    differences
+}
+
+fn diff_table(left: &TableSchema, right: &TableSchema, differences: &mut Vec<SchemaDifference>) {
+    let table = || left.name.clone();
+    for column in &left.columns {
+        match right.column_index(&column.name).map(|index| &right.columns[index]) {
+            Some(other) if other.definition != column.definition => differences.push(SchemaDifference::ColumnDefinition {
+                table: table(),
+                column: column.name.clone(),
+                left: column.definition.clone(),
+                right: other.definition.clone(),
+            }),
+            Some(_) => {}
+            None => differences.push(SchemaDifference::Column {
+                table: table(),
+                column: column.name.clone(),
+                only_in: Side::Left,
+            }),
+        }
+    }
+    for column in right.columns.iter().filter(|column| left.column_index(&column.name).is_none()) {
+        differences.push(SchemaDifference::Column { table: table(), column: column.name.clone(), only_in: Side::Right });
+    }
+    // Only worth saying if the columns they share are out of order
+    let shared = |schema: &TableSchema, other: &TableSchema| -> Vec<String> {
+        schema
+            .columns
+            .iter()
+            .filter(|column| other.column_index(&column.name).is_some())
+            .map(|column| column.name.to_ascii_lowercase())
+            .collect()
+    };
+    let (left_order, right_order) = (shared(left, right), shared(right, left));
+    if left_order != right_order {
+        differences.push(SchemaDifference::ColumnOrder { table: table(), left: left_order, right: right_order });
+    }
+
+    let same_columns = |a: &[String], b: &[String]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b));
+    if !same_columns(&left.primary_key, &right.primary_key) {
+        differences.push(SchemaDifference::PrimaryKey {
+            table: table(),
+            left: left.primary_key.clone(),
+            right: right.primary_key.clone(),
+        });
+    }
+
+    for index in &left.indexes {
+        match right.indexes.iter().find(|other| other.name.eq_ignore_ascii_case(&index.name)) {
+            Some(other) if other.kind != index.kind || !same_columns(&other.columns, &index.columns) => {
+                differences.push(SchemaDifference::IndexDefinition {
+                    table: table(),
+                    index: index.name.clone(),
+                    left: describe_index(index),
+                    right: describe_index(other),
+                })
+            }
+            Some(_) => {}
+            None => differences.push(SchemaDifference::Index { table: table(), index: index.name.clone(), only_in: Side::Left }),
+        }
+    }
+    for index in right.indexes.iter().filter(|index| !left.indexes.iter().any(|other| other.name.eq_ignore_ascii_case(&index.name))) {
+        differences.push(SchemaDifference::Index { table: table(), index: index.name.clone(), only_in: Side::Right });
+    }
+
+    for foreign_key in &left.foreign_keys {
+        let name = foreign_key_name(foreign_key);
+        match right.foreign_keys.iter().find(|other| foreign_key_name(other) == name) {
+            Some(other) if describe_reference(other) != describe_reference(foreign_key) => {
+                differences.push(SchemaDifference::ForeignKeyDefinition {
+                    table: table(),
+                    foreign_key: name,
+                    left: describe_reference(foreign_key),
+                    right: describe_reference(other),
+                })
+            }
+            Some(_) => {}
+            None => differences.push(SchemaDifference::ForeignKey { table: table(), foreign_key: name, only_in: Side::Left }),
+        }
+    }
+    for foreign_key in &right.foreign_keys {
+        let name = foreign_key_name(foreign_key);
+        if !left.foreign_keys.iter().any(|other| foreign_key_name(other) == name) {
+            differences.push(SchemaDifference::ForeignKey { table: table(), foreign_key: name, only_in: Side::Right });
+        }
+    }
+
+    let mut options: Vec<&str> = Vec::new();
+    for option in left.options.iter().chain(&right.options) {
+        let name = option.name.as_str();
+        if VOLATILE_OPTIONS.contains(&name) || options.contains(&name) {
+            continue;
+        }
+        options.push(name);
+        let (l, r) = (left.option(name), right.option(name));
+        if l != r {
+            differences.push(SchemaDifference::Option {
+                table: table(),
+                option: name.to_string(),
+                left: l.map(str::to_string),
+                right: r.map(str::to_string),
+            });
+        }
+    }
+}
+
+/// An index's kind and columns, e.g. `UNIQUE (post_name, post_type)`.
+fn describe_index(index: &Index) -> String {
+    let kind = match index.kind {
+        IndexKind::Plain => "",
+        IndexKind::Unique => "UNIQUE ",
+        IndexKind::Fulltext => "FULLTEXT ",
+        IndexKind::Spatial => "SPATIAL ",
+    };
+    format!("{}({})", kind, index.columns.join(", "))
+}
+
+/// A foreign key's name, or its columns if it hasn't one.
+fn foreign_key_name(foreign_key: &ForeignKey) -> String {
+    match &foreign_key.name {
+        Some(name) => name.clone(),
+        None => format!("({})", foreign_key.columns.join(", ")),
+    }
+}
+
+/// What a foreign key's columns reference, e.g. `(post_id) -> wp_posts (ID)`.
+fn describe_reference(foreign_key: &ForeignKey) -> String {
+    let table = match &foreign_key.referenced_database {
+        Some(database) => format!("{}.{}", database, foreign_key.referenced_table),
+        None => foreign_key.referenced_table.clone(),
+    };
+    format!("({}) -> {} ({})", foreign_key.columns.join(", "), table, foreign_key.referenced_columns.join(", "))
+}
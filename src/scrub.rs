@@ -0,0 +1,630 @@
+//! Replacing the values of selected columns as rows are written out.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::anonymize::{Fake, PseudonymState, Pseudonyms};
+use crate::date::{is_date_type, shift, Interval, ShiftDates};
+use crate::insert::{insert_table, join_values, parse_insert, parse_value, quote_string, requote, split_values, Value};
+use crate::schema::{parse_create_table, Column, TableSchema};
+use crate::strict::earliest_date;
+use crate::rules::ColumnRule;
+use crate::search_replace::{replace_serialized, rewrite_serialized, Search};
+
+/// A `table.column=value` rule, replacing the column's value in every row.
+#[derive(Debug, Clone)]
+pub struct ScrubColumn {
+    pub table: String,
+    pub column: String,
+    pub replacement: Replacement,
+    /// Only scrub the rows this matches, rather than every row
+    pub rows: Option<RowMatch>,
+}
+
+/// The rows of a table whose `column` has one of `values`, such as the
+/// `wp_options` rows named `admin_email`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowMatch {
+    pub column: String,
+    pub values: Vec<String>,
+}
+
+impl RowMatch {
+    fn matches(&self, value: &[u8]) -> bool {
+        parse_value(value).as_bytes().is_some_and(|value| self.values.iter().any(|v| v.as_bytes() == value))
+    }
+}
+
+/// What a scrubbed column's values are replaced with.
+#[derive(Debug, Clone)]
+pub enum Replacement {
+    Null,
+    /// A constant, written as a string literal. NULLs stay NULL.
+    Constant(String),
+    /// A fake value, the same for each occurrence of the same original value.
+    /// NULLs stay NULL.
+    Fake(Fake),
+    /// The hex of a keyed hash of the value, the same for each occurrence of
+    /// the same value. NULLs stay NULL. In an integer column, it's
+    /// [`HashNumber`](Self::HashNumber) instead.
+    Hash,
+    /// A keyed hash of the value as a number from 0 up to this, the largest
+    /// the integer column takes, the same for each occurrence of the same
+    /// value. NULLs stay NULL.
+    HashNumber(u64),
+    /// The first this many characters of strings. Other values are kept.
+    Truncate(usize),
+    /// Strings with the regex's matches replaced, fixing up PHP-serialized
+    /// values as search-replace does. Other values are kept.
+    RegexReplace { regex: regex::bytes::Regex, replacement: String },
+}
+
+impl Replacement {
+    /// The SQL literal to write in place of `value`, as written in the dump.
+    fn literal(&self, value: &[u8], column: &str, pseudonyms: &mut Pseudonyms) -> Vec<u8> {
+        match (self, parse_value(value)) {
+            (Replacement::Null, _) => b"NULL".to_vec(),
+            (Replacement::Constant(_) | Replacement::Hash | Replacement::HashNumber(_), Value::Null) => value.to_vec(),
+            (Replacement::Constant(constant), _) => quote_string(constant.as_bytes()),
+            (Replacement::Fake(fake), _) => fake.literal(value, column, pseudonyms),
+            (Replacement::Hash, original) => {
+                let original = original.as_bytes().expect("NULLs are kept");
+                let hash: String = pseudonyms.hash("hash", original).iter().map(|b| format!("{:02x}", b)).collect();
+                quote_string(hash.as_bytes())
+            }
+            (Replacement::HashNumber(max), original) => {
+                let original = original.as_bytes().expect("NULLs are kept");
+                let hash = pseudonyms.hash("hash", original);
+                let number = u64::from_le_bytes(hash[..8].try_into().unwrap());
+                let number = match max.checked_add(1) {
+                    Some(numbers) => number % numbers,
+                    None => number,
+                };
+                number.to_string().into_bytes()
+            }
+            (Replacement::Truncate(length), Value::String(bytes)) => {
+                // Binary strings are cut by bytes
+                let end = match std::str::from_utf8(&bytes) {
+                    Ok(text) => text.char_indices().nth(*length).map(|(end, _)| end),
+                    Err(_) => (bytes.len() > *length).then_some(*length),
+                };
+                match end {
+                    Some(end) => requote(value, &bytes[..end]),
+                    None => value.to_vec(),
+                }
+            }
+            (Replacement::RegexReplace { regex, replacement }, Value::String(bytes)) => {
+                let search = Search::Regex(regex.clone());
+                match replace_serialized(&bytes, &search, replacement.as_bytes(), &mut 0) {
+                    Cow::Owned(replaced) => requote(value, &replaced),
+                    Cow::Borrowed(_) => value.to_vec(),
+                }
+            }
+            (Replacement::Truncate(_) | Replacement::RegexReplace { .. }, _) => value.to_vec(),
+        }
+    }
+
+    /// The replacement's name, as rules files and reports call it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Replacement::Null => "null",
+            Replacement::Constant(_) => "constant",
+            Replacement::Fake(fake) => fake.name(),
+            Replacement::Hash | Replacement::HashNumber(_) => "hash",
+            Replacement::Truncate(_) => "truncate",
+            Replacement::RegexReplace { .. } => "regex-replace",
+        }
+    }
+}
+
+impl FromStr for ScrubColumn {
+    type Err = String;
+
+    /// Parse `table.column=value`, where a value of `NULL` writes `NULL`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected table.column=value, got `{}`", s);
+        let (target, value) = s.split_once('=').ok_or_else(error)?;
+        let (table, column) = match target.trim().split_once('.') {
+            Some((table, column)) if !table.is_empty() && !column.is_empty() => (table, column),
+            _ => return Err(error()),
+        };
+        let replacement = match value {
+            "NULL" => Replacement::Null,
+            value => Replacement::Constant(value.to_string()),
+        };
+        Ok(ScrubColumn { table: table.to_string(), column: column.to_string(), replacement, rows: None })
+    }
+}
+
+/// How to replace the IP addresses found in the dump's values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubIps {
+    /// Zero the host part: the last octet of an IPv4 address, and all but the
+    /// first 48 bits of an IPv6 address
+    Mask,
+    /// Replace with `0.0.0.0` or `::`
+    Zero,
+    /// Replace with a fake private address (in `10.0.0.0/8` or `fd00::/8`),
+    /// the same for each occurrence of the same address
+    Hash,
+}
+
+impl FromStr for ScrubIps {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mask" => Ok(ScrubIps::Mask),
+            "zero" => Ok(ScrubIps::Zero),
+            "hash" => Ok(ScrubIps::Hash),
+            _ => Err(format!("unknown IP scrubbing `{}`, expected mask, zero or hash", s)),
+        }
+    }
+}
+
+/// How `--redact-columns` replaces the values of the columns it finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redact {
+    /// Replace with an empty string
+    Blank,
+    /// Replace with the hex of a keyed hash, the same for each occurrence of
+    /// the same value
+    Hash,
+}
+
+/// The types whose values are strings, and are blanked or hashed
+const STRING_TYPES: &[&str] = &[
+    "char", "varchar", "tinytext", "text", "mediumtext", "longtext", "binary", "varbinary", "tinyblob", "blob",
+    "mediumblob", "longblob", "set",
+];
+
+/// The types whose values are numbers, and are zeroed
+const NUMBER_TYPES: &[&str] = &[
+    "tinyint", "smallint", "mediumint", "int", "integer", "bigint", "serial", "decimal", "dec", "numeric", "fixed",
+    "float", "double", "real", "bool", "boolean", "year",
+];
+
+impl Redact {
+    /// The replacement for a string column's values.
+    pub fn replacement(&self) -> Replacement {
+        match self {
+            Redact::Blank => Replacement::Constant(String::new()),
+            Redact::Hash => Replacement::Hash,
+        }
+    }
+
+    /// The replacement for `column`'s values, so its rows still load in
+    /// strict mode: [`replacement`](Self::replacement) for a string, and
+    /// otherwise `NULL`, or if the column doesn't allow it, zero, the earliest
+    /// date, an enum's first value or JSON's `null`. Columns of other types
+    /// that don't allow `NULL`, such as spatial ones, are left as they are.
+    pub fn replacement_for(&self, column: &Column) -> Option<Replacement> {
+        let data_type = column.data_type.to_ascii_lowercase();
+        let base = data_type.split(['(', ' ']).next().unwrap_or_default();
+        if STRING_TYPES.contains(&base) {
+            return Some(self.replacement());
+        }
+        if column.nullable {
+            return Some(Replacement::Null);
+        }
+        let zero = match base {
+            _ if NUMBER_TYPES.contains(&base) => "0".to_string(),
+            // A BIT column takes its bits from the bytes of a string
+            "bit" => "\0".to_string(),
+            "date" | "datetime" | "timestamp" => earliest_date(base).to_string(),
+            "time" => "00:00:00".to_string(),
+            "json" => "null".to_string(),
+            "enum" => {
+                let values = split_values(&column.data_type.as_bytes()[column.data_type.find('(')?..]);
+                String::from_utf8(parse_value(values.first()?).as_bytes()?.to_vec()).ok()?
+            }
+            _ => return None,
+        };
+        Some(Replacement::Constant(zero))
+    }
+}
+
+/// The largest value of an integer column, if it's one.
+fn integer_max(column: &Column) -> Option<u64> {
+    let data_type = column.data_type.to_ascii_lowercase();
+    let base = data_type.split(['(', ' ']).next().unwrap_or_default();
+    let unsigned = data_type.contains("unsigned");
+    let bits = match base {
+        "tinyint" => 8,
+        "smallint" => 16,
+        "mediumint" => 24,
+        "int" | "integer" => 32,
+        "bigint" => 64,
+        "serial" => return Some(u64::MAX),
+        _ => return None,
+    };
+    Some(if unsigned { u64::MAX >> (64 - bits) } else { u64::MAX >> (65 - bits) })
+}
+
+impl FromStr for Redact {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blank" => Ok(Redact::Blank),
+            "hash" => Ok(Redact::Hash),
+            _ => Err(format!("unknown redaction `{}`, expected blank or hash", s)),
+        }
+    }
+}
+
+impl ScrubIps {
+    fn replace(&self, ip: IpAddr, pseudonyms: &Pseudonyms) -> IpAddr {
+        // IPv4 addresses mapped into IPv6 are scrubbed as IPv4, keeping the mapping
+        if let IpAddr::V6(v6) = ip {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return match self.replace(IpAddr::V4(v4), pseudonyms) {
+                    IpAddr::V4(v4) => IpAddr::V6(v4.to_ipv6_mapped()),
+                    v6 => v6,
+                };
+            }
+        }
+        match (self, ip) {
+            (ScrubIps::Mask, IpAddr::V4(v4)) => {
+                let [a, b, c, _] = v4.octets();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+            }
+            (ScrubIps::Mask, IpAddr::V6(v6)) => {
+                let [a, b, c, ..] = v6.segments();
+                IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+            }
+            (ScrubIps::Zero, IpAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (ScrubIps::Zero, IpAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            (ScrubIps::Hash, ip) => {
+                let mut rng = pseudonyms.rng("scrub-ips", ip.to_string().as_bytes());
+                match ip {
+                    IpAddr::V4(_) => {
+                        let [_, b, c, d] = (rng.next_u64() as u32).to_be_bytes();
+                        IpAddr::V4(Ipv4Addr::new(10, b, c, d))
+                    }
+                    IpAddr::V6(_) => {
+                        let bits = (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64());
+                        IpAddr::V6(Ipv6Addr::from((bits >> 8) | (0xfd << 120)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// `text` with each IP address in it replaced, or `None` if it has none.
+    /// An address counts if it's a whole word, or an IPv4 address with a
+    /// `:port` after it.
+    fn rewrite(&self, text: &[u8], pseudonyms: &Pseudonyms) -> Option<Vec<u8>> {
+        let is_ip_byte = |b: u8| b.is_ascii_hexdigit() || b == b'.' || b == b':';
+        let is_word_byte = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        let mut rewritten = Vec::new();
+        let mut position = 0;
+        let mut i = 0;
+        while i < text.len() {
+            if !is_ip_byte(text[i]) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            i += text[i..].iter().take_while(|&&b| is_ip_byte(b)).count();
+            if start > 0 && is_word_byte(text[start - 1]) || text.get(i).is_some_and(|&b| is_word_byte(b)) {
+                continue;
+            }
+            // Punctuation ending a sentence isn't part of the address
+            let token = &text[start..i];
+            let token = &token[..token.len() - token.iter().rev().take_while(|&&b| b == b'.' || b == b':').count()];
+            let Ok(token) = std::str::from_utf8(token) else { continue };
+            let ip = token.parse::<IpAddr>().ok().map(|ip| (ip, token.len())).or_else(|| {
+                let (host, port) = token.split_once(':')?;
+                (!port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+                    .then(|| host.parse::<Ipv4Addr>().ok())
+                    .flatten()
+                    .map(|v4| (IpAddr::V4(v4), host.len()))
+            });
+            if let Some((ip, length)) = ip {
+                rewritten.extend_from_slice(&text[position..start]);
+                rewritten.extend_from_slice(self.replace(ip, pseudonyms).to_string().as_bytes());
+                position = start + length;
+            }
+        }
+        if position == 0 {
+            return None;
+        }
+        rewritten.extend_from_slice(&text[position..]);
+        Some(rewritten)
+    }
+}
+
+/// The columns to scrub, and what's been learned of their tables' schemas.
+#[derive(Debug, Clone, Default)]
+pub struct Scrubs {
+    rules: Vec<ScrubColumn>,
+    /// Rules by pattern, and whether each has matched a column yet
+    patterns: Vec<(ColumnRule, bool)>,
+    /// The rules for the columns `patterns` matched
+    patterned: Vec<ScrubColumn>,
+    /// Column names of each scrubbed table, from its `CREATE TABLE`
+    columns: HashMap<String, Vec<String>>,
+    /// Tables whose rows have been scrubbed
+    seen: HashSet<String>,
+    pseudonyms: Option<Pseudonyms>,
+    ips: Option<ScrubIps>,
+    shifts: Vec<ShiftDates>,
+    /// The date columns of each table with dates to shift, and their shifts
+    date_columns: HashMap<String, Vec<(String, Interval)>>,
+}
+
+impl Scrubs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, rule: ScrubColumn) {
+        self.rules.push(rule);
+    }
+
+    /// Also scrub the columns `rule` matches, in each table once its
+    /// `CREATE TABLE` is seen. A column matched by several rules gets each in
+    /// turn, in the order they were added.
+    pub fn insert_pattern(&mut self, rule: ColumnRule) {
+        self.patterns.push((rule, false));
+    }
+
+    /// Also replace IP addresses in every string value, in every table.
+    pub fn scrub_ips(&mut self, mode: ScrubIps) {
+        self.ips = Some(mode);
+    }
+
+    /// Also shift the dates in the date columns `rule` targets.
+    pub fn shift_dates(&mut self, rule: ShiftDates) {
+        self.shifts.push(rule);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.patterns.is_empty() && self.ips.is_none() && self.shifts.is_empty()
+    }
+
+    /// Pick fakes with `pseudonyms`, rather than with a random key that lasts
+    /// for this run.
+    pub fn set_pseudonyms(&mut self, pseudonyms: Pseudonyms) {
+        self.pseudonyms = Some(pseudonyms);
+    }
+
+    pub fn pseudonyms(&self) -> Option<&Pseudonyms> {
+        self.pseudonyms.as_ref()
+    }
+
+    /// The state of the pseudonyms, if any fakes, hashes or random shifts
+    /// have been picked yet.
+    pub(crate) fn pseudonym_state(&self) -> Option<PseudonymState> {
+        self.pseudonyms.as_ref().map(Pseudonyms::state)
+    }
+
+    /// Carry on picking with the pseudonyms of `state`.
+    pub(crate) fn resume_pseudonyms(&mut self, state: PseudonymState) {
+        self.pseudonyms.get_or_insert_with(Pseudonyms::random).resume(state);
+    }
+
+    /// The rules for tables that had no rows in the dump, which might be typos.
+    pub fn unmatched(&self) -> impl Iterator<Item = &ScrubColumn> {
+        self.rules.iter().filter(|rule| !self.seen.contains(&rule.table))
+    }
+
+    /// The rules by pattern that matched no column.
+    pub fn unmatched_patterns(&self) -> impl Iterator<Item = &ColumnRule> {
+        self.patterns.iter().filter(|(_, matched)| !matched).map(|(rule, _)| rule)
+    }
+
+    /// Replace the scrubbed columns' values in an `INSERT`, and learn the
+    /// column order (and date columns) of scrubbed tables from their
+    /// `CREATE TABLE`.
+    ///
+    /// Fails if a scrubbed column isn't in its table, or a table's rows come
+    /// before its `CREATE TABLE` without listing their columns.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        if self.is_empty() {
+            return Ok(Cow::Borrowed(text));
+        }
+
+        if let Some(schema) = parse_create_table(text) {
+            // Once per table, as its name may come up again
+            if !self.columns.contains_key(&schema.name) {
+                for (pattern, matched) in &mut self.patterns {
+                    let rows_column = pattern.rows.as_ref().map(|rows| rows.column.as_str());
+                    if rows_column.is_some_and(|column| schema.column_index(column).is_none()) {
+                        continue;
+                    }
+                    for column in &schema.columns {
+                        if let Some(mut rule) = pattern.for_column(&schema.name, &column.name) {
+                            *matched = true;
+                            if let Some(redact) = pattern.redact {
+                                match redact.replacement_for(column) {
+                                    Some(replacement) => rule.replacement = replacement,
+                                    None => continue,
+                                }
+                            }
+                            self.patterned.push(rule);
+                        }
+                    }
+                }
+            }
+            // Hashes of integers are numbers that fit the column
+            for rule in self.rules.iter_mut().chain(&mut self.patterned) {
+                if rule.table != schema.name || !matches!(rule.replacement, Replacement::Hash) {
+                    continue;
+                }
+                if let Some(max) = schema.column_index(&rule.column).and_then(|i| integer_max(&schema.columns[i])) {
+                    rule.replacement = Replacement::HashNumber(max);
+                }
+            }
+            let ruled = self.rules.iter().chain(&self.patterned).any(|rule| rule.table == schema.name);
+            if ruled {
+                if let Some(missing) = self
+                    .rules
+                    .iter()
+                    .chain(&self.patterned)
+                    .filter(|rule| rule.table == schema.name)
+                    .flat_map(|rule| std::iter::once(&rule.column).chain(rule.rows.as_ref().map(|rows| &rows.column)))
+                    .find(|column| schema.column_index(column).is_none())
+                {
+                    return Err(invalid_input(format!("table `{}` has no column `{}`", schema.name, missing)));
+                }
+            }
+            let dates = self.date_shifts(&schema);
+            if ruled || !dates.is_empty() {
+                let columns = schema.columns.into_iter().map(|column| column.name).collect();
+                self.columns.insert(schema.name.clone(), columns);
+            }
+            if !dates.is_empty() {
+                self.date_columns.insert(schema.name, dates);
+            }
+            return Ok(Cow::Borrowed(text));
+        }
+
+        let Some(table) = insert_table(text) else { return Ok(Cow::Borrowed(text)) };
+        let ruled = self.rules.iter().chain(&self.patterned).any(|rule| rule.table == table);
+        let dates = self.date_columns.get(&table);
+        if !ruled && dates.is_none() && self.ips.is_none() {
+            return Ok(Cow::Borrowed(text));
+        }
+        let Some(insert) = parse_insert(text) else { return Ok(Cow::Borrowed(text)) };
+
+        // Columns may be left out of a column list, and then have no dates to shift
+        let mut shifts = Vec::new();
+        if let (Some(dates), Some(columns)) = (dates, insert.columns.as_ref().or(self.columns.get(&table))) {
+            for (column, interval) in dates {
+                if let Some(index) = columns.iter().position(|c| c.eq_ignore_ascii_case(column)) {
+                    shifts.push((index, *interval));
+                }
+            }
+        }
+
+        let mut replacements = Vec::new();
+        if ruled {
+            let columns = match (&insert.columns, self.columns.get(&table)) {
+                (Some(columns), _) | (None, Some(columns)) => columns,
+                (None, None) => {
+                    return Err(invalid_input(format!("found rows for `{}` before its CREATE TABLE", table)));
+                }
+            };
+            let position = |column: &str| {
+                columns.iter().position(|c| c.eq_ignore_ascii_case(column)).ok_or_else(|| {
+                    invalid_input(format!("INSERT into `{}` does not include column `{}`", table, column))
+                })
+            };
+            for rule in self.rules.iter().chain(&self.patterned).filter(|rule| rule.table == table) {
+                let rows = match &rule.rows {
+                    Some(rows) => Some((position(&rows.column)?, rows)),
+                    None => None,
+                };
+                replacements.push((position(&rule.column)?, rows, &rule.replacement, format!("{}.{}", table, rule.column)));
+            }
+        }
+
+        let pseudonyms = self.pseudonyms.get_or_insert_with(Pseudonyms::random);
+        let mut changed = ruled || !shifts.is_empty();
+        let tuples: Vec<Vec<u8>> = insert
+            .tuples
+            .iter()
+            .map(|tuple| {
+                let mut values: Vec<Cow<[u8]>> = split_values(tuple).into_iter().map(Cow::Borrowed).collect();
+                for (index, rows, replacement, column) in &replacements {
+                    let matched = rows.is_none_or(|(at, rows)| values.get(at).is_some_and(|value| rows.matches(value)));
+                    if let (true, Some(value)) = (matched, values.get_mut(*index)) {
+                        *value = Cow::Owned(replacement.literal(value, column, pseudonyms));
+                    }
+                }
+                for (index, interval) in &shifts {
+                    let Some(value) = values.get_mut(*index) else { continue };
+                    let Value::String(date) = parse_value(value) else { continue };
+                    if let Some(shifted) = shift(&date, *interval) {
+                        *value = Cow::Owned(requote(value, shifted.as_bytes()));
+                    }
+                }
+                if let Some(ips) = self.ips {
+                    for value in &mut values {
+                        let Value::String(bytes) = parse_value(value) else { continue };
+                        if let Cow::Owned(scrubbed) = rewrite_serialized(&bytes, &mut |string| ips.rewrite(string, pseudonyms)) {
+                            *value = Cow::Owned(requote(value, &scrubbed));
+                            changed = true;
+                        }
+                    }
+                }
+                join_values(values.iter().map(|value| &value[..]))
+            })
+            .collect();
+        if !changed {
+            return Ok(Cow::Borrowed(text));
+        }
+        let rewritten = insert.with_tuples(tuples.iter().map(Vec::as_slice));
+        if ruled {
+            self.seen.insert(table);
+        }
+        Ok(Cow::Owned(rewritten))
+    }
+
+    /// The shift for each of `schema`'s date columns that a rule targets,
+    /// picking random shifts as they're needed.
+    fn date_shifts(&mut self, schema: &TableSchema) -> Vec<(String, Interval)> {
+        let mut dates = Vec::new();
+        for column in schema.columns.iter().filter(|column| is_date_type(&column.definition)) {
+            let Some(rule) = self
+                .shifts
+                .iter()
+                .filter_map(|rule| rule.specificity(&schema.name, &column.name).map(|specificity| (specificity, rule)))
+                .max_by_key(|(specificity, _)| *specificity)
+                .map(|(_, rule)| rule)
+            else {
+                continue;
+            };
+            let interval = if rule.random {
+                // The same rule always picks the same shift, so its dates keep their order
+                let scope = format!("{}.{}", rule.table.as_deref().unwrap_or(""), rule.column.as_deref().unwrap_or(""));
+                let pseudonyms = self.pseudonyms.get_or_insert_with(Pseudonyms::random);
+                let bound = rule.interval.approximate_seconds().unsigned_abs();
+                let offset = pseudonyms.rng("shift-dates", scope.as_bytes()).below(bound.saturating_mul(2).saturating_add(1));
+                Interval { months: 0, seconds: (offset as i64).wrapping_sub(bound as i64) }
+            } else {
+                rule.interval
+            };
+            dates.push((column.name.clone(), interval));
+        }
+        dates
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::ColumnRule;
+
+    #[test]
+    fn hashes_fit_the_column() {
+        let mut scrubs = Scrubs::new();
+        scrubs.insert_pattern(ColumnRule::new(Some("t"), "small|big|name", Replacement::Hash, None).unwrap());
+        let create = b"CREATE TABLE `t` (\n  `small` tinyint unsigned,\n  `big` bigint NOT NULL,\n  `name` varchar(64)\n);";
+        scrubs.rewrite(create).unwrap();
+        let insert = scrubs.rewrite(b"INSERT INTO `t` VALUES (7,123456789,'someone'),(NULL,5,NULL);").unwrap();
+        let insert = parse_insert(&insert).unwrap();
+
+        let row = split_values(insert.tuples[0]);
+        let small: u64 = std::str::from_utf8(row[0]).unwrap().parse().unwrap();
+        assert!(small <= 255);
+        let big: i64 = std::str::from_utf8(row[1]).unwrap().parse().unwrap();
+        assert!(big >= 0);
+        let Value::String(name) = parse_value(row[2]) else { panic!("a hash of a string is a string") };
+        assert_eq!(name.len(), 64);
+        assert!(name.iter().all(u8::is_ascii_hexdigit));
+
+        let row = split_values(insert.tuples[1]);
+        assert_eq!(row[0], b"NULL");
+        assert_eq!(row[2], b"NULL");
+    }
+}
@@ -0,0 +1,473 @@
+//! Replacing text in a dump's data, keeping PHP-serialized values intact.
+
+use std::borrow::Cow;
+use std::io::{self, BufRead, Write};
+
+use regex::bytes::Regex;
+
+use crate::dump::{find, StatementReader};
+use crate::insert::{join_values, parse_insert, parse_value, quote_string, requote, split_values, Value};
+
+/// How deeply serialized arrays and objects may nest before a value is taken
+/// not to be serialized, so a hostile value can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// What to look for in the dump's values.
+#[derive(Debug, Clone)]
+pub enum Search {
+    Text(Vec<u8>),
+    /// A regex, whose replacement can refer to its groups as `$1` or `${name}`
+    Regex(Regex),
+}
+
+impl Search {
+    fn is_match(&self, text: &[u8]) -> bool {
+        match self {
+            Search::Text(search) => find(text, search).is_some(),
+            Search::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// `text` with every match replaced, adding the number of matches to
+    /// `count`.
+    fn replace_all(&self, text: &[u8], replace: &[u8], count: &mut u64) -> Vec<u8> {
+        let mut replaced = Vec::with_capacity(text.len());
+        match self {
+            Search::Text(search) => {
+                let mut position = 0;
+                while let Some(at) = find(&text[position..], search) {
+                    replaced.extend_from_slice(&text[position..position + at]);
+                    replaced.extend_from_slice(replace);
+                    position += at + search.len();
+                    *count += 1;
+                }
+                replaced.extend_from_slice(&text[position..]);
+            }
+            Search::Regex(regex) => {
+                let mut position = 0;
+                for captures in regex.captures_iter(text) {
+                    let whole = captures.get(0).expect("group 0 is the whole match");
+                    replaced.extend_from_slice(&text[position..whole.start()]);
+                    captures.expand(replace, &mut replaced);
+                    position = whole.end();
+                    *count += 1;
+                }
+                replaced.extend_from_slice(&text[position..]);
+            }
+        }
+        replaced
+    }
+}
+
+/// The number of replacements made in one table's rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableReplacements {
+    pub name: String,
+    pub replacements: u64,
+}
+
+/// Copy `input` to `output`, replacing matches of `search` with `replace` in
+/// the string values of every `INSERT`. Table and column names, keywords, and
+/// everything outside the rows are left alone.
+///
+/// Values holding PHP-serialized data (as WordPress stores options and
+/// metadata) have the replacement made in each of their strings, with the
+/// strings' length prefixes updated to match. Returns the number of
+/// replacements made in each table, in the order the tables came in.
+pub fn search_replace_dump<R: BufRead, W: Write>(
+    input: R,
+    search: &Search,
+    replace: &[u8],
+    output: &mut W,
+) -> io::Result<Vec<TableReplacements>> {
+    // Text that mysqldump escapes can't be looked for in the statement as is,
+    // and neither can a regex's matches
+    let prefilter = match search {
+        Search::Text(text) if text.is_empty() => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the text to search for is empty"));
+        }
+        Search::Text(text) => quote_string(text).len() == text.len() + 2,
+        Search::Regex(_) => false,
+    };
+
+    let mut tables: Vec<TableReplacements> = Vec::new();
+    for statement in StatementReader::new(input) {
+        let statement = statement?;
+        let text = &statement.text;
+        if prefilter && !search.is_match(text) {
+            output.write_all(text)?;
+            continue;
+        }
+        let mut count = 0;
+        match rewrite_insert(text, search, replace, &mut count) {
+            Some((table, rewritten)) => {
+                output.write_all(&rewritten)?;
+                match tables.iter_mut().find(|t| t.name == table) {
+                    Some(t) => t.replacements += count,
+                    None => tables.push(TableReplacements { name: table, replacements: count }),
+                }
+            }
+            None => output.write_all(text)?,
+        }
+    }
+    output.flush()?;
+    Ok(tables)
+}
+
+/// The `INSERT`'s table and the statement with the replacements made in its
+/// rows, or `None` if it isn't an `INSERT` or nothing was replaced.
+fn rewrite_insert(text: &[u8], search: &Search, replace: &[u8], count: &mut u64) -> Option<(String, Vec<u8>)> {
+    let insert = parse_insert(text)?;
+    let mut changed = false;
+    let tuples: Vec<Cow<[u8]>> = insert
+        .tuples
+        .iter()
+        .map(|tuple| {
+            let mut values: Vec<Cow<[u8]>> = split_values(tuple).into_iter().map(Cow::Borrowed).collect();
+            let mut tuple_changed = false;
+            for value in &mut values {
+                let Value::String(bytes) = parse_value(value) else { continue };
+                if let Cow::Owned(replaced) = replace_value(&bytes, search, replace, count) {
+                    *value = Cow::Owned(requote(value, &replaced));
+                    tuple_changed = true;
+                }
+            }
+            if !tuple_changed {
+                return Cow::Borrowed(*tuple);
+            }
+            changed = true;
+            Cow::Owned(join_values(values.iter().map(|value| &value[..])))
+        })
+        .collect();
+    changed.then(|| (insert.table.clone(), insert.with_tuples(tuples.iter().map(|tuple| &tuple[..]))))
+}
+
+/// Replace the matches in one of a row's values as [`replace_serialized`]
+/// does, except that a value that starts like serialized data but is too
+/// broken (say, truncated) or deeply nested to parse is left as it is, rather
+/// than broken further by a replacement changing its length.
+fn replace_value<'a>(text: &'a [u8], search: &Search, replace: &[u8], count: &mut u64) -> Cow<'a, [u8]> {
+    if looks_serialized(text) && !is_serialized(text) {
+        return Cow::Borrowed(text);
+    }
+    replace_serialized(text, search, replace, count)
+}
+
+/// Replace every match of `search` in `text` with `replace`, adding the
+/// number of replacements to `count`, fixing up serialized data as
+/// [`rewrite_serialized`] does.
+pub fn replace_serialized<'a>(text: &'a [u8], search: &Search, replace: &[u8], count: &mut u64) -> Cow<'a, [u8]> {
+    if !search.is_match(text) {
+        return Cow::Borrowed(text);
+    }
+    rewrite_serialized(text, &mut |string| search.is_match(string).then(|| search.replace_all(string, replace, count)))
+}
+
+/// Rewrite `text` with `rewrite`, which returns `None` to leave a string as
+/// it is.
+///
+/// If `text` is a PHP-serialized value, each of its strings (and those of
+/// serialized values nested in them) is rewritten instead, and the strings'
+/// `s:N:` length prefixes updated to their new lengths. Anything else, and
+/// serialized data too broken to parse, is rewritten whole. The data of
+/// objects with their own serialization (`C:`) is left as is, as its format
+/// is up to the class.
+pub fn rewrite_serialized<'a>(text: &'a [u8], rewrite: &mut dyn FnMut(&[u8]) -> Option<Vec<u8>>) -> Cow<'a, [u8]> {
+    // Check it parses before rewriting anything, so nothing is rewritten twice
+    if !is_serialized(text) {
+        return rewrite(text).map_or(Cow::Borrowed(text), Cow::Owned);
+    }
+
+    let mut parser = Serialized { text, position: 0, rewrite: Some(rewrite), changed: false };
+    let mut rewritten = Vec::with_capacity(text.len());
+    parser.value(&mut rewritten, 0).expect("the value was checked");
+    if parser.changed {
+        Cow::Owned(rewritten)
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn is_serialized(text: &[u8]) -> bool {
+    let mut check = Serialized { text, position: 0, rewrite: None, changed: false };
+    check.value(&mut Vec::new(), 0).is_some() && check.position == text.len()
+}
+
+/// Whether `text` starts like a serialized array, object or string, with
+/// `a:N:`, `O:N:`, `C:N:` or `s:N:`, as plain text is unlikely to.
+fn looks_serialized(text: &[u8]) -> bool {
+    let [b'a' | b'O' | b'C' | b's', b':', rest @ ..] = text else { return false };
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    digits > 0 && rest.get(digits) == Some(&b':')
+}
+
+type Rewrite<'r> = &'r mut dyn FnMut(&[u8]) -> Option<Vec<u8>>;
+
+/// A parser for PHP's `serialize()` format, copying the value out with its
+/// strings rewritten as it goes.
+struct Serialized<'a, 'r> {
+    text: &'a [u8],
+    position: usize,
+    /// `None` to only check the value parses
+    rewrite: Option<Rewrite<'r>>,
+    changed: bool,
+}
+
+impl<'a> Serialized<'a, '_> {
+    /// Copy one value to `out`. `None` if the text isn't a serialized value.
+    fn value(&mut self, out: &mut Vec<u8>, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let kind = *self.text.get(self.position)?;
+        match kind {
+            b'N' => self.copy(b"N;", out),
+            // Booleans, numbers and references
+            b'b' | b'i' | b'd' | b'r' | b'R' => {
+                self.copy(&[kind, b':'], out)?;
+                let end = self.position + self.text[self.position..].iter().position(|&b| b == b';')?;
+                out.extend_from_slice(&self.text[self.position..=end]);
+                self.position = end + 1;
+                Some(())
+            }
+            b's' => {
+                self.expect(&[kind, b':'])?;
+                let string = self.string()?;
+                self.expect(b";")?;
+                let string = match &mut self.rewrite {
+                    Some(rewrite) => rewrite_serialized(string, &mut **rewrite),
+                    None => Cow::Borrowed(string),
+                };
+                self.changed |= matches!(string, Cow::Owned(_));
+                out.extend_from_slice(format!("s:{}:\"", string.len()).as_bytes());
+                out.extend_from_slice(&string);
+                out.extend_from_slice(b"\";");
+                Some(())
+            }
+            // Enum cases, whose names are code rather than data
+            b'E' => {
+                let start = self.position;
+                self.expect(&[kind, b':'])?;
+                self.string()?;
+                self.expect(b";")?;
+                out.extend_from_slice(&self.text[start..self.position]);
+                Some(())
+            }
+            b'a' => {
+                self.copy(b"a:", out)?;
+                let length = self.length(out)?;
+                self.copy(b":{", out)?;
+                self.pairs(length, out, depth)
+            }
+            b'O' => {
+                let start = self.position;
+                self.expect(&[kind, b':'])?;
+                self.string()?;
+                self.expect(b":")?;
+                out.extend_from_slice(&self.text[start..self.position]);
+                let length = self.length(out)?;
+                self.copy(b":{", out)?;
+                self.pairs(length, out, depth)
+            }
+            b'C' => {
+                let start = self.position;
+                self.expect(&[kind, b':'])?;
+                self.string()?;
+                self.expect(b":")?;
+                let length = self.number()?;
+                self.expect(b":{")?;
+                self.position = self.position.checked_add(length).filter(|&end| end <= self.text.len())?;
+                self.expect(b"}")?;
+                out.extend_from_slice(&self.text[start..self.position]);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    /// Copy `length` keys and values, and the closing brace.
+    fn pairs(&mut self, length: usize, out: &mut Vec<u8>, depth: usize) -> Option<()> {
+        for _ in 0..length {
+            self.value(out, depth + 1)?;
+            self.value(out, depth + 1)?;
+        }
+        self.copy(b"}", out)
+    }
+
+    /// Parse `N:"..."` and return the string, of N bytes.
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let length = self.number()?;
+        self.expect(b":\"")?;
+        let start = self.position;
+        let end = start.checked_add(length).filter(|&end| end <= self.text.len())?;
+        self.position = end;
+        self.expect(b"\"")?;
+        Some(&self.text[start..end])
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let digits = self.text[self.position..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let number = std::str::from_utf8(&self.text[self.position..self.position + digits]).ok()?.parse().ok()?;
+        self.position += digits;
+        Some(number)
+    }
+
+    /// Copy an array's or object's number of elements.
+    fn length(&mut self, out: &mut Vec<u8>) -> Option<usize> {
+        let start = self.position;
+        let length = self.number()?;
+        out.extend_from_slice(&self.text[start..self.position]);
+        Some(length)
+    }
+
+    fn expect(&mut self, expected: &[u8]) -> Option<()> {
+        let rest = self.text[self.position..].strip_prefix(expected)?;
+        self.position = self.text.len() - rest.len();
+        Some(())
+    }
+
+    fn copy(&mut self, expected: &[u8], out: &mut Vec<u8>) -> Option<()> {
+        self.expect(expected)?;
+        out.extend_from_slice(expected);
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(text: &str) -> String {
+        let search = Search::Text(b"old.example.com".to_vec());
+        let mut count = 0;
+        let replaced = replace_value(text.as_bytes(), &search, "new.example.org".as_bytes(), &mut count);
+        String::from_utf8(replaced.into_owned()).unwrap()
+    }
+
+    /// `s:N:"text";`, with N its length in bytes
+    fn string(text: &str) -> String {
+        format!("s:{}:\"{}\";", text.len(), text)
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(replace("see http://old.example.com/"), "see http://new.example.org/");
+        assert_eq!(replace("a: old.example.com"), "a: new.example.org");
+        assert_eq!(replace("nothing to replace"), "nothing to replace");
+    }
+
+    #[test]
+    fn string_lengths() {
+        assert_eq!(replace(&string("http://old.example.com")), string("http://new.example.org"));
+        assert_eq!(
+            replace(r#"s:32:"old.example.com, old.example.com";"#),
+            r#"s:32:"new.example.org, new.example.org";"#
+        );
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let text = format!(
+            "a:2:{{{}a:1:{{{}{}}}i:0;a:0:{{}}}}",
+            string("site"),
+            string("url"),
+            string("http://old.example.com/wp")
+        );
+        let expected = format!(
+            "a:2:{{{}a:1:{{{}{}}}i:0;a:0:{{}}}}",
+            string("site"),
+            string("url"),
+            string("http://new.example.org/wp")
+        );
+        assert_eq!(replace(&text), expected);
+    }
+
+    #[test]
+    fn serialized_in_a_string() {
+        let inner = format!("a:1:{{i:0;{}}}", string("old.example.com"));
+        let expected = format!("a:1:{{i:0;{}}}", string("new.example.org"));
+        assert_eq!(replace(&string(&inner)), string(&expected));
+    }
+
+    #[test]
+    fn objects() {
+        let text = format!(r#"O:8:"stdClass":2:{{{}{}{}{}}}"#, string("home"), string("https://old.example.com"), string("id"), "i:7;");
+        let expected = format!(r#"O:8:"stdClass":2:{{{}{}{}{}}}"#, string("home"), string("https://new.example.org"), string("id"), "i:7;");
+        assert_eq!(replace(&text), expected);
+
+        // The class name is code, and left alone
+        let search = Search::Text(b"stdClass".to_vec());
+        let text = format!(r#"O:8:"stdClass":1:{{{}{}}}"#, string("class"), string("stdClass"));
+        let expected = format!(r#"O:8:"stdClass":1:{{{}{}}}"#, string("class"), string("Other"));
+        let replaced = replace_value(text.as_bytes(), &search, b"Other", &mut 0);
+        assert_eq!(replaced.as_ref(), expected.as_bytes());
+    }
+
+    #[test]
+    fn custom_serialization() {
+        let payload = r#"x:i:0;a:1:{s:3:"url";s:22:"http://old.example.com";};m:a:0:{}"#;
+        let text = format!(r#"C:11:"ArrayObject":{}:{{{}}}"#, payload.len(), payload);
+        assert_eq!(replace(&text), text);
+        let nested = format!("a:2:{{i:0;{}i:1;{}}}", text, string("old.example.com"));
+        let expected = format!("a:2:{{i:0;{}i:1;{}}}", text, string("new.example.org"));
+        assert_eq!(replace(&nested), expected);
+    }
+
+    #[test]
+    fn multibyte() {
+        assert_eq!(replace(r#"s:24:"café at old.example.com";"#), r#"s:24:"café at new.example.org";"#);
+        let search = Search::Text(b"old.example.com".to_vec());
+        let replaced = replace_value(r#"s:24:"café at old.example.com";"#.as_bytes(), &search, "nëw.example.com".as_bytes(), &mut 0);
+        assert_eq!(replaced.as_ref(), r#"s:25:"café at nëw.example.com";"#.as_bytes());
+        let replaced = replace_value(r#"s:24:"café at old.example.com";"#.as_bytes(), &search, b"x", &mut 0);
+        assert_eq!(replaced.as_ref(), r#"s:10:"café at x";"#.as_bytes());
+    }
+
+    #[test]
+    fn malformed() {
+        for text in [
+            // Truncated
+            format!("a:1:{{{}{}", string("url"), string("http://old.example.com")),
+            r#"s:22:"http://old.example.c"#.to_string(),
+            // A length that's wrong
+            r#"s:30:"http://old.example.com";"#.to_string(),
+            format!("a:2:{{{}{}}}", string("url"), string("http://old.example.com")),
+            // Something after the value
+            format!("{}{}", string("http://old.example.com"), "s:"),
+            format!(r#"C:11:"ArrayObject":99:{{{}}}"#, string("old.example.com")),
+        ] {
+            assert_eq!(replace(&text), text);
+        }
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth: usize, url: &str| {
+            format!("{}{}{}", "a:1:{i:0;".repeat(depth), string(url), "}".repeat(depth))
+        };
+        assert_eq!(replace(&nested(MAX_DEPTH, "old.example.com")), nested(MAX_DEPTH, "new.example.org"));
+        let too_deep = nested(MAX_DEPTH + 1, "old.example.com");
+        assert_eq!(replace(&too_deep), too_deep);
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        let value = format!("a:1:{{{}{}}}", string("title"), string("it's \"old.example.com\"\\"));
+        let expected = format!("a:1:{{{}{}}}", string("title"), string("it's \"new.example.org\"\\"));
+        let statement = |value: &str| {
+            let mut statement = b"INSERT INTO `wp_options` VALUES (1,'widget',".to_vec();
+            statement.extend_from_slice(&quote_string(value.as_bytes()));
+            statement.extend_from_slice(b",'yes');\n");
+            statement
+        };
+        let input = statement(&value);
+        assert!(find(&input, br#"it\'s \"old.example.com\"\\\";}'"#).is_some());
+
+        let search = Search::Text(b"old.example.com".to_vec());
+        let mut output = Vec::new();
+        let tables = search_replace_dump(&input[..], &search, b"new.example.org", &mut output).unwrap();
+        assert_eq!(output, statement(&expected));
+        assert_eq!(tables, [TableReplacements { name: "wp_options".to_string(), replacements: 1 }]);
+    }
+}
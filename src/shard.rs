@@ -0,0 +1,115 @@
+//! Splitting a dump into several dumps, routing each row of a table to a
+//! shard by the hash of one of its columns.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::dump::StatementReader;
+use crate::insert::{parse_insert, split_values};
+use crate::schema::parse_create_table;
+
+/// A `table.column` pair naming the column to shard a table by.
+#[derive(Debug, Clone)]
+pub struct ShardKey {
+    pub table: String,
+    pub column: String,
+}
+
+impl FromStr for ShardKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('.') {
+            Some((table, column)) if !table.is_empty() && !column.is_empty() => {
+                Ok(ShardKey { table: table.to_string(), column: column.to_string() })
+            }
+            _ => Err(format!("expected table.column, got `{}`", s)),
+        }
+    }
+}
+
+/// Write `input` out to each of `outputs`, splitting the rows of tables named
+/// in `keys` between them.
+///
+/// Every shard gets the full schema and all other statements, including the
+/// data of tables that aren't sharded, so each one restores on its own. A row
+/// goes to shard `hash(value) % outputs.len()`, where the value is hashed as
+/// written in the dump, so the same value always lands in the same shard.
+pub fn shard_dump<R: BufRead, W: Write>(input: R, keys: &[ShardKey], outputs: &mut [W]) -> io::Result<()> {
+    let keys: HashMap<&str, &str> = keys.iter().map(|key| (key.table.as_str(), key.column.as_str())).collect();
+    let mut column_indexes: HashMap<String, usize> = HashMap::new();
+
+    for statement in StatementReader::new(input) {
+        let statement = statement?;
+
+        if let Some(schema) = parse_create_table(&statement.text) {
+            if let Some(column) = keys.get(schema.name.as_str()) {
+                let index = schema.column_index(column).ok_or_else(|| {
+                    invalid_input(format!("table `{}` has no column `{}`", schema.name, column))
+                })?;
+                column_indexes.insert(schema.name, index);
+            }
+        }
+
+        let insert = match parse_insert(&statement.text) {
+            Some(insert) if keys.contains_key(insert.table.as_str()) => insert,
+            _ => {
+                for output in outputs.iter_mut() {
+                    output.write_all(&statement.text)?;
+                }
+                continue;
+            }
+        };
+
+        let index = match &insert.columns {
+            Some(columns) => {
+                let column = keys[insert.table.as_str()];
+                columns.iter().position(|c| c.eq_ignore_ascii_case(column)).ok_or_else(|| {
+                    invalid_input(format!("INSERT into `{}` does not include column `{}`", insert.table, column))
+                })?
+            }
+            None => *column_indexes.get(&insert.table).ok_or_else(|| {
+                invalid_input(format!("found rows for `{}` before its CREATE TABLE", insert.table))
+            })?,
+        };
+
+        let mut buckets: Vec<Vec<&[u8]>> = vec![Vec::new(); outputs.len()];
+        for tuple in &insert.tuples {
+            let value = split_values(tuple).get(index).copied().ok_or_else(|| {
+                invalid_input(format!("row in `{}` has fewer than {} values", insert.table, index + 1))
+            })?;
+            buckets[(fnv1a(value) % outputs.len() as u64) as usize].push(tuple);
+        }
+        for (output, bucket) in outputs.iter_mut().zip(buckets) {
+            if !bucket.is_empty() {
+                output.write_all(&insert.with_tuples(bucket))?;
+            }
+        }
+    }
+
+    for output in outputs {
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a, chosen because it is stable across platforms and releases,
+/// unlike std's `DefaultHasher`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Spread the bits of a hash, as FNV-1a leaves the hashes of similar values,
+/// like consecutive IDs, similar in their top bits.
+pub(crate) fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,555 @@
+//! Where [`extract_sql_per_table`](crate::extract::extract_sql_per_table)
+//! writes each table: files in a directory, gzipped files, a tar archive, one
+//! output with markers between the tables, or a library user's own
+//! [`TableSink`].
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{self, File, OpenOptions};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::BufWriter;
+use std::io::{self, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+#[cfg(not(target_arch = "wasm32"))]
+use flate2::write::GzEncoder;
+#[cfg(not(target_arch = "wasm32"))]
+use flate2::Compression;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::checksum::{ChecksumWriter, Checksums};
+use crate::convert::file_name;
+use crate::date::civil_from_days;
+
+/// Opens a writer for each table's output, and closes it once the table's
+/// been written. Tables are written one at a time, in the order of the dump.
+pub trait TableSink {
+    type Writer: Write;
+
+    fn open(&mut self, table: &str) -> io::Result<Self::Writer>;
+
+    /// Finish writing `table`, such as by flushing the writer or uploading
+    /// what it holds.
+    fn close(&mut self, _table: &str, mut writer: Self::Writer) -> io::Result<()> {
+        writer.flush()
+    }
+
+    /// Note that the dump's switched to `database`, in a dump made with
+    /// `--databases`, before the tables in it are opened.
+    fn use_database(&mut self, _database: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether `table`'s writer will add to what's already been written for
+    /// it, starting with the dump's header and the table's structure, so
+    /// only its rows are written to it, such as when merging the tables of
+    /// several dumps. Asked before each table's opened, but not its parts.
+    /// By default, never.
+    fn appends(&self, _table: &str) -> bool {
+        false
+    }
+
+    /// Open a writer for part `part` of `table`, numbered from 1, for a
+    /// table split between files by [`ExtractOptions::max_file_size`]. By
+    /// default, the same as opening a table named as [`part_name`] has it.
+    ///
+    /// [`ExtractOptions::max_file_size`]: crate::extract::ExtractOptions::max_file_size
+    fn open_part(&mut self, table: &str, part: usize) -> io::Result<Self::Writer> {
+        self.open(&part_name(table, part))
+    }
+
+    /// Finish writing part `part` of `table`. By default, the same as
+    /// closing a table named as [`part_name`] has it.
+    fn close_part(&mut self, table: &str, part: usize, writer: Self::Writer) -> io::Result<()> {
+        self.close(&part_name(table, part), writer)
+    }
+
+    /// Finish writing, once every table's been closed.
+    fn finish(self) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+}
+
+/// The name of part `part` of `table`'s output, e.g. `wp_posts.001`.
+pub fn part_name(table: &str, part: usize) -> String {
+    format!("{}.{:03}", table, part)
+}
+
+/// The file for part `part` of `table`, with `extension`, e.g.
+/// `wp_posts.001.sql`, named as [`file_name`] names a table's.
+pub fn part_file_name(table: &str, part: usize, extension: &str) -> String {
+    file_name(table, &format!(".{:03}{}", part, extension))
+}
+
+/// Where a sink writes each table, from a template such as
+/// `{db}/{table}.{ext}`: `{db}` is the database the dump last switched to
+/// (empty if it hasn't), `{table}` the table, `{ext}` the extension, such as
+/// `sql.gz`, `{part}` the part's number, such as `001`, and `{date}` today's
+/// date in UTC, such as `2024-06-30`.
+///
+/// The database and table are written as [`file_name`] writes them, so they
+/// can't leave the directory, and empty directories are left out of the
+/// path. A table split into parts in a template without `{part}` has the
+/// part's number before the extension, as in `wp_posts.001.sql`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTemplate {
+    template: String,
+    /// Today's date, for `{date}`
+    date: String,
+}
+
+impl FromStr for FileTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| format!("`{}` has a `{{` with no `}}`", s))? + start;
+            let placeholder = &rest[start + 1..end];
+            if !["db", "table", "ext", "part", "date"].contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder `{{{}}}` in `{}`, expected {{db}}, {{table}}, {{ext}}, {{part}} or {{date}}",
+                    placeholder, s
+                ));
+            }
+            rest = &rest[end + 1..];
+        }
+        if !s.contains("{table}") {
+            return Err(format!("`{}` has no {{table}}, so the tables would all be written to one file", s));
+        }
+        if s.starts_with('/') || s.split('/').any(|segment| segment == "..") {
+            return Err(format!("`{}` has to stay in the directory", s));
+        }
+        let days = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs() / 86_400);
+        let (year, month, day) = civil_from_days(days as i64);
+        Ok(FileTemplate { template: s.to_string(), date: format!("{:04}-{:02}-{:02}", year, month, day) })
+    }
+}
+
+impl FileTemplate {
+    /// The path of `table`'s file, or of part `part` of it, in `database`,
+    /// with `extension`, such as `sql`.
+    pub fn path(&self, database: Option<&str>, table: &str, part: Option<usize>, extension: &str) -> String {
+        let has_part = self.template.contains("{part}");
+        let extension = match part {
+            Some(part) if !has_part => format!("{:03}.{}", part, extension),
+            _ => extension.to_string(),
+        };
+        // Values are written as file_name writes them, so none has a
+        // placeholder of its own to be filled in again
+        let mut path = self
+            .template
+            .replace("{db}", &file_name(database.unwrap_or_default(), ""))
+            .replace("{table}", &file_name(table, ""))
+            .replace("{part}", &part.map(|part| format!("{:03}", part)).unwrap_or_default())
+            .replace("{date}", &self.date);
+        match part {
+            Some(part) if !has_part && !path.contains("{ext}") => path += &format!(".{:03}", part),
+            _ => {}
+        }
+        let path = path.replace("{ext}", &extension);
+        path.split('/').filter(|segment| !segment.is_empty()).collect::<Vec<_>>().join("/")
+    }
+}
+
+/// How a sink names each table's file: by a [`FileTemplate`], or as
+/// [`file_name`] and [`part_file_name`] have it.
+#[derive(Debug, Clone)]
+struct FileNames {
+    template: Option<FileTemplate>,
+    /// The database the dump last switched to
+    database: Option<String>,
+    /// Such as `sql.gz`
+    extension: &'static str,
+}
+
+impl FileNames {
+    fn new(extension: &'static str) -> Self {
+        FileNames { template: None, database: None, extension }
+    }
+
+    fn name(&self, table: &str, part: Option<usize>) -> String {
+        match (&self.template, part) {
+            (Some(template), _) => template.path(self.database.as_deref(), table, part, self.extension),
+            (None, Some(part)) => part_file_name(table, part, &format!(".{}", self.extension)),
+            (None, None) => file_name(table, &format!(".{}", self.extension)),
+        }
+    }
+}
+
+/// Writes each table to `table.sql` in a directory, which has to exist, or
+/// each part of one to `table.001.sql` and so on. It isn't built for
+/// WebAssembly, which has no directory to write to.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    dir: PathBuf,
+    names: FileNames,
+    append: bool,
+    checksums: Option<Checksums>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileSink { dir: dir.into(), names: FileNames::new("sql"), append: false, checksums: None }
+    }
+
+    /// Name the files by `template`, making the directories it puts them in.
+    pub fn template(mut self, template: FileTemplate) -> Self {
+        self.names.template = Some(template);
+        self
+    }
+
+    /// Add to the tables' files already in the directory rather than
+    /// replacing them, with only the rows of a table that has one, as
+    /// [`TableSink::appends`] has it. Parts are still replaced.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Work out each file's checksum as it's written, into `checksums`.
+    pub fn checksums(mut self, checksums: &Checksums) -> Self {
+        self.checksums = Some(checksums.clone());
+        self
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TableSink for FileSink {
+    type Writer = BufWriter<ChecksumWriter<File>>;
+
+    fn use_database(&mut self, database: &str) -> io::Result<()> {
+        self.names.database = Some(database.to_string());
+        Ok(())
+    }
+
+    fn appends(&self, table: &str) -> bool {
+        self.append && has_content(&self.dir.join(self.names.name(table, None)))
+    }
+
+    fn open(&mut self, table: &str) -> io::Result<Self::Writer> {
+        create(&self.dir, &self.names.name(table, None), self.append, &self.checksums)
+    }
+
+    fn close(&mut self, table: &str, writer: Self::Writer) -> io::Result<()> {
+        finish(writer, self.names.name(table, None), &self.checksums)
+    }
+
+    fn open_part(&mut self, table: &str, part: usize) -> io::Result<Self::Writer> {
+        create(&self.dir, &self.names.name(table, Some(part)), false, &self.checksums)
+    }
+
+    fn close_part(&mut self, table: &str, part: usize, writer: Self::Writer) -> io::Result<()> {
+        finish(writer, self.names.name(table, Some(part)), &self.checksums)
+    }
+}
+
+/// Create the file `name` in `dir`, and the directories in between, or open
+/// it to `append` to, to write it with its checksum worked out if there's
+/// `checksums`.
+#[cfg(not(target_arch = "wasm32"))]
+fn create(
+    dir: &Path,
+    name: &str,
+    append: bool,
+    checksums: &Option<Checksums>,
+) -> io::Result<BufWriter<ChecksumWriter<File>>> {
+    let path = dir.join(name);
+    if let Some(parent) = path.parent().filter(|_| name.contains('/')) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = if append {
+        OpenOptions::new().create(true).append(true).open(path)?
+    } else {
+        File::create(path)?
+    };
+    Ok(BufWriter::new(ChecksumWriter::new(file, checksums.as_ref().map(Checksums::checksum))))
+}
+
+/// Whether there's a file at `path` with something in it.
+#[cfg(not(target_arch = "wasm32"))]
+fn has_content(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0)
+}
+
+/// Flush the file `name`, adding its checksum to `checksums`.
+#[cfg(not(target_arch = "wasm32"))]
+fn finish(writer: BufWriter<ChecksumWriter<File>>, name: String, checksums: &Option<Checksums>) -> io::Result<()> {
+    let (_, digest) = writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish();
+    if let (Some(checksums), Some(digest)) = (checksums, digest) {
+        checksums.add(name, digest);
+    }
+    Ok(())
+}
+
+/// Writes each table to `table.sql.gz` in a directory, which has to exist, or
+/// each part of one to `table.001.sql.gz` and so on. Like [`FileSink`], it
+/// isn't built for WebAssembly.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct GzipFileSink {
+    dir: PathBuf,
+    names: FileNames,
+    level: Compression,
+    append: bool,
+    checksums: Option<Checksums>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl GzipFileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        GzipFileSink {
+            dir: dir.into(),
+            names: FileNames::new("sql.gz"),
+            level: Compression::default(),
+            append: false,
+            checksums: None,
+        }
+    }
+
+    /// Name the files by `template`, as [`FileSink::template`] does.
+    pub fn template(mut self, template: FileTemplate) -> Self {
+        self.names.template = Some(template);
+        self
+    }
+
+    /// Add to the tables' files already in the directory, as
+    /// [`FileSink::append`] does. What's added is a gzip member of its own,
+    /// which `gunzip` reads on from the one before.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Compress at this level, from 0 (none) to 9 (smallest), rather than 6.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Compression::new(level);
+        self
+    }
+
+    /// Work out each compressed file's checksum as it's written, into
+    /// `checksums`.
+    pub fn checksums(mut self, checksums: &Checksums) -> Self {
+        self.checksums = Some(checksums.clone());
+        self
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TableSink for GzipFileSink {
+    type Writer = GzEncoder<BufWriter<ChecksumWriter<File>>>;
+
+    fn use_database(&mut self, database: &str) -> io::Result<()> {
+        self.names.database = Some(database.to_string());
+        Ok(())
+    }
+
+    fn appends(&self, table: &str) -> bool {
+        self.append && has_content(&self.dir.join(self.names.name(table, None)))
+    }
+
+    fn open(&mut self, table: &str) -> io::Result<Self::Writer> {
+        let file = create(&self.dir, &self.names.name(table, None), self.append, &self.checksums)?;
+        Ok(GzEncoder::new(file, self.level))
+    }
+
+    fn close(&mut self, table: &str, writer: Self::Writer) -> io::Result<()> {
+        finish(writer.finish()?, self.names.name(table, None), &self.checksums)
+    }
+
+    fn open_part(&mut self, table: &str, part: usize) -> io::Result<Self::Writer> {
+        let file = create(&self.dir, &self.names.name(table, Some(part)), false, &self.checksums)?;
+        Ok(GzEncoder::new(file, self.level))
+    }
+
+    fn close_part(&mut self, table: &str, part: usize, writer: Self::Writer) -> io::Result<()> {
+        finish(writer.finish()?, self.names.name(table, Some(part)), &self.checksums)
+    }
+}
+
+/// Writes each table as a `table.sql` entry of a tar archive, or each part of
+/// one as `table.001.sql` and so on. Each is held in memory until it's
+/// closed, as an entry's size comes before it.
+pub struct TarSink<W: Write> {
+    builder: tar::Builder<W>,
+    names: FileNames,
+}
+
+impl<W: Write> TarSink<W> {
+    pub fn new(output: W) -> Self {
+        TarSink { builder: tar::Builder::new(output), names: FileNames::new("sql") }
+    }
+
+    /// Name the entries by `template`, as [`FileSink::template`] names files.
+    pub fn template(mut self, template: FileTemplate) -> Self {
+        self.names.template = Some(template);
+        self
+    }
+
+    fn append(&mut self, path: String, data: &[u8]) -> io::Result<()> {
+        let mtime = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        self.builder.append_data(&mut header, path, data)
+    }
+}
+
+impl<W: Write> TableSink for TarSink<W> {
+    type Writer = Vec<u8>;
+
+    fn use_database(&mut self, database: &str) -> io::Result<()> {
+        self.names.database = Some(database.to_string());
+        Ok(())
+    }
+
+    fn open(&mut self, _table: &str) -> io::Result<Self::Writer> {
+        Ok(Vec::new())
+    }
+
+    fn close(&mut self, table: &str, writer: Self::Writer) -> io::Result<()> {
+        self.append(self.names.name(table, None), &writer)
+    }
+
+    fn close_part(&mut self, table: &str, part: usize, writer: Self::Writer) -> io::Result<()> {
+        self.append(self.names.name(table, Some(part)), &writer)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.builder.into_inner()?.flush()
+    }
+}
+
+/// Writes every table to one output, each framed between a line
+/// `-->>TABLE name` and a line `--<<TABLE`, so what reads it can tell the
+/// tables apart without a file for each. A `\`, newline or carriage return in
+/// a name is written as `\\`, `\n` or `\r`.
+///
+/// mysqldump writes the newlines in values as `\n`, so no line of a table's
+/// rows starts with a marker. Each table has to be written whole before the
+/// next is opened, so it's no use with
+/// [`extract_sql_per_table_threaded`](crate::extract::extract_sql_per_table_threaded).
+pub struct MuxSink<W: Write> {
+    output: Arc<Mutex<MuxOutput<W>>>,
+}
+
+/// The output a [`MuxSink`] and its writers share.
+struct MuxOutput<W> {
+    output: W,
+    /// Whether what's been written ends with a newline, or nothing has
+    at_line_start: bool,
+}
+
+/// Writes a table to a [`MuxSink`]'s output.
+pub struct MuxWriter<W: Write> {
+    output: Arc<Mutex<MuxOutput<W>>>,
+}
+
+impl<W: Write> MuxSink<W> {
+    pub fn new(output: W) -> Self {
+        MuxSink { output: Arc::new(Mutex::new(MuxOutput { output, at_line_start: true })) }
+    }
+
+    /// Write `text` to the output, starting on a line of its own.
+    fn write_line(&mut self, text: &str) -> io::Result<()> {
+        let mut mux = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        if !mux.at_line_start {
+            mux.output.write_all(b"\n")?;
+        }
+        mux.output.write_all(text.as_bytes())?;
+        mux.at_line_start = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for MuxWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut mux = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        let written = mux.output.write(buf)?;
+        if written > 0 {
+            mux.at_line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.lock().unwrap_or_else(PoisonError::into_inner).output.flush()
+    }
+}
+
+impl<W: Write> TableSink for MuxSink<W> {
+    type Writer = MuxWriter<W>;
+
+    fn open(&mut self, table: &str) -> io::Result<Self::Writer> {
+        let name = table.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+        self.write_line(&format!("-->>TABLE {}\n", name))?;
+        Ok(MuxWriter { output: self.output.clone() })
+    }
+
+    fn close(&mut self, _table: &str, _writer: Self::Writer) -> io::Result<()> {
+        self.write_line("--<<TABLE\n")
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.output.lock().unwrap_or_else(PoisonError::into_inner).output.flush()
+    }
+}
+
+/// Calls `done` with each table's name once another sink's closed it, such
+/// as to upload the table's file, or restore it while the rest are written.
+/// For a table split into parts, it's called with each part's name, as
+/// [`part_name`] has it.
+#[derive(Clone)]
+pub struct OnClose<S, F> {
+    sink: S,
+    done: F,
+}
+
+impl<S, F> OnClose<S, F> {
+    pub fn new(sink: S, done: F) -> Self {
+        OnClose { sink, done }
+    }
+}
+
+impl<S: TableSink, F: FnMut(&str) -> io::Result<()>> TableSink for OnClose<S, F> {
+    type Writer = S::Writer;
+
+    fn use_database(&mut self, database: &str) -> io::Result<()> {
+        self.sink.use_database(database)
+    }
+
+    fn appends(&self, table: &str) -> bool {
+        self.sink.appends(table)
+    }
+
+    fn open(&mut self, table: &str) -> io::Result<Self::Writer> {
+        self.sink.open(table)
+    }
+
+    fn close(&mut self, table: &str, writer: Self::Writer) -> io::Result<()> {
+        self.sink.close(table, writer)?;
+        (self.done)(table)
+    }
+
+    fn open_part(&mut self, table: &str, part: usize) -> io::Result<Self::Writer> {
+        self.sink.open_part(table, part)
+    }
+
+    fn close_part(&mut self, table: &str, part: usize, writer: Self::Writer) -> io::Result<()> {
+        self.sink.close_part(table, part, writer)?;
+        (self.done)(&part_name(table, part))
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.sink.finish()
+    }
+}
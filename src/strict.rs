@@ -0,0 +1,249 @@
+//! Fixing the dates a server in strict SQL mode rejects, such as MySQL's
+//! zero date `0000-00-00`.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::date::{is_date_type, is_valid_date};
+use crate::dump::{find, quote_identifier};
+use crate::insert::{insert_table, join_values, parse_insert, parse_value, quote_string, split_values, Value};
+use crate::schema::{parse_create_table, Column};
+
+/// What invalid dates, like `0000-00-00` or `2020-00-00`, are replaced with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidDates {
+    /// `NULL` where the column allows it, otherwise the earliest date the
+    /// column's type can hold
+    #[default]
+    Null,
+    /// The earliest date the column's type can hold
+    Sentinel,
+}
+
+impl FromStr for InvalidDates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "null" => Ok(InvalidDates::Null),
+            "sentinel" => Ok(InvalidDates::Sentinel),
+            _ => Err(format!("unknown replacement `{}`, expected null or sentinel", s)),
+        }
+    }
+}
+
+/// A change made to fix a strict mode violation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "fix", rename_all = "snake_case"))]
+pub enum StrictFix {
+    /// A column's invalid `DEFAULT` was replaced
+    Default { table: String, column: String, old: String, new: String },
+    /// A column's invalid dates were replaced, in `rows` rows
+    Values { table: String, column: String, replacement: String, rows: u64 },
+}
+
+/// A date column, as its dates are fixed.
+#[derive(Debug, Clone)]
+struct DateColumn {
+    name: String,
+    /// `NULL` if the column allows it, otherwise a sentinel
+    replacement: Vec<u8>,
+}
+
+/// The fixes to make, and what's been learned of the tables' date columns.
+#[derive(Debug, Clone, Default)]
+pub struct StrictMode {
+    values: Option<InvalidDates>,
+    defaults: bool,
+    /// Column names and date columns of each table with any, from its
+    /// `CREATE TABLE`
+    columns: HashMap<String, (Vec<String>, Vec<DateColumn>)>,
+    fixes: Vec<StrictFix>,
+}
+
+impl StrictMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the invalid dates in rows.
+    pub fn fix_values(&mut self, replacement: InvalidDates) {
+        self.values = Some(replacement);
+    }
+
+    /// Replace the invalid `DEFAULT`s of date columns in `CREATE TABLE`s.
+    pub fn fix_defaults(&mut self) {
+        self.defaults = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_none() && !self.defaults
+    }
+
+    /// Every change made so far.
+    pub fn fixes(&self) -> &[StrictFix] {
+        &self.fixes
+    }
+
+    /// Fix the invalid dates in an `INSERT`, or the invalid defaults in a
+    /// `CREATE TABLE`.
+    pub fn rewrite<'a>(&mut self, text: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.is_empty() {
+            return Cow::Borrowed(text);
+        }
+
+        if let Some(schema) = parse_create_table(text) {
+            let replacement = self.values.unwrap_or_default();
+            let dates: Vec<DateColumn> = schema
+                .columns
+                .iter()
+                .filter(|column| is_date_type(&column.definition))
+                .map(|column| DateColumn {
+                    name: column.name.clone(),
+                    replacement: replacement_for(column, replacement),
+                })
+                .collect();
+            if dates.is_empty() {
+                return Cow::Borrowed(text);
+            }
+            let names = schema.columns.iter().map(|column| column.name.clone()).collect();
+            self.columns.insert(schema.name.clone(), (names, dates));
+            if self.defaults {
+                return self.fix_create_table(text, &schema.name, &schema.columns);
+            }
+            return Cow::Borrowed(text);
+        }
+
+        if self.values.is_none() {
+            return Cow::Borrowed(text);
+        }
+        let Some(table) = insert_table(text) else { return Cow::Borrowed(text) };
+        let Some((names, dates)) = self.columns.get(&table) else { return Cow::Borrowed(text) };
+        let Some(insert) = parse_insert(text) else { return Cow::Borrowed(text) };
+        let names = insert.columns.as_ref().unwrap_or(names);
+        let positions: Vec<(usize, &DateColumn)> = dates
+            .iter()
+            .filter_map(|date| names.iter().position(|name| name.eq_ignore_ascii_case(&date.name)).map(|i| (i, date)))
+            .collect();
+
+        let mut rows = vec![0; positions.len()];
+        let tuples: Vec<Cow<[u8]>> = insert
+            .tuples
+            .iter()
+            .map(|tuple| {
+                let mut values: Vec<Cow<[u8]>> = split_values(tuple).into_iter().map(Cow::Borrowed).collect();
+                let mut changed = false;
+                for (fixed, (index, date)) in rows.iter_mut().zip(&positions) {
+                    let Some(value) = values.get_mut(*index) else { continue };
+                    let Value::String(bytes) = parse_value(value) else { continue };
+                    if !is_valid_date(&bytes) {
+                        *value = Cow::Owned(date.replacement.clone());
+                        *fixed += 1;
+                        changed = true;
+                    }
+                }
+                if changed {
+                    Cow::Owned(join_values(values.iter().map(|value| &value[..])))
+                } else {
+                    Cow::Borrowed(*tuple)
+                }
+            })
+            .collect();
+        if rows.iter().all(|rows| *rows == 0) {
+            return Cow::Borrowed(text);
+        }
+
+        for (fixed, (_, date)) in rows.into_iter().zip(&positions).filter(|(fixed, _)| *fixed > 0) {
+            let replacement = String::from_utf8_lossy(&date.replacement).into_owned();
+            let existing = self.fixes.iter_mut().find_map(|fix| match fix {
+                StrictFix::Values { table: t, column, replacement: r, rows }
+                    if *t == table && *column == date.name && *r == replacement =>
+                {
+                    Some(rows)
+                }
+                _ => None,
+            });
+            match existing {
+                Some(rows) => *rows += fixed,
+                None => self.fixes.push(StrictFix::Values {
+                    table: table.clone(),
+                    column: date.name.clone(),
+                    replacement,
+                    rows: fixed,
+                }),
+            }
+        }
+        Cow::Owned(insert.with_tuples(tuples.iter().map(|tuple| &tuple[..])))
+    }
+
+    /// Replace the invalid `DEFAULT`s of `columns` in a `CREATE TABLE`,
+    /// where each column is on its own line.
+    fn fix_create_table<'a>(&mut self, text: &'a [u8], table: &str, columns: &[Column]) -> Cow<'a, [u8]> {
+        let replacement = self.values.unwrap_or_default();
+        let mut rewritten = Vec::with_capacity(text.len());
+        let mut changed = false;
+        for line in text.split_inclusive(|b| *b == b'\n') {
+            let trimmed = line.trim_ascii_start();
+            let column = columns.iter().find(|column| {
+                is_date_type(&column.definition) && trimmed.starts_with(quote_identifier(&column.name).as_bytes())
+            });
+            let fixed = column.and_then(|column| {
+                let default = find(line, b"DEFAULT '")?;
+                let start = default + b"DEFAULT ".len();
+                let length = line[start + 1..].iter().position(|b| *b == b'\'')? + 2;
+                let literal = &line[start..start + length];
+                if is_valid_date(&literal[1..length - 1]) {
+                    return None;
+                }
+                let new = replacement_for(column, replacement);
+                self.fixes.push(StrictFix::Default {
+                    table: table.to_string(),
+                    column: column.name.clone(),
+                    old: format!("DEFAULT {}", String::from_utf8_lossy(literal)),
+                    new: format!("DEFAULT {}", String::from_utf8_lossy(&new)),
+                });
+                let mut line = line.to_vec();
+                line.splice(start..start + length, new);
+                Some(line)
+            });
+            match fixed {
+                Some(line) => {
+                    rewritten.extend_from_slice(&line);
+                    changed = true;
+                }
+                None => rewritten.extend_from_slice(line),
+            }
+        }
+        if changed {
+            Cow::Owned(rewritten)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+/// The literal replacing `column`'s invalid dates: `NULL` if it allows it
+/// and that's wanted, otherwise the earliest value its type holds.
+fn replacement_for(column: &Column, replacement: InvalidDates) -> Vec<u8> {
+    let definition = column.definition.to_ascii_uppercase();
+    if replacement == InvalidDates::Null && !definition.contains("NOT NULL") {
+        return b"NULL".to_vec();
+    }
+    quote_string(earliest_date(&definition).as_bytes())
+}
+
+/// The earliest value a `DATE`, `DATETIME` or `TIMESTAMP` column can hold,
+/// from its definition.
+pub(crate) fn earliest_date(definition: &str) -> &'static str {
+    let definition = definition.to_ascii_uppercase();
+    if definition.starts_with("TIMESTAMP") {
+        // The earliest TIMESTAMP, in UTC
+        "1970-01-01 00:00:01"
+    } else if definition.starts_with("DATETIME") {
+        "1000-01-01 00:00:00"
+    } else {
+        "1000-01-01"
+    }
+}
@@ -0,0 +1,411 @@
+//! Extracting a referentially consistent subset of rows, starting from a few
+//! seed rows and following foreign keys.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+use crate::dump::StatementReader;
+use crate::insert::{parse_insert, parse_value, split_values, Insert};
+use crate::schema::{parse_create_table, TableSchema};
+
+/// A relationship between tables that isn't declared as a foreign key, such
+/// as `wp_postmeta.post_id=wp_posts.ID` in WordPress.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    pub child: String,
+    pub child_column: String,
+    pub parent: String,
+    pub parent_column: String,
+}
+
+impl FromStr for Relation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (child, parent) = s.split_once('=').ok_or_else(|| format!("expected child.column=parent.column, got `{}`", s))?;
+        let (child, child_column) = split_column(child)?;
+        let (parent, parent_column) = split_column(parent)?;
+        Ok(Relation { child, child_column, parent, parent_column })
+    }
+}
+
+/// Where to start the subset from.
+#[derive(Debug, Clone)]
+pub enum Seed {
+    /// Rows whose `column` has one of `values`, from `table.column IN (...)`
+    /// or `table.column = value`
+    Where { table: String, column: String, values: HashSet<Vec<u8>> },
+    /// The first `rows` rows of `table`, from `table=rows`
+    Sample { table: String, rows: u64 },
+}
+
+impl Seed {
+    pub fn parse_where(s: &str) -> Result<Seed, String> {
+        let error = || format!("expected table.column IN (...) or table.column = value, got `{}`", s);
+        let (target, values) = match s.to_ascii_uppercase().find(" IN ") {
+            Some(at) => (&s[..at], s[at + 4..].trim().to_string()),
+            None => {
+                let (target, value) = s.split_once('=').ok_or_else(error)?;
+                (target, format!("({})", value.trim()))
+            }
+        };
+        if !values.starts_with('(') || !values.ends_with(')') {
+            return Err(error());
+        }
+        let (table, column) = split_column(target.trim())?;
+        let values = split_values(values.as_bytes())
+            .into_iter()
+            .filter_map(|value| parse_value(value).as_bytes().map(key_part))
+            .collect();
+        Ok(Seed::Where { table, column, values })
+    }
+
+    pub fn parse_sample(s: &str) -> Result<Seed, String> {
+        match s.split_once('=').map(|(table, rows)| (table, rows.parse())) {
+            Some((table, Ok(rows))) if !table.is_empty() => Ok(Seed::Sample { table: table.to_string(), rows }),
+            _ => Err(format!("expected table=rows, got `{}`", s)),
+        }
+    }
+
+    fn table(&self) -> &str {
+        match self {
+            Seed::Where { table, .. } | Seed::Sample { table, .. } => table,
+        }
+    }
+}
+
+/// How many rows of a table made it into the subset.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableSubset {
+    pub name: String,
+    pub kept_rows: u64,
+    pub total_rows: u64,
+}
+
+/// A foreign key with its columns resolved to positions in each table.
+struct Edge {
+    child: String,
+    child_columns: Vec<usize>,
+    parent: String,
+    parent_columns: Vec<usize>,
+}
+
+/// Write the subset of `input` reachable from `seeds` to `output`.
+///
+/// Starting from the seed rows, rows in child tables that reference a kept row
+/// are kept too, recursively. Then every row referenced by a kept row is kept,
+/// so the result restores with foreign key checks on. Rows pulled in only
+/// because something references them don't pull in their own children, which
+/// would otherwise tend to drag in the whole database.
+///
+/// The whole schema is kept. Each step is a pass over `input`, repeated until
+/// nothing changes, so this reads the dump several times.
+pub fn subset_dump<F: Read + Seek, W: Write>(
+    mut input: F,
+    seeds: &[Seed],
+    relations: &[Relation],
+    output: &mut W,
+) -> io::Result<Vec<TableSubset>> {
+    let mut schemas = HashMap::new();
+    let mut order = Vec::new();
+    for statement in StatementReader::new(BufReader::new(&mut input)) {
+        if let Some(schema) = parse_create_table(&statement?.text) {
+            order.push(schema.name.clone());
+            schemas.insert(schema.name.clone(), schema);
+        }
+    }
+
+    let edges = resolve_edges(&schemas, relations)?;
+    for seed in seeds {
+        if !schemas.contains_key(seed.table()) {
+            return Err(invalid_input(format!("no CREATE TABLE for `{}` in the dump", seed.table())));
+        }
+    }
+    let seed_columns: Vec<Option<usize>> = seeds
+        .iter()
+        .map(|seed| match seed {
+            Seed::Where { table, column, .. } => column_index(&schemas[table], column).map(Some),
+            Seed::Sample { .. } => Ok(None),
+        })
+        .collect::<io::Result<_>>()?;
+    let relevant: HashSet<&str> = seeds
+        .iter()
+        .map(|seed| seed.table())
+        .chain(edges.iter().flat_map(|edge| [edge.child.as_str(), edge.parent.as_str()]))
+        .collect();
+
+    let mut kept: HashMap<String, HashSet<u64>> =
+        schemas.keys().map(|name| (name.clone(), HashSet::new())).collect();
+
+    // Downwards: seed rows, and rows referencing a kept row
+    let mut parent_keys: Vec<HashSet<Vec<u8>>> = vec![HashSet::new(); edges.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for_each_row(&mut input, &schemas, &relevant, |table, ordinal, row| {
+            let kept_rows = kept.get_mut(table).unwrap();
+            let mut keep = kept_rows.contains(&ordinal);
+            if !keep {
+                keep = seeds.iter().zip(&seed_columns).any(|(seed, column)| match (seed, column) {
+                    (Seed::Where { table: t, values, .. }, Some(column)) if t == table => {
+                        key(row, &[*column]).is_some_and(|key| values.contains(&key))
+                    }
+                    (Seed::Sample { table: t, rows }, _) if t == table => ordinal < *rows,
+                    _ => false,
+                }) || edges.iter().zip(&parent_keys).any(|(edge, keys)| {
+                    edge.child == table && key(row, &edge.child_columns).is_some_and(|key| keys.contains(&key))
+                });
+                if keep {
+                    kept_rows.insert(ordinal);
+                    changed = true;
+                }
+            }
+            if keep {
+                for (edge, keys) in edges.iter().zip(parent_keys.iter_mut()) {
+                    if edge.parent == table {
+                        if let Some(key) = key(row, &edge.parent_columns) {
+                            changed |= keys.insert(key);
+                        }
+                    }
+                }
+            }
+        })?;
+    }
+
+    // Upwards: rows referenced by a kept row
+    let mut needed: Vec<HashSet<Vec<u8>>> = vec![HashSet::new(); edges.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for_each_row(&mut input, &schemas, &relevant, |table, ordinal, row| {
+            let kept_rows = kept.get_mut(table).unwrap();
+            let mut keep = kept_rows.contains(&ordinal);
+            if !keep {
+                keep = edges.iter().zip(&needed).any(|(edge, keys)| {
+                    edge.parent == table && key(row, &edge.parent_columns).is_some_and(|key| keys.contains(&key))
+                });
+                if keep {
+                    kept_rows.insert(ordinal);
+                    changed = true;
+                }
+            }
+            if keep {
+                for (edge, keys) in edges.iter().zip(needed.iter_mut()) {
+                    if edge.child == table {
+                        if let Some(key) = key(row, &edge.child_columns) {
+                            changed |= keys.insert(key);
+                        }
+                    }
+                }
+            }
+        })?;
+    }
+
+    input.seek(SeekFrom::Start(0))?;
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    for statement in StatementReader::new(BufReader::new(&mut input)) {
+        let statement = statement?;
+        let insert = match parse_insert(&statement.text) {
+            Some(insert) => insert,
+            None => {
+                output.write_all(&statement.text)?;
+                continue;
+            }
+        };
+        let total = totals.entry(insert.table.clone()).or_default();
+        let first = *total;
+        *total += insert.tuples.len() as u64;
+        let kept_rows = match kept.get(&insert.table) {
+            Some(kept_rows) => kept_rows,
+            None => continue,
+        };
+        let tuples: Vec<&[u8]> = (first..)
+            .zip(&insert.tuples)
+            .filter(|(ordinal, _)| kept_rows.contains(ordinal))
+            .map(|(_, tuple)| *tuple)
+            .collect();
+        if !tuples.is_empty() {
+            output.write_all(&insert.with_tuples(tuples))?;
+        }
+    }
+    output.flush()?;
+
+    Ok(order
+        .into_iter()
+        .map(|name| TableSubset {
+            kept_rows: kept.get(&name).map_or(0, |rows| rows.len() as u64),
+            total_rows: totals.get(&name).copied().unwrap_or(0),
+            name,
+        })
+        .collect())
+}
+
+/// Foreign keys declared in the schema plus the extra `relations`.
+fn resolve_edges(schemas: &HashMap<String, TableSchema>, relations: &[Relation]) -> io::Result<Vec<Edge>> {
+    let mut edges = Vec::new();
+    for schema in schemas.values() {
+        for foreign_key in &schema.foreign_keys {
+            let parent = match schemas.get(&foreign_key.referenced_table) {
+                Some(parent) if foreign_key.referenced_database.is_none() => parent,
+                _ => continue,
+            };
+            edges.push(Edge {
+                child: schema.name.clone(),
+                child_columns: column_indexes(schema, &foreign_key.columns)?,
+                parent: parent.name.clone(),
+                parent_columns: column_indexes(parent, &foreign_key.referenced_columns)?,
+            });
+        }
+    }
+    for relation in relations {
+        let table = |name: &str| {
+            schemas.get(name).ok_or_else(|| invalid_input(format!("no CREATE TABLE for `{}` in the dump", name)))
+        };
+        edges.push(Edge {
+            child: relation.child.clone(),
+            child_columns: vec![column_index(table(&relation.child)?, &relation.child_column)?],
+            parent: relation.parent.clone(),
+            parent_columns: vec![column_index(table(&relation.parent)?, &relation.parent_column)?],
+        });
+    }
+    Ok(edges)
+}
+
+/// Call `visit` with each row of the `relevant` tables, its position within
+/// the table's data, and its values in `CREATE TABLE` column order.
+fn for_each_row<F: Read + Seek>(
+    input: &mut F,
+    schemas: &HashMap<String, TableSchema>,
+    relevant: &HashSet<&str>,
+    mut visit: impl FnMut(&str, u64, &[&[u8]]),
+) -> io::Result<()> {
+    input.seek(SeekFrom::Start(0))?;
+    let mut ordinals: HashMap<String, u64> = HashMap::new();
+    for statement in StatementReader::new(BufReader::new(&mut *input)) {
+        let statement = statement?;
+        let insert = match parse_insert(&statement.text) {
+            Some(insert) if relevant.contains(insert.table.as_str()) => insert,
+            _ => continue,
+        };
+        let schema = match schemas.get(&insert.table) {
+            Some(schema) => schema,
+            None => continue,
+        };
+        let positions = tuple_positions(&insert, schema);
+        let ordinal = ordinals.entry(insert.table.clone()).or_default();
+        for tuple in &insert.tuples {
+            let values = split_values(tuple);
+            let row: Vec<&[u8]> = positions
+                .iter()
+                .map(|position| position.and_then(|p| values.get(p).copied()).unwrap_or(b"NULL"))
+                .collect();
+            visit(&insert.table, *ordinal, &row);
+            *ordinal += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Where each of the schema's columns is in the insert's tuples.
+fn tuple_positions(insert: &Insert, schema: &TableSchema) -> Vec<Option<usize>> {
+    match &insert.columns {
+        Some(columns) => schema
+            .columns
+            .iter()
+            .map(|column| columns.iter().position(|c| c.eq_ignore_ascii_case(&column.name)))
+            .collect(),
+        None => (0..schema.columns.len()).map(Some).collect(),
+    }
+}
+
+/// Key for the values of `columns` in `row`, or `None` if any is NULL (in
+/// which case a foreign key doesn't constrain the row).
+fn key(row: &[&[u8]], columns: &[usize]) -> Option<Vec<u8>> {
+    let mut key = Vec::new();
+    for &column in columns {
+        key.extend(key_part(parse_value(row.get(column)?).as_bytes()?));
+    }
+    Some(key)
+}
+
+/// Length-prefix each value so compound keys can't collide.
+fn key_part(bytes: &[u8]) -> Vec<u8> {
+    let mut part = (bytes.len() as u32).to_le_bytes().to_vec();
+    part.extend_from_slice(bytes);
+    part
+}
+
+fn column_index(schema: &TableSchema, column: &str) -> io::Result<usize> {
+    schema
+        .column_index(column)
+        .ok_or_else(|| invalid_input(format!("table `{}` has no column `{}`", schema.name, column)))
+}
+
+fn column_indexes(schema: &TableSchema, columns: &[String]) -> io::Result<Vec<usize>> {
+    columns.iter().map(|column| column_index(schema, column)).collect()
+}
+
+fn split_column(s: &str) -> Result<(String, String), String> {
+    match s.trim().split_once('.') {
+        Some((table, column)) if !table.is_empty() && !column.is_empty() => Ok((table.to_string(), column.to_string())),
+        _ => Err(format!("expected table.column, got `{}`", s)),
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The subset of `dump` from the rows `seed` picks, as the rows kept of
+    /// each table, by their `id`.
+    fn subset(dump: &str, seed: &str) -> Vec<(String, Vec<String>)> {
+        let mut output = Vec::new();
+        let seeds = [Seed::parse_where(seed).unwrap()];
+        let tables = subset_dump(io::Cursor::new(dump), &seeds, &[], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        tables
+            .into_iter()
+            .map(|table| {
+                let insert = output.lines().filter_map(|line| parse_insert(line.as_bytes())).find(|i| i.table == table.name);
+                let id = |tuple: &&[u8]| String::from_utf8(split_values(tuple)[0].to_vec()).unwrap();
+                let ids: Vec<String> = insert.map_or(Vec::new(), |insert| insert.tuples.iter().map(id).collect());
+                assert_eq!(ids.len() as u64, table.kept_rows);
+                (table.name, ids)
+            })
+            .collect()
+    }
+
+    fn ids(table: &str, ids: &[&str]) -> (String, Vec<String>) {
+        (table.to_string(), ids.iter().map(|id| id.to_string()).collect())
+    }
+
+    #[test]
+    fn self_reference() {
+        let dump = "CREATE TABLE `comments` (\n  `id` int NOT NULL,\n  `parent_id` int DEFAULT NULL,\n  \
+            PRIMARY KEY (`id`),\n  \
+            CONSTRAINT `parent` FOREIGN KEY (`parent_id`) REFERENCES `comments` (`id`)\n);\n\
+            INSERT INTO `comments` VALUES (1,NULL),(2,1),(3,2),(4,3),(5,1),(6,NULL);\n";
+        // Its replies in turn, and the comment it replies to, but not that
+        // comment's other replies
+        assert_eq!(subset(dump, "comments.id = 2"), [ids("comments", &["1", "2", "3", "4"])]);
+    }
+
+    #[test]
+    fn cycle() {
+        let dump = "CREATE TABLE `a` (\n  `id` int NOT NULL,\n  `b_id` int,\n  \
+            CONSTRAINT `a_b` FOREIGN KEY (`b_id`) REFERENCES `b` (`id`)\n);\n\
+            CREATE TABLE `b` (\n  `id` int NOT NULL,\n  `a_id` int,\n  \
+            CONSTRAINT `b_a` FOREIGN KEY (`a_id`) REFERENCES `a` (`id`)\n);\n\
+            INSERT INTO `a` VALUES (1,10),(2,20),(3,NULL);\n\
+            INSERT INTO `b` VALUES (10,1),(20,2),(30,1);\n";
+        assert_eq!(subset(dump, "a.id = 1"), [ids("a", &["1"]), ids("b", &["10", "30"])]);
+        // Through b 20 to a 2, which references it back
+        assert_eq!(subset(dump, "b.id = 20"), [ids("a", &["2"]), ids("b", &["20"])]);
+    }
+}
@@ -0,0 +1,1004 @@
+//! Rewrites applied to statements as a dump is extracted.
+
+use std::borrow::Cow;
+use std::io;
+use std::ops::Range;
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::anonymize::PseudonymState;
+use crate::dump::{classify_statement, contains, starts_with_ignore_case, strip_conditional_comment, strip_keywords, StatementKind};
+use crate::filter::RowFilters;
+use crate::latest::KeepLatest;
+use crate::rename::Renames;
+use crate::row::RowTransformers;
+use crate::schema::{parse_create_table, table_options};
+use crate::scrub::Scrubs;
+use crate::strict::StrictMode;
+
+/// The rewrites to apply while extracting.
+#[derive(Debug, Default)]
+pub struct Transforms {
+    pub renames: Renames,
+    pub drop_rows: RowFilters,
+    /// Keep only the newest rows of each table
+    pub keep_latest: KeepLatest,
+    pub strict_mode: StrictMode,
+    pub scrubs: Scrubs,
+    /// A library user's own rewrites of rows, run after the scrubs
+    pub rows: RowTransformers,
+    pub engine: Option<SetEngine>,
+    pub strip_options: Option<StripTableOptions>,
+    pub charset: Option<ConvertCharset>,
+    pub strip_definers: bool,
+    pub sql_security: SqlSecurity,
+    pub ddl_mode: DdlMode,
+    pub insert_mode: Option<InsertMode>,
+    /// Drop comment lines and blank lines
+    pub strip_comments: bool,
+    /// Resolve `/*!NNNNN ... */` comments for this server version: unwrap
+    /// the ones it would run and remove the rest
+    pub unwrap_conditional: Option<u32>,
+    /// Load each table's rows in one transaction, without unique or foreign
+    /// key checks
+    pub fast_restore: bool,
+    pub fk_checks: FkChecks,
+    /// Drop statements by their type, whatever their table
+    pub statements: Option<StatementFilter>,
+}
+
+/// How the `CREATE TABLE` of each table is guarded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DdlMode {
+    /// As dumped, usually after a `DROP TABLE IF EXISTS`
+    #[default]
+    Drop,
+    /// No `DROP TABLE`, and `CREATE TABLE IF NOT EXISTS`
+    IfNotExists,
+    /// No `DROP TABLE`, and a plain `CREATE TABLE`
+    Unguarded,
+}
+
+impl FromStr for DdlMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(DdlMode::Drop),
+            "if-not-exists" => Ok(DdlMode::IfNotExists),
+            "none" => Ok(DdlMode::Unguarded),
+            _ => Err(format!("expected drop, if-not-exists or none, got `{}`", s)),
+        }
+    }
+}
+
+/// Switch tables to a different storage engine.
+#[derive(Debug, Clone)]
+pub struct SetEngine {
+    pub engine: String,
+    /// Only tables matching this, if given
+    pub tables: Option<Regex>,
+}
+
+impl FromStr for SetEngine {
+    type Err = String;
+
+    /// Parse `ENGINE` or `ENGINE:TABLE_REGEX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (engine, tables) = match s.split_once(':') {
+            Some((engine, tables)) => (engine, Some(Regex::new(tables).map_err(|e| e.to_string())?)),
+            None => (s, None),
+        };
+        if engine.is_empty() || !engine.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            return Err(format!("invalid storage engine `{}`", engine));
+        }
+        Ok(SetEngine { engine: engine.to_string(), tables })
+    }
+}
+
+/// Remove table options, such as `ROW_FORMAT`, from `CREATE TABLE`s.
+#[derive(Debug, Clone)]
+pub struct StripTableOptions {
+    /// Upper-cased option names, where `CHARSET` also matches `DEFAULT CHARSET`
+    pub options: Vec<String>,
+    /// Only tables matching this, if given
+    pub tables: Option<Regex>,
+}
+
+impl FromStr for StripTableOptions {
+    type Err = String;
+
+    /// Parse `OPTION,OPTION...` or `OPTION,OPTION...:TABLE_REGEX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (options, tables) = match s.split_once(':') {
+            Some((options, tables)) => (options, Some(Regex::new(tables).map_err(|e| e.to_string())?)),
+            None => (s, None),
+        };
+        let options: Vec<String> = options.split(',').map(|option| option.trim().to_ascii_uppercase()).collect();
+        let valid = |option: &String| {
+            !option.is_empty() && option.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b' ')
+        };
+        if let Some(option) = options.iter().find(|option| !valid(option)) {
+            return Err(format!("invalid table option `{}`", option));
+        }
+        Ok(StripTableOptions { options, tables })
+    }
+}
+
+/// Convert `CHARACTER SET` / `CHARSET` and `COLLATE` clauses to a charset.
+#[derive(Debug, Clone)]
+pub struct ConvertCharset {
+    pub charset: String,
+    /// Collation to use, in place of the matching collation of `charset`
+    pub collation: Option<String>,
+}
+
+impl FromStr for ConvertCharset {
+    type Err = String;
+
+    /// Parse `CHARSET` or `CHARSET:COLLATION`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (charset, collation) = match s.split_once(':') {
+            Some((charset, collation)) => (charset, Some(collation)),
+            None => (s, None),
+        };
+        let valid = |name: &str| !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if !valid(charset) || !collation.is_none_or(valid) {
+            return Err(format!("expected CHARSET[:COLLATION], got `{}`", s));
+        }
+        if collation.is_none() && default_collation(charset).is_none() {
+            return Err(format!("unknown charset `{}`, so the collation to use is needed too, as `{}:COLLATION`", charset, charset));
+        }
+        Ok(ConvertCharset { charset: charset.to_string(), collation: collation.map(str::to_string) })
+    }
+}
+
+/// The collations of the Unicode charsets, after the charset's name and `_`
+const UNICODE_COLLATIONS: &[&str] = &[
+    "general_ci",
+    "bin",
+    "unicode_ci",
+    "unicode_520_ci",
+    "icelandic_ci",
+    "latvian_ci",
+    "romanian_ci",
+    "slovenian_ci",
+    "polish_ci",
+    "estonian_ci",
+    "spanish_ci",
+    "swedish_ci",
+    "turkish_ci",
+    "czech_ci",
+    "danish_ci",
+    "lithuanian_ci",
+    "slovak_ci",
+    "spanish2_ci",
+    "roman_ci",
+    "persian_ci",
+    "esperanto_ci",
+    "hungarian_ci",
+    "sinhala_ci",
+    "german2_ci",
+    "croatian_ci",
+    "vietnamese_ci",
+];
+
+/// The charsets converted to without a collation given, with their default
+/// collation as on every MySQL and MariaDB version (MySQL 8's
+/// `utf8mb4_0900_ai_ci` isn't on the others), and their other collations.
+const CHARSETS: &[(&str, &str, &[&str])] = &[
+    ("utf8mb4", "general_ci", UNICODE_COLLATIONS),
+    ("utf8mb3", "general_ci", UNICODE_COLLATIONS),
+    ("utf8", "general_ci", UNICODE_COLLATIONS),
+    ("ucs2", "general_ci", UNICODE_COLLATIONS),
+    ("utf16", "general_ci", UNICODE_COLLATIONS),
+    ("utf32", "general_ci", UNICODE_COLLATIONS),
+    (
+        "latin1",
+        "swedish_ci",
+        &["bin", "danish_ci", "general_ci", "general_cs", "german1_ci", "german2_ci", "spanish_ci"],
+    ),
+    ("ascii", "general_ci", &["bin"]),
+];
+
+/// MySQL 8's collations of `utf8mb4` besides the Unicode ones
+const UTF8MB4_0900_COLLATIONS: &[&str] = &["0900_ai_ci", "0900_as_ci", "0900_as_cs", "0900_bin"];
+
+/// The default collation of a charset, if it's one of [`CHARSETS`].
+fn default_collation(charset: &str) -> Option<String> {
+    let (name, default, _) = CHARSETS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(charset))?;
+    Some(format!("{}_{}", name, default))
+}
+
+/// The collation of a charset with a suffix, such as `unicode_ci`, if that
+/// collation exists.
+fn collation_with_suffix(charset: &str, suffix: &str) -> Option<String> {
+    let (name, default, others) = CHARSETS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(charset))?;
+    let mysql8 = *name == "utf8mb4" && UTF8MB4_0900_COLLATIONS.iter().any(|known| known.eq_ignore_ascii_case(suffix));
+    let known = suffix.eq_ignore_ascii_case(default) || others.iter().any(|known| known.eq_ignore_ascii_case(suffix));
+    (known || mysql8).then(|| format!("{}_{}", name, suffix.to_ascii_lowercase()))
+}
+
+/// What to do with the dump's `SET FOREIGN_KEY_CHECKS` statements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FkChecks {
+    /// As dumped
+    #[default]
+    Keep,
+    /// Also turn the checks off around the whole output
+    Disable,
+    /// Remove them
+    Strip,
+}
+
+impl FromStr for FkChecks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(FkChecks::Keep),
+            "disable" => Ok(FkChecks::Disable),
+            "strip" => Ok(FkChecks::Strip),
+            _ => Err(format!("expected keep, disable or strip, got `{}`", s)),
+        }
+    }
+}
+
+/// What to do with the `SQL SECURITY` clauses of views and routines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SqlSecurity {
+    /// As dumped
+    #[default]
+    Keep,
+    /// Rewrite `SQL SECURITY DEFINER` to `SQL SECURITY INVOKER`, so the
+    /// objects run with the privileges of whoever uses them
+    Invoker,
+    /// Remove them, so the server's default applies
+    Strip,
+}
+
+impl FromStr for SqlSecurity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(SqlSecurity::Keep),
+            "invoker" => Ok(SqlSecurity::Invoker),
+            "strip" => Ok(SqlSecurity::Strip),
+            _ => Err(format!("expected keep, invoker or strip, got `{}`", s)),
+        }
+    }
+}
+
+/// The verb to write data statements with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertMode {
+    Insert,
+    InsertIgnore,
+    Replace,
+}
+
+impl FromStr for InsertMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(InsertMode::Insert),
+            "insert-ignore" => Ok(InsertMode::InsertIgnore),
+            "replace" => Ok(InsertMode::Replace),
+            _ => Err(format!("expected insert, insert-ignore or replace, got `{}`", s)),
+        }
+    }
+}
+
+/// A type of statement, for picking statements by type whatever their table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementType {
+    /// `CREATE TABLE`
+    Create,
+    /// `DROP TABLE`
+    Drop,
+    /// `INSERT` and `REPLACE`
+    Insert,
+    /// `LOCK TABLES` and `UNLOCK TABLES`
+    Lock,
+    Set,
+    /// `ALTER TABLE ... DISABLE KEYS` and `ENABLE KEYS`
+    Keys,
+    /// `CREATE VIEW` and `DROP VIEW`
+    View,
+    Trigger,
+    /// Procedures, functions and events
+    Routine,
+    /// `CREATE DATABASE` and `USE`
+    Database,
+    /// Comment lines and blank lines
+    Comment,
+    /// Anything else
+    Other,
+}
+
+impl StatementType {
+    /// The type of a statement.
+    pub fn of(text: &[u8]) -> Self {
+        if is_comment(text) {
+            return StatementType::Comment;
+        }
+        let statement = strip_conditional_comment(text.trim_ascii_start());
+        if strip_keywords(statement, &["USE"]).is_some()
+            || strip_keywords(statement, &["CREATE", "DATABASE"]).is_some()
+            || strip_keywords(statement, &["CREATE", "SCHEMA"]).is_some()
+        {
+            return StatementType::Database;
+        }
+        match classify_statement(text) {
+            StatementKind::CreateTable(_) => StatementType::Create,
+            StatementKind::DropTable(_) => StatementType::Drop,
+            StatementKind::Insert(_) | StatementKind::Replace(_) => StatementType::Insert,
+            StatementKind::Lock(_) | StatementKind::Unlock => StatementType::Lock,
+            StatementKind::Set => StatementType::Set,
+            StatementKind::ViewDef(_) => StatementType::View,
+            StatementKind::Trigger(_) => StatementType::Trigger,
+            StatementKind::Routine(_) => StatementType::Routine,
+            _ if alters_keys(statement) => StatementType::Keys,
+            _ => StatementType::Other,
+        }
+    }
+}
+
+impl FromStr for StatementType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "CREATE" => Ok(StatementType::Create),
+            "DROP" => Ok(StatementType::Drop),
+            "INSERT" => Ok(StatementType::Insert),
+            "LOCK" => Ok(StatementType::Lock),
+            "SET" => Ok(StatementType::Set),
+            "KEYS" => Ok(StatementType::Keys),
+            "VIEW" => Ok(StatementType::View),
+            "TRIGGER" => Ok(StatementType::Trigger),
+            "ROUTINE" => Ok(StatementType::Routine),
+            "DATABASE" => Ok(StatementType::Database),
+            "COMMENT" => Ok(StatementType::Comment),
+            "OTHER" => Ok(StatementType::Other),
+            _ => Err(format!(
+                "unknown statement type `{}`, expected CREATE, DROP, INSERT, LOCK, SET, KEYS, VIEW, TRIGGER, ROUTINE, DATABASE, COMMENT or OTHER",
+                s
+            )),
+        }
+    }
+}
+
+/// Drop statements by their type, or keep only those of some types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementFilter {
+    pub types: Vec<StatementType>,
+    /// Keep only statements of these types, rather than dropping them
+    pub only: bool,
+}
+
+impl StatementFilter {
+    /// Whether a statement is kept.
+    pub fn keeps(&self, text: &[u8]) -> bool {
+        self.types.contains(&StatementType::of(text)) == self.only
+    }
+}
+
+impl FromStr for StatementFilter {
+    type Err = String;
+
+    /// Parse `TYPE,TYPE...`, of types to drop.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let types = s.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        Ok(StatementFilter { types, only: false })
+    }
+}
+
+/// Parse a server version as `8.0.32` or as in conditional comments, `80032`.
+pub fn parse_server_version(s: &str) -> Result<u32, String> {
+    let invalid = || format!("expected a version such as 8.0.32 or 80032, got `{}`", s);
+    if !s.contains('.') {
+        return s.parse().map_err(|_| invalid());
+    }
+    let mut parts = s.split('.').map(|part| part.parse::<u32>().ok().filter(|part| *part < 100));
+    let mut part = || parts.next().unwrap_or(Some(0)).ok_or_else(invalid);
+    let version = part()? * 10000 + part()? * 100 + part()?;
+    match parts.next() {
+        Some(_) => Err(invalid()),
+        None => Ok(version),
+    }
+}
+
+/// What the transforms have learned from a dump that changes how they rewrite
+/// what comes after, saved in a [`Checkpoint`](crate::extract::Checkpoint)
+/// so a resumed extract writes the same as one that wasn't stopped.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransformState {
+    /// Databases named so far, whose qualifiers the renames strip
+    pub databases: Vec<String>,
+    /// Tables and views named so far, which the renames lowercase
+    pub tables: Vec<String>,
+    /// What the scrubs pick fakes, hashes and random date shifts with, once
+    /// they've picked any
+    pub pseudonyms: Option<PseudonymState>,
+}
+
+impl Transforms {
+    /// What they've learned from the dump so far, for a checkpoint.
+    pub fn state(&self) -> TransformState {
+        let (databases, tables) = self.renames.seen();
+        TransformState { databases, tables, pseudonyms: self.scrubs.pseudonym_state() }
+    }
+
+    /// Carry on from what [`state`](Self::state) had learned.
+    pub fn resume(&mut self, state: TransformState) {
+        self.renames.resume(state.databases, state.tables);
+        if let Some(pseudonyms) = state.pseudonyms {
+            self.scrubs.resume_pseudonyms(pseudonyms);
+        }
+    }
+
+    /// Rewrite a statement, or return `None` to drop it. Table scoped rewrites
+    /// match the table's original name.
+    pub fn apply<'a>(&mut self, text: &'a [u8]) -> io::Result<Option<Cow<'a, [u8]>>> {
+        let mut text = Cow::Borrowed(text);
+
+        if self.strip_comments && is_comment(&text) {
+            return Ok(None);
+        }
+        if self.statements.as_ref().is_some_and(|filter| !filter.keeps(&text)) {
+            return Ok(None);
+        }
+        if self.fk_checks == FkChecks::Strip && sets_fk_checks(&text) {
+            return Ok(None);
+        }
+        if let Some(version) = self.unwrap_conditional {
+            if contains(&text, b"/*!") {
+                text = match unwrap_conditional(text, version) {
+                    Some(text) => text,
+                    None => return Ok(None),
+                };
+            }
+        }
+
+        if self.ddl_mode != DdlMode::Drop {
+            text = match self.ddl_mode.apply(text) {
+                Some(text) => text,
+                None => return Ok(None),
+            };
+        }
+
+        if let Some(set_engine) = &self.engine {
+            if let Some(schema) = parse_create_table(&text) {
+                if set_engine.tables.as_ref().is_none_or(|tables| tables.is_match(&schema.name)) {
+                    text = set_engine.apply(text);
+                }
+            }
+        }
+
+        if let Some(strip) = &self.strip_options {
+            if let Some(schema) = parse_create_table(&text) {
+                if strip.tables.as_ref().is_none_or(|tables| tables.is_match(&schema.name)) {
+                    text = strip.apply(text);
+                }
+            }
+        }
+
+        if let Some(convert) = &self.charset {
+            let body = strip_conditional_comment(text.trim_ascii_start());
+            if strip_keywords(body, &["CREATE", "TABLE"]).is_some() || strip_keywords(body, &["CREATE", "DATABASE"]).is_some() {
+                text = convert.apply(text);
+            }
+        }
+
+        if let Some(mode) = self.insert_mode {
+            text = mode.apply(text);
+        }
+
+        if self.strip_definers && contains(&text, b"DEFINER") {
+            text = strip_definers(text);
+        }
+
+        if self.sql_security != SqlSecurity::Keep && contains(&text, b"SECURITY") {
+            text = self.sql_security.apply(text);
+        }
+
+        let latest = match self.keep_latest.rewrite(&text) {
+            None => return Ok(None),
+            Some(Cow::Borrowed(_)) => None,
+            Some(Cow::Owned(latest)) => Some(latest),
+        };
+        if let Some(latest) = latest {
+            text = Cow::Owned(latest);
+        }
+
+        let kept = match self.drop_rows.rewrite(&text)? {
+            None => return Ok(None),
+            Some(Cow::Borrowed(_)) => None,
+            Some(Cow::Owned(kept)) => Some(kept),
+        };
+        if let Some(kept) = kept {
+            text = Cow::Owned(kept);
+        }
+
+        if let Cow::Owned(scrubbed) = self.scrubs.rewrite(&text)? {
+            text = Cow::Owned(scrubbed);
+        }
+
+        // After the scrubs, so shifted dates aren't the zero dates' stand-ins
+        if let Cow::Owned(fixed) = self.strict_mode.rewrite(&text) {
+            text = Cow::Owned(fixed);
+        }
+
+        let transformed = match self.rows.rewrite(&text)? {
+            None => return Ok(None),
+            Some(Cow::Borrowed(_)) => None,
+            Some(Cow::Owned(transformed)) => Some(transformed),
+        };
+        if let Some(transformed) = transformed {
+            text = Cow::Owned(transformed);
+        }
+
+        let renamed = match self.renames.rewrite(&text) {
+            None => return Ok(None),
+            Some(Cow::Borrowed(_)) => None,
+            Some(Cow::Owned(renamed)) => Some(renamed),
+        };
+        Ok(Some(renamed.map_or(text, Cow::Owned)))
+    }
+
+    /// Whether [`apply`](Self::apply) only rewrites the start of an
+    /// `INSERT`, up to its rows, so an extract can write the rows of one too
+    /// long to gather as they're read.
+    pub fn keeps_rows(&self) -> bool {
+        self.drop_rows.is_empty()
+            && self.keep_latest.is_empty()
+            && self.strict_mode.is_empty()
+            && self.scrubs.is_empty()
+            && self.rows.is_empty()
+            && self.unwrap_conditional.is_none()
+            && !self.strip_definers
+            && self.sql_security == SqlSecurity::Keep
+    }
+}
+
+impl DdlMode {
+    /// Only unwrapped `DROP TABLE`s are dropped: the ones in conditional
+    /// comments remove a view's stand-in table before the view is created.
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Option<Cow<'a, [u8]>> {
+        if strip_keywords(&text, &["DROP", "TABLE"]).is_some() {
+            return None;
+        }
+        if *self == DdlMode::IfNotExists && strip_keywords(&text, &["CREATE", "TABLE", "IF", "NOT", "EXISTS"]).is_none() {
+            if let Some(rest) = strip_keywords(&text, &["CREATE", "TABLE"]) {
+                let at = text.len() - rest.len();
+                let mut rewritten = text[..at].to_vec();
+                rewritten.extend_from_slice(b"IF NOT EXISTS ");
+                rewritten.extend_from_slice(&text[at..]);
+                return Some(Cow::Owned(rewritten));
+            }
+        }
+        Some(text)
+    }
+}
+
+impl InsertMode {
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let Some(rest) = strip_keywords(&text, &["INSERT", "IGNORE", "INTO"])
+            .or_else(|| strip_keywords(&text, &["INSERT", "INTO"]))
+            .or_else(|| strip_keywords(&text, &["REPLACE", "INTO"]))
+        else {
+            return text;
+        };
+        let verb: &[u8] = match self {
+            InsertMode::Insert => b"INSERT INTO ",
+            InsertMode::InsertIgnore => b"INSERT IGNORE INTO ",
+            InsertMode::Replace => b"REPLACE INTO ",
+        };
+        let start = text.len() - text.trim_ascii_start().len();
+        let end = text.len() - rest.len();
+        if text[start..end] == *verb {
+            return text;
+        }
+        let mut rewritten = Vec::with_capacity(text.len() + verb.len());
+        rewritten.extend_from_slice(&text[..start]);
+        rewritten.extend_from_slice(verb);
+        rewritten.extend_from_slice(&text[end..]);
+        Cow::Owned(rewritten)
+    }
+}
+
+impl SetEngine {
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        match table_options(&text).into_iter().find(|option| option.name == "ENGINE") {
+            Some(option) if !option.value.eq_ignore_ascii_case(&self.engine) => {
+                let mut rewritten = text[..option.range.start].to_vec();
+                rewritten.extend_from_slice(format!("ENGINE={}", self.engine).as_bytes());
+                rewritten.extend_from_slice(&text[option.range.end..]);
+                Cow::Owned(rewritten)
+            }
+            _ => text,
+        }
+    }
+}
+
+impl StripTableOptions {
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let removals: Vec<_> = table_options(&text)
+            .into_iter()
+            .filter(|option| {
+                let name = option.name.strip_prefix("DEFAULT ").unwrap_or(&option.name);
+                self.options.iter().any(|strip| *strip == option.name || strip == name)
+            })
+            .map(|option| option.range)
+            .collect();
+        if removals.is_empty() {
+            return text;
+        }
+        let mut rewritten = Vec::with_capacity(text.len());
+        let mut position = 0;
+        for range in removals {
+            rewritten.extend_from_slice(text[position..range.start].trim_ascii_end());
+            position = range.end;
+        }
+        rewritten.extend_from_slice(&text[position..]);
+        Cow::Owned(rewritten)
+    }
+}
+
+impl ConvertCharset {
+    /// Rewrite the clauses in a `CREATE TABLE` or `CREATE DATABASE`.
+    ///
+    /// Every charset other than `binary` is converted. Collations become the
+    /// requested one, except `_bin` collations which stay binary; without a
+    /// requested collation, a collation keeps its suffix if the charset has a
+    /// collation with it, so `utf8_unicode_ci` becomes `utf8mb4_unicode_ci`,
+    /// and becomes the charset's default otherwise. A table or database
+    /// default charset with no collation next to it gets the requested one
+    /// added.
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let clauses = charset_clauses(&text);
+        let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+        for (i, (keyword, range)) in clauses.iter().enumerate() {
+            let value = String::from_utf8_lossy(&text[range.clone()]).into_owned();
+            if value.eq_ignore_ascii_case("binary") {
+                continue;
+            }
+            if *keyword == "COLLATE" {
+                let suffix = value.split_once('_').map_or("", |(_, suffix)| suffix);
+                let same_suffix = collation_with_suffix(&self.charset, suffix);
+                let collation = match &self.collation {
+                    Some(collation) if suffix.eq_ignore_ascii_case("bin") => same_suffix.unwrap_or_else(|| collation.clone()),
+                    Some(collation) => collation.clone(),
+                    None => match same_suffix.or_else(|| default_collation(&self.charset)) {
+                        Some(collation) => collation,
+                        // A charset whose collations aren't known, so it's left as it is
+                        None => continue,
+                    },
+                };
+                if !collation.eq_ignore_ascii_case(&value) {
+                    replacements.push((range.clone(), collation));
+                }
+                continue;
+            }
+
+            if !value.eq_ignore_ascii_case(&self.charset) {
+                replacements.push((range.clone(), self.charset.clone()));
+            }
+            // Columns without a collation pick up the table's, so only the
+            // defaults need one added
+            let has_collation = clauses.get(i + 1).is_some_and(|(next, next_range)| {
+                let between = text[range.end..next_range.start].trim_ascii();
+                let between = strip_keywords(between, &["DEFAULT"]).unwrap_or(between);
+                *next == "COLLATE" && strip_keywords(between, &["COLLATE"]).is_some_and(|rest| matches!(rest.trim_ascii(), b"" | b"="))
+            });
+            if *keyword == "DEFAULT CHARSET" && !has_collation {
+                if let Some(collation) = &self.collation {
+                    // Match `CHARSET=x` or `CHARACTER SET x`
+                    let separator = if text[..range.start].trim_ascii_end().ends_with(b"=") { "=" } else { " " };
+                    replacements.push((range.end..range.end, format!(" COLLATE{}{}", separator, collation)));
+                }
+            }
+        }
+
+        if replacements.is_empty() {
+            return text;
+        }
+        let mut rewritten = Vec::with_capacity(text.len());
+        let mut position = 0;
+        for (range, value) in replacements {
+            rewritten.extend_from_slice(&text[position..range.start]);
+            rewritten.extend_from_slice(value.as_bytes());
+            position = range.end;
+        }
+        rewritten.extend_from_slice(&text[position..]);
+        Cow::Owned(rewritten)
+    }
+}
+
+/// The `CHARACTER SET`, `CHARSET` and `COLLATE` clauses of a statement outside
+/// string literals, as the clause (`DEFAULT CHARSET` when at table or database
+/// level) and the range of its value.
+fn charset_clauses(text: &[u8]) -> Vec<(&'static str, Range<usize>)> {
+    let mut clauses = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(text, i),
+            b if b.is_ascii_alphabetic() && (i == 0 || !(text[i - 1].is_ascii_alphanumeric() || text[i - 1] == b'_')) => {
+                let start = i;
+                while i < text.len() && (text[i].is_ascii_alphanumeric() || text[i] == b'_') {
+                    i += 1;
+                }
+                let word = &text[start..i];
+                let rest = &text[i..];
+                let after = if word.eq_ignore_ascii_case(b"CHARSET") || word.eq_ignore_ascii_case(b"COLLATE") {
+                    Some(rest)
+                } else if word.eq_ignore_ascii_case(b"CHARACTER") {
+                    strip_keywords(rest, &["SET"])
+                } else {
+                    None
+                };
+                let Some(after) = after else { continue };
+                let after = after.trim_ascii_start();
+                let after = after.strip_prefix(b"=").unwrap_or(after).trim_ascii_start();
+                let value_start = text.len() - after.len();
+                let value_len = after.iter().take_while(|b| b.is_ascii_alphanumeric() || **b == b'_').count();
+                if value_len == 0 {
+                    continue;
+                }
+                let before = text[..start].trim_ascii_end();
+                let is_default = before.len() >= 7 && before[before.len() - 7..].eq_ignore_ascii_case(b"DEFAULT");
+                let clause = match (word.eq_ignore_ascii_case(b"COLLATE"), is_default) {
+                    (true, _) => "COLLATE",
+                    (false, true) => "DEFAULT CHARSET",
+                    (false, false) => "CHARSET",
+                };
+                clauses.push((clause, value_start..value_start + value_len));
+                i = value_start + value_len;
+            }
+            _ => i += 1,
+        }
+    }
+    clauses
+}
+
+/// Remove `` DEFINER=`user`@`host` `` clauses outside string literals, and the
+/// conditional comment around one when it's all the comment holds, as in a
+/// trigger's `` /*!50017 DEFINER=`root`@`localhost`*/ ``.
+fn strip_definers(text: Cow<'_, [u8]>) -> Cow<'_, [u8]> {
+    let mut removals: Vec<Range<usize>> = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(&text, i),
+            b'D' | b'd' if starts_with_ignore_case(&text[i..], b"DEFINER") && (i == 0 || !is_word_byte(text[i - 1])) => {
+                let start = i;
+                i += b"DEFINER".len();
+                let Some(rest) = text[i..].trim_ascii_start().strip_prefix(b"=") else { continue };
+                let value_start = text.len() - rest.trim_ascii_start().len();
+                let Some(value_end) = definer_end(&text, value_start) else { continue };
+                let end = value_end + (text[value_end..].len() - text[value_end..].trim_ascii_start().len());
+                i = end;
+
+                // `/*!50017 DEFINER=...*/`: drop the whole comment
+                let comment_start = start.checked_sub(9).filter(|&opening| {
+                    let opening = &text[opening..start];
+                    opening.starts_with(b"/*!") && opening[3..8].iter().all(u8::is_ascii_digit) && opening[8] == b' '
+                });
+                match comment_start {
+                    Some(opening) if text[end..].starts_with(b"*/") => {
+                        let after = end + 2;
+                        let after = after + (text[after..].len() - text[after..].trim_ascii_start().len());
+                        removals.push(opening..after);
+                        i = after;
+                    }
+                    _ => removals.push(start..end),
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    if removals.is_empty() {
+        return text;
+    }
+    let mut rewritten = Vec::with_capacity(text.len());
+    let mut position = 0;
+    for range in removals {
+        rewritten.extend_from_slice(&text[position..range.start]);
+        position = range.end;
+    }
+    rewritten.extend_from_slice(&text[position..]);
+    Cow::Owned(rewritten)
+}
+
+impl SqlSecurity {
+    /// Rewrite or remove the `SQL SECURITY` clauses outside string literals,
+    /// removing the conditional comment around one when it's all the comment
+    /// holds, as in a view's `/*!50013 SQL SECURITY DEFINER */` once its
+    /// definer is stripped.
+    fn apply<'a>(&self, text: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let mut replacements: Vec<(Range<usize>, &[u8])> = Vec::new();
+        let mut i = 0;
+        while i < text.len() {
+            match text[i] {
+                b'\'' | b'"' | b'`' => i = skip_quoted(&text, i),
+                b'S' | b's' if starts_with_ignore_case(&text[i..], b"SQL") && (i == 0 || !is_word_byte(text[i - 1])) => {
+                    let start = i;
+                    i += b"SQL".len();
+                    let Some(rest) = strip_keywords(&text[i..], &["SECURITY"]) else { continue };
+                    let value = text.len() - rest.len();
+                    let is_definer = strip_keywords(rest, &["DEFINER"]).is_some();
+                    if !is_definer && strip_keywords(rest, &["INVOKER"]).is_none() {
+                        continue;
+                    }
+                    let end = value + b"DEFINER".len();
+                    i = end;
+                    match self {
+                        SqlSecurity::Keep => {}
+                        SqlSecurity::Invoker if is_definer => replacements.push((value..end, b"INVOKER")),
+                        SqlSecurity::Invoker => {}
+                        SqlSecurity::Strip => {
+                            let rest = text[end..].trim_ascii_start();
+                            // `/*!50013 SQL SECURITY DEFINER */`: drop the whole comment
+                            let comment_start = start.checked_sub(9).filter(|&opening| {
+                                let opening = &text[opening..start];
+                                opening.starts_with(b"/*!") && opening[3..8].iter().all(u8::is_ascii_digit) && opening[8] == b' '
+                            });
+                            match comment_start {
+                                Some(opening) if rest.starts_with(b"*/") => {
+                                    let after = &rest[2..];
+                                    let after = text.len() - after.len() + (after.len() - after.trim_ascii_start().len());
+                                    replacements.push((opening..after, b""));
+                                    i = after;
+                                }
+                                // Along with the whitespace before it, keeping what follows on its line
+                                _ => {
+                                    let start = text[..start].trim_ascii_end().len();
+                                    replacements.push((start..end, b""));
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+
+        if replacements.is_empty() {
+            return text;
+        }
+        let mut rewritten = Vec::with_capacity(text.len());
+        let mut position = 0;
+        for (range, replacement) in replacements {
+            rewritten.extend_from_slice(&text[position..range.start]);
+            rewritten.extend_from_slice(replacement);
+            position = range.end;
+        }
+        rewritten.extend_from_slice(&text[position..]);
+        Cow::Owned(rewritten)
+    }
+}
+
+/// End of the `user@host` (or `CURRENT_USER`) definer starting at `start`.
+fn definer_end(text: &[u8], start: usize) -> Option<usize> {
+    let part_end = |i: usize| match text.get(i)? {
+        b'`' | b'\'' | b'"' => Some(skip_quoted(text, i)),
+        _ => {
+            let len = text[i..].iter().take_while(|b| is_word_byte(**b) || matches!(b, b'%' | b'.' | b'-')).count();
+            (len > 0).then_some(i + len)
+        }
+    };
+    let user_end = part_end(start)?;
+    if starts_with_ignore_case(&text[start..], b"CURRENT_USER") {
+        return Some(if text[user_end..].starts_with(b"()") { user_end + 2 } else { user_end });
+    }
+    if text.get(user_end) != Some(&b'@') {
+        return None;
+    }
+    part_end(user_end + 1)
+}
+
+/// Position just past the quoted string or identifier starting at `start`.
+fn skip_quoted(text: &[u8], start: usize) -> usize {
+    let quote = text[start];
+    let mut i = start + 1;
+    while i < text.len() && text[i] != quote {
+        if text[i] == b'\\' && quote != b'`' {
+            i += 1;
+        }
+        i += 1;
+    }
+    (i + 1).min(text.len())
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+fn sets_fk_checks(text: &[u8]) -> bool {
+    let body = strip_conditional_comment(text.trim_ascii_start());
+    strip_keywords(body, &["SET"]).is_some() && text.windows(18).any(|w| w.eq_ignore_ascii_case(b"FOREIGN_KEY_CHECKS"))
+}
+
+/// Whether a statement is a comment or blank line. Comments inside statements
+/// (such as in routine bodies) aren't touched.
+fn is_comment(text: &[u8]) -> bool {
+    let text = text.trim_ascii();
+    text.is_empty() || text.starts_with(b"--") || text.starts_with(b"#") || (text.starts_with(b"/*") && !text.starts_with(b"/*!"))
+}
+
+/// Whether a statement, out of any version comment, is an `ALTER TABLE`
+/// turning a table's keys off or on around loading its rows.
+pub(crate) fn alters_keys(statement: &[u8]) -> bool {
+    strip_keywords(statement, &["ALTER", "TABLE"]).is_some_and(|rest| {
+        let rest = rest.trim_ascii_end();
+        let rest = rest.strip_suffix(b";").unwrap_or(rest).trim_ascii_end();
+        let rest = rest.strip_suffix(b"*/").unwrap_or(rest).trim_ascii_end();
+        rest.ends_with(b"DISABLE KEYS") || rest.ends_with(b"ENABLE KEYS")
+    })
+}
+
+/// Replace each `/*!NNNNN ... */` comment outside string literals with its
+/// contents if `version` would run it, or remove it. Returns `None` if nothing
+/// is left of the statement.
+fn unwrap_conditional(text: Cow<'_, [u8]>, version: u32) -> Option<Cow<'_, [u8]>> {
+    let mut rewritten = Vec::with_capacity(text.len());
+    let mut position = 0;
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(&text, i),
+            b'/' if text[i..].starts_with(b"/*!") => {
+                let digits = text[i + 3..].iter().take_while(|b| b.is_ascii_digit()).count();
+                let content_start = i + 3 + digits;
+                let Some(content_end) = comment_end(&text, content_start) else { break };
+                let runs = digits == 0
+                    || std::str::from_utf8(&text[i + 3..content_start])
+                        .ok()
+                        .and_then(|gate| gate.parse::<u32>().ok())
+                        .is_some_and(|gate| gate <= version);
+                rewritten.extend_from_slice(&text[position..i]);
+                i = content_end + 2;
+                if runs {
+                    rewritten.extend_from_slice(text[content_start..content_end].trim_ascii());
+                } else {
+                    // Along with the space separating it from what follows
+                    i += text[i..].len() - text[i..].trim_ascii_start().len();
+                    if text[i..].starts_with(b";") || text[i..].is_empty() {
+                        let trailing = rewritten.len() - rewritten.trim_ascii_end().len();
+                        rewritten.truncate(rewritten.len() - trailing);
+                    }
+                }
+                position = i;
+            }
+            _ => i += 1,
+        }
+    }
+    if position == 0 {
+        return Some(text);
+    }
+    rewritten.extend_from_slice(&text[position..]);
+    if rewritten.iter().all(|b| b.is_ascii_whitespace() || *b == b';') {
+        return None;
+    }
+    Some(Cow::Owned(rewritten))
+}
+
+/// Position of the `*/` closing a comment whose contents start at `start`.
+fn comment_end(text: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i + 1 < text.len() {
+        match text[i] {
+            b'\'' | b'"' | b'`' => i = skip_quoted(text, i),
+            b'*' if text[i + 1] == b'/' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
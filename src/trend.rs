@@ -0,0 +1,57 @@
+//! Following tables' growth across snapshots of a database, from the stats
+//! of a dump of each.
+
+use std::collections::HashMap;
+
+use crate::stats::TableStats;
+
+/// A table's size in each of a series of snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableTrend {
+    pub name: String,
+    /// The table's stats in each snapshot, `None` where it isn't in it
+    pub snapshots: Vec<Option<TableStats>>,
+}
+
+impl TableTrend {
+    /// How much the bytes of the table's `INSERT`s grew from the first
+    /// snapshot to the last, counting a table missing from one as empty.
+    pub fn data_bytes_growth(&self) -> i64 {
+        self.last(|stats| stats.data_bytes) - self.first(|stats| stats.data_bytes)
+    }
+
+    /// How much the table's rows grew from the first snapshot to the last,
+    /// likewise.
+    pub fn rows_growth(&self) -> i64 {
+        self.last(|stats| stats.rows) - self.first(|stats| stats.rows)
+    }
+
+    fn first(&self, value: impl Fn(&TableStats) -> u64) -> i64 {
+        self.snapshots.first().and_then(Option::as_ref).map_or(0, |stats| value(stats) as i64)
+    }
+
+    fn last(&self, value: impl Fn(&TableStats) -> u64) -> i64 {
+        self.snapshots.last().and_then(Option::as_ref).map_or(0, |stats| value(stats) as i64)
+    }
+}
+
+/// Each table's trend across `snapshots`, the stats of each snapshot's dump
+/// in order from oldest to newest, with the tables that grew most by data
+/// bytes first.
+pub fn table_trends(snapshots: &[Vec<TableStats>]) -> Vec<TableTrend> {
+    let mut trends: Vec<TableTrend> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for (snapshot, tables) in snapshots.iter().enumerate() {
+        for table in tables {
+            let index = *indexes.entry(table.name.clone()).or_insert_with(|| {
+                trends.push(TableTrend { name: table.name.clone(), snapshots: vec![None; snapshots.len()] });
+                trends.len() - 1
+            });
+            trends[index].snapshots[snapshot] = Some(table.clone());
+        }
+    }
+    // Stable, so ties stay in the order the tables were first seen
+    trends.sort_by_key(|trend| std::cmp::Reverse(trend.data_bytes_growth()));
+    trends
+}
@@ -0,0 +1,200 @@
+//! Checksumming a dump's rows, and the same rows as the `mysql` client
+//! prints them, to check a restore has every row it should.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+use crate::dump::Statement;
+use crate::extract::{ExtractOptions, TableContext};
+use crate::insert::{parse_insert, parse_value, split_values, Value};
+use crate::shard::fnv1a;
+use crate::visit::{scan_dump, StatementVisitor};
+
+/// A table's rows, and a checksum of them that doesn't depend on their
+/// order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableChecksum {
+    pub name: String,
+    pub rows: u64,
+    pub checksum: u64,
+}
+
+/// Adds up the checksums of rows, each from its values.
+#[derive(Debug, Clone, Default)]
+struct RowChecksum {
+    rows: u64,
+    checksum: u64,
+    /// The row's values, encoded to be hashed
+    buffer: Vec<u8>,
+}
+
+impl RowChecksum {
+    /// Add a row of `values`, `None` being NULL.
+    ///
+    /// The client prints NULL as `NULL`, so a string `'NULL'` counts as
+    /// NULL too, for the two to match.
+    fn push<'v>(&mut self, values: impl IntoIterator<Item = Option<&'v [u8]>>) {
+        self.buffer.clear();
+        for value in values {
+            match value.filter(|value| *value != b"NULL") {
+                Some(value) => {
+                    self.buffer.extend_from_slice(&(value.len() as u64).to_le_bytes());
+                    self.buffer.extend_from_slice(value);
+                }
+                None => self.buffer.extend_from_slice(&u64::MAX.to_le_bytes()),
+            }
+        }
+        self.rows += 1;
+        self.checksum = self.checksum.wrapping_add(fnv1a(&self.buffer));
+    }
+}
+
+/// The rows and checksum of each table (and view) `options` includes, in the
+/// order of the dump, from its `INSERT`s. Only `options`' filters are used,
+/// and they're passed no database and nothing written. Statements are read
+/// whole, however long.
+///
+/// Hex literals, as mysqldump writes with `--hex-blob`, are checksummed as
+/// the bytes they stand for, and other literals as written.
+pub fn checksum_dump<R: BufRead>(reader: R, options: &ExtractOptions) -> io::Result<Vec<TableChecksum>> {
+    struct Checksums<'a> {
+        options: &'a ExtractOptions,
+        tables: Vec<(String, RowChecksum)>,
+        indexes: HashMap<String, Option<usize>>,
+        bytes_read: u64,
+    }
+
+    impl Checksums<'_> {
+        /// The index of `name`'s checksum, if it's included.
+        fn table(&mut self, name: &str) -> Option<usize> {
+            if let Some(&index) = self.indexes.get(name) {
+                return index;
+            }
+            let context = TableContext {
+                name,
+                database: None,
+                bytes_read: self.bytes_read,
+                bytes_written: 0,
+                engine: None,
+                foreign_keys: None,
+            };
+            let index = self.options.keeps(&context).then(|| {
+                self.tables.push((name.to_string(), RowChecksum::default()));
+                self.tables.len() - 1
+            });
+            self.indexes.insert(name.to_string(), index);
+            index
+        }
+    }
+
+    impl StatementVisitor for Checksums<'_> {
+        fn on_table_start(&mut self, table: &str) -> io::Result<()> {
+            self.table(table);
+            Ok(())
+        }
+
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            let Some(index) = self.table(table) else {
+                return Ok(());
+            };
+            let Some(insert) = parse_insert(&statement.text) else {
+                return Ok(());
+            };
+            let rows = &mut self.tables[index].1;
+            for tuple in insert.tuples {
+                let values: Vec<Option<Vec<u8>>> = split_values(tuple).into_iter().map(value_bytes).collect();
+                rows.push(values.iter().map(Option::as_deref));
+            }
+            Ok(())
+        }
+
+        fn on_other(&mut self, statement: &Statement) -> io::Result<()> {
+            self.bytes_read += statement.text.len() as u64;
+            Ok(())
+        }
+
+        fn should_stop(&self) -> bool {
+            self.options.cancel_requested()
+        }
+    }
+
+    let mut checksums = Checksums { options, tables: Vec::new(), indexes: HashMap::new(), bytes_read: 0 };
+    scan_dump(reader, &mut checksums)?;
+    Ok(checksums
+        .tables
+        .into_iter()
+        .map(|(name, rows)| TableChecksum { name, rows: rows.rows, checksum: rows.checksum })
+        .collect())
+}
+
+/// A value's bytes as the client would print them.
+fn value_bytes(raw: &[u8]) -> Option<Vec<u8>> {
+    match parse_value(raw) {
+        Value::Null => None,
+        Value::String(bytes) => Some(bytes),
+        Value::Literal(literal) => Some(decode_hex(literal).unwrap_or_else(|| literal.to_vec())),
+    }
+}
+
+/// The bytes of a hex literal such as `0x616263`.
+fn decode_hex(literal: &[u8]) -> Option<Vec<u8>> {
+    let digits = literal.strip_prefix(b"0x").or_else(|| literal.strip_prefix(b"0X"))?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// The rows and checksum of the rows the `mysql` client prints for a
+/// `SELECT *` with `--batch --skip-column-names`: a line per row, its values
+/// separated by tabs, with tabs, newlines, backslashes and NULs in them
+/// escaped.
+///
+/// They match [`checksum_dump`]'s for a table restored from the dump.
+pub fn checksum_client_rows<R: BufRead>(mut reader: R) -> io::Result<(u64, u64)> {
+    let mut checksum = RowChecksum::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let row = line.strip_suffix(b"\n").unwrap_or(&line);
+        let values: Vec<Vec<u8>> = row.split(|b| *b == b'\t').map(unescape_client).collect();
+        checksum.push(values.iter().map(|value| Some(value.as_slice())));
+    }
+    Ok((checksum.rows, checksum.checksum))
+}
+
+/// A value as the client printed it, with `\0`, `\t`, `\n` and `\\`
+/// unescaped.
+fn unescape_client(value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut escaped = false;
+    for &b in value {
+        if escaped {
+            bytes.push(match b {
+                b'0' => 0,
+                b't' => b'\t',
+                b'n' => b'\n',
+                other => other,
+            });
+            escaped = false;
+        } else if b == b'\\' {
+            escaped = true;
+        } else {
+            bytes.push(b);
+        }
+    }
+    bytes
+}
@@ -0,0 +1,206 @@
+//! Scanning a dump statement by statement with callbacks, for analyzers that
+//! don't want to write their own read loop.
+
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::dump::{parse_identifier_into, strip_conditional_comment, strip_keywords, SectionKind, SectionReader, Statement};
+use crate::extract::cancelled;
+use crate::insert::insert_target;
+
+/// Callbacks for [`scan_dump`], each a no-op unless implemented. Returning an
+/// error stops the scan.
+///
+/// A table's (or view's) statements come between its `on_table_start` and
+/// `on_table_end`, along with the comments before them, such as mysqldump's
+/// `-- Table structure for table` banner.
+pub trait StatementVisitor {
+    /// A table's or view's section begins.
+    fn on_table_start(&mut self, _table: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A table's or view's section is over, as another section or the end
+    /// of the dump has been reached.
+    fn on_table_end(&mut self, _table: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A `CREATE`, `ALTER`, `DROP`, `RENAME` or `TRUNCATE` statement, with the
+    /// table or view whose section it's in, if any.
+    fn on_ddl(&mut self, _table: Option<&str>, _statement: &Statement) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// An `INSERT` or `REPLACE`, with the table it writes to.
+    fn on_insert(&mut self, _table: &str, _statement: &Statement) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Any other statement, comment or blank line.
+    fn on_other(&mut self, _statement: &Statement) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether to stop the scan, checked before each statement, which then
+    /// fails with a [`Cancelled`](crate::extract::Cancelled) error.
+    fn should_stop(&self) -> bool {
+        false
+    }
+
+    /// The next piece of the last statement, if it was
+    /// [`continued`](Statement::continued) as [`scan_dump_in_pieces`] read
+    /// it in pieces.
+    fn on_more(&mut self, _piece: &Statement) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Passes everything to another visitor, stopping once `cancel` is set, as
+/// [`ExtractOptions::cancel_when`](crate::extract::ExtractOptions::cancel_when)
+/// stops an extract.
+pub struct StopWhen<'a, V: ?Sized> {
+    visitor: &'a mut V,
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a, V: StatementVisitor + ?Sized> StopWhen<'a, V> {
+    pub fn new(visitor: &'a mut V, cancel: Option<&'a AtomicBool>) -> Self {
+        StopWhen { visitor, cancel }
+    }
+}
+
+impl<V: StatementVisitor + ?Sized> StatementVisitor for StopWhen<'_, V> {
+    fn on_table_start(&mut self, table: &str) -> io::Result<()> {
+        self.visitor.on_table_start(table)
+    }
+
+    fn on_table_end(&mut self, table: &str) -> io::Result<()> {
+        self.visitor.on_table_end(table)
+    }
+
+    fn on_ddl(&mut self, table: Option<&str>, statement: &Statement) -> io::Result<()> {
+        self.visitor.on_ddl(table, statement)
+    }
+
+    fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+        self.visitor.on_insert(table, statement)
+    }
+
+    fn on_other(&mut self, statement: &Statement) -> io::Result<()> {
+        self.visitor.on_other(statement)
+    }
+
+    fn should_stop(&self) -> bool {
+        self.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) || self.visitor.should_stop()
+    }
+
+    fn on_more(&mut self, piece: &Statement) -> io::Result<()> {
+        self.visitor.on_more(piece)
+    }
+}
+
+/// Read the dump from `reader`, calling `visitor` for each statement in
+/// order.
+pub fn scan_dump<R: BufRead, V: StatementVisitor + ?Sized>(reader: R, visitor: &mut V) -> io::Result<()> {
+    scan(SectionReader::new(reader), visitor)
+}
+
+/// [`scan_dump`], but with statements longer than `max_statement_size` read
+/// in pieces, so as not to hold all of one in memory. Each is passed to the
+/// callback for its first piece, and then to `on_more` piece by piece.
+pub fn scan_dump_in_pieces<R: BufRead, V: StatementVisitor + ?Sized>(
+    reader: R,
+    max_statement_size: usize,
+    visitor: &mut V,
+) -> io::Result<()> {
+    scan(SectionReader::new(reader).max_statement_size(max_statement_size), visitor)
+}
+
+fn scan<R: BufRead, V: StatementVisitor + ?Sized>(mut sections: SectionReader<R>, visitor: &mut V) -> io::Result<()> {
+    let mut current_section = usize::MAX;
+    let mut current_table: Option<String> = None;
+    // The table of each INSERT, reused so they don't allocate
+    let mut insert_table = String::new();
+    let mut continuing = false;
+
+    while let Some(item) = sections.next() {
+        if visitor.should_stop() {
+            return Err(cancelled());
+        }
+        let (index, statement) = item?;
+        if std::mem::replace(&mut continuing, statement.continued) {
+            visitor.on_more(&statement)?;
+            continue;
+        }
+        if index != current_section {
+            current_section = index;
+            if let Some(table) = current_table.take() {
+                visitor.on_table_end(&table)?;
+            }
+            if let SectionKind::Table(name) | SectionKind::View(name) = &sections.sections()[index].kind {
+                visitor.on_table_start(name)?;
+                current_table = Some(name.clone());
+            }
+        }
+
+        let text = statement.text.trim_ascii_start();
+        if insert_target(text).and_then(|rest| parse_identifier_into(rest, &mut insert_table)).is_some() {
+            visitor.on_insert(&insert_table, &statement)?;
+        } else if is_ddl(text) {
+            visitor.on_ddl(current_table.as_deref(), &statement)?;
+        } else {
+            visitor.on_other(&statement)?;
+        }
+    }
+
+    if let Some(table) = current_table {
+        visitor.on_table_end(&table)?;
+    }
+    Ok(())
+}
+
+fn is_ddl(text: &[u8]) -> bool {
+    let text = strip_conditional_comment(text);
+    ["CREATE", "ALTER", "DROP", "RENAME", "TRUNCATE"].iter().any(|verb| strip_keywords(text, &[verb]).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{is_cancelled, ExtractOptions};
+    use crate::stats::compute_table_stats;
+
+    #[test]
+    fn stopping_mid_dump() {
+        /// Counts the inserts, setting `cancel` at the tenth
+        struct Inserts<'a> {
+            count: usize,
+            cancel: &'a AtomicBool,
+        }
+
+        impl StatementVisitor for Inserts<'_> {
+            fn on_insert(&mut self, _table: &str, _statement: &Statement) -> io::Result<()> {
+                self.count += 1;
+                if self.count == 10 {
+                    self.cancel.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        }
+
+        let mut dump = String::from("CREATE TABLE `t` (\n  `id` int\n);\n");
+        for id in 0..100 {
+            dump.push_str(&format!("INSERT INTO `t` VALUES ({id});\n"));
+        }
+        let cancel = AtomicBool::new(false);
+        let mut inserts = Inserts { count: 0, cancel: &cancel };
+        let error = scan_dump(dump.as_bytes(), &mut StopWhen::new(&mut inserts, Some(&cancel))).unwrap_err();
+        assert!(is_cancelled(&error));
+        assert_eq!(inserts.count, 10);
+
+        let cancel = std::sync::Arc::new(AtomicBool::new(true));
+        let error = compute_table_stats(dump.as_bytes(), &ExtractOptions::new().cancel_when(cancel)).unwrap_err();
+        assert!(is_cancelled(&error));
+    }
+}
@@ -0,0 +1,77 @@
+//! A JavaScript API for WebAssembly builds, to size up a dump in the browser.
+//!
+//! ```js
+//! import init, { tableStats } from "./pkg/mysqltrim.js";
+//!
+//! // In a worker, where a File can be read synchronously
+//! const reader = new FileReaderSync();
+//! let offset = 0;
+//! const read = () => {
+//!     const chunk = file.slice(offset, offset + (1 << 20));
+//!     offset += chunk.size;
+//!     return new Uint8Array(reader.readAsArrayBuffer(chunk));
+//! };
+//! const tables = JSON.parse(tableStats(read, null, null));
+//! ```
+
+use std::io::{self, BufReader, Read};
+
+use regex::Regex;
+use wasm_bindgen::prelude::*;
+
+use crate::extract::ExtractOptions;
+use crate::stats::compute_table_stats;
+
+/// Reads the chunks a JavaScript function returns, until it returns an empty
+/// one.
+struct JsReader {
+    read: js_sys::Function,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for JsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            let chunk = self.read.call0(&JsValue::NULL).map_err(|e| io::Error::other(format!("{:?}", e)))?;
+            let chunk = chunk
+                .dyn_into::<js_sys::Uint8Array>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "read() should return a Uint8Array"))?;
+            self.chunk = chunk.to_vec();
+            self.position = 0;
+        }
+        let length = buf.len().min(self.chunk.len() - self.position);
+        buf[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// The stats of each table in the dump `read` returns chunk by chunk, as a
+/// JSON array of objects with `name`, `rows`, `data_bytes`, `ddl_bytes` and
+/// `statements`. `include` and `exclude` are regexes, as for trimming.
+#[wasm_bindgen(js_name = tableStats)]
+pub fn table_stats(read: js_sys::Function, include: Option<String>, exclude: Option<String>) -> Result<String, JsError> {
+    let mut options = ExtractOptions::new();
+    if let Some(include) = include {
+        options = options.include(Regex::new(&include)?);
+    }
+    if let Some(exclude) = exclude {
+        options = options.exclude(Regex::new(&exclude)?);
+    }
+
+    let reader = BufReader::new(JsReader { read, chunk: Vec::new(), position: 0 });
+    let tables: Vec<_> = compute_table_stats(reader, &options)?
+        .into_iter()
+        .map(|table| {
+            serde_json::json!({
+                "name": table.name,
+                "rows": table.rows,
+                "data_bytes": table.data_bytes,
+                "ddl_bytes": table.ddl_bytes,
+                "statements": table.statements,
+            })
+        })
+        .collect();
+    Ok(serde_json::Value::Array(tables).to_string())
+}
@@ -0,0 +1,220 @@
+//! Summing up a WordPress site's dump: the options loaded on every page, the
+//! posts by type and status, and the metadata left behind by deleted posts
+//! and users.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead};
+
+use crate::dump::Statement;
+use crate::insert::{parse_insert, parse_value, split_values, Value};
+use crate::schema::parse_create_table;
+use crate::visit::{scan_dump, StatementVisitor};
+
+/// How many of the largest transients are kept.
+const LARGEST_TRANSIENTS: usize = 10;
+
+/// The `autoload` values of options WordPress loads on every request:
+/// `yes` before 6.6, and `on`, `auto` and `auto-on` since.
+const AUTOLOADED: &[&[u8]] = &[b"yes", b"on", b"auto", b"auto-on"];
+
+/// What [`wordpress_report`] found in a site's tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WordPressReport {
+    /// Rows of `wp_options`
+    pub options: u64,
+    /// Options loaded on every request
+    pub autoloaded_options: u64,
+    /// The bytes of the autoloaded options' values
+    pub autoloaded_bytes: u64,
+    /// The largest transients, by the bytes of their values, largest first
+    pub largest_transients: Vec<OptionSize>,
+    /// The rows of `wp_posts` of each type and status, most first
+    pub posts: Vec<PostCount>,
+    /// Rows of `wp_postmeta` whose post isn't in `wp_posts`, or `None` if
+    /// `wp_posts` isn't in the dump
+    pub orphaned_postmeta: Option<u64>,
+    /// Rows of `wp_usermeta` whose user isn't in `wp_users`, likewise
+    pub orphaned_usermeta: Option<u64>,
+}
+
+/// An option, and the bytes of its value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OptionSize {
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// The posts of a type and status.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PostCount {
+    pub post_type: String,
+    pub post_status: String,
+    pub rows: u64,
+}
+
+/// The WordPress tables the report reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Table {
+    Options,
+    Posts,
+    Postmeta,
+    Users,
+    Usermeta,
+}
+
+impl Table {
+    fn of(name: &str, table_prefix: &str) -> Option<Self> {
+        match name.strip_prefix(table_prefix)? {
+            "options" => Some(Table::Options),
+            "posts" => Some(Table::Posts),
+            "postmeta" => Some(Table::Postmeta),
+            "users" => Some(Table::Users),
+            "usermeta" => Some(Table::Usermeta),
+            _ => None,
+        }
+    }
+
+    /// The columns read from its rows.
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Table::Options => &["option_name", "option_value", "autoload"],
+            Table::Posts => &["ID", "post_type", "post_status"],
+            Table::Postmeta => &["post_id"],
+            Table::Users => &["ID"],
+            Table::Usermeta => &["user_id"],
+        }
+    }
+}
+
+/// Report on the main site of the WordPress dump `reader` reads, whose
+/// tables are named with `table_prefix`, such as `wp_`. Statements are read
+/// whole, however long.
+///
+/// Metadata is counted as orphaned by the IDs of the posts and users in the
+/// dump, so after a trim that kept only some of them, it's what the trim
+/// left behind.
+pub fn wordpress_report<R: BufRead>(reader: R, table_prefix: &str) -> io::Result<WordPressReport> {
+    struct Report<'a> {
+        table_prefix: &'a str,
+        report: WordPressReport,
+        /// The column names of each table, from its `CREATE TABLE`
+        columns: HashMap<Table, Vec<String>>,
+        /// The tables with a `CREATE TABLE`
+        found: HashSet<Table>,
+        transients: Vec<OptionSize>,
+        posts: HashMap<(String, String), u64>,
+        post_ids: HashSet<Vec<u8>>,
+        user_ids: HashSet<Vec<u8>>,
+        /// The rows of metadata for each post and user, counted until the
+        /// posts and users are known
+        postmeta: HashMap<Vec<u8>, u64>,
+        usermeta: HashMap<Vec<u8>, u64>,
+    }
+
+    impl StatementVisitor for Report<'_> {
+        fn on_ddl(&mut self, _table: Option<&str>, statement: &Statement) -> io::Result<()> {
+            let Some(schema) = parse_create_table(&statement.text) else { return Ok(()) };
+            if let Some(table) = Table::of(&schema.name, self.table_prefix) {
+                self.found.insert(table);
+                self.columns.insert(table, schema.columns.into_iter().map(|column| column.name).collect());
+            }
+            Ok(())
+        }
+
+        fn on_insert(&mut self, table: &str, statement: &Statement) -> io::Result<()> {
+            let Some(table) = Table::of(table, self.table_prefix) else { return Ok(()) };
+            let Some(insert) = parse_insert(&statement.text) else { return Ok(()) };
+            let Some(names) = insert.columns.as_ref().or_else(|| self.columns.get(&table)) else { return Ok(()) };
+            let Some(positions) = table
+                .columns()
+                .iter()
+                .map(|column| names.iter().position(|name| name.eq_ignore_ascii_case(column)))
+                .collect::<Option<Vec<usize>>>()
+            else {
+                return Ok(());
+            };
+
+            for tuple in &insert.tuples {
+                let values = split_values(tuple);
+                let Some(values) = positions
+                    .iter()
+                    .map(|&position| values.get(position).map(|value| parse_value(value)))
+                    .collect::<Option<Vec<Value>>>()
+                else {
+                    continue;
+                };
+                let bytes = |value: &Value| value.as_bytes().unwrap_or_default().to_vec();
+                match table {
+                    Table::Options => {
+                        let name = String::from_utf8_lossy(&bytes(&values[0])).into_owned();
+                        let size = values[1].as_bytes().map_or(0, |value| value.len() as u64);
+                        self.report.options += 1;
+                        if values[2].as_bytes().is_some_and(|autoload| AUTOLOADED.contains(&autoload)) {
+                            self.report.autoloaded_options += 1;
+                            self.report.autoloaded_bytes += size;
+                        }
+                        let transient = ["_transient_", "_site_transient_"].iter().any(|prefix| name.starts_with(prefix))
+                            && !name.contains("_transient_timeout_");
+                        if transient {
+                            self.transients.push(OptionSize { name, bytes: size });
+                            if self.transients.len() > LARGEST_TRANSIENTS * 2 {
+                                keep_largest(&mut self.transients);
+                            }
+                        }
+                    }
+                    Table::Posts => {
+                        self.post_ids.insert(bytes(&values[0]));
+                        let kind = |value: &Value| String::from_utf8_lossy(value.as_bytes().unwrap_or_default()).into_owned();
+                        *self.posts.entry((kind(&values[1]), kind(&values[2]))).or_default() += 1;
+                    }
+                    Table::Postmeta => *self.postmeta.entry(bytes(&values[0])).or_default() += 1,
+                    Table::Users => {
+                        self.user_ids.insert(bytes(&values[0]));
+                    }
+                    Table::Usermeta => *self.usermeta.entry(bytes(&values[0])).or_default() += 1,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut report = Report {
+        table_prefix,
+        report: WordPressReport::default(),
+        columns: HashMap::new(),
+        found: HashSet::new(),
+        transients: Vec::new(),
+        posts: HashMap::new(),
+        post_ids: HashSet::new(),
+        user_ids: HashSet::new(),
+        postmeta: HashMap::new(),
+        usermeta: HashMap::new(),
+    };
+    scan_dump(reader, &mut report)?;
+
+    let orphans = |meta: &HashMap<Vec<u8>, u64>, ids: &HashSet<Vec<u8>>| -> u64 {
+        meta.iter().filter(|(id, _)| !ids.contains(*id)).map(|(_, rows)| rows).sum()
+    };
+    let Report { mut report, found, mut transients, posts, post_ids, user_ids, postmeta, usermeta, .. } = report;
+    report.orphaned_postmeta = found.contains(&Table::Posts).then(|| orphans(&postmeta, &post_ids));
+    report.orphaned_usermeta = found.contains(&Table::Users).then(|| orphans(&usermeta, &user_ids));
+    keep_largest(&mut transients);
+    report.largest_transients = transients;
+    report.posts = posts
+        .into_iter()
+        .map(|((post_type, post_status), rows)| PostCount { post_type, post_status, rows })
+        .collect();
+    report.posts.sort_by(|a, b| {
+        b.rows.cmp(&a.rows).then_with(|| (&a.post_type, &a.post_status).cmp(&(&b.post_type, &b.post_status)))
+    });
+    Ok(report)
+}
+
+/// Sort `options` largest first, and keep the largest few.
+fn keep_largest(options: &mut Vec<OptionSize>) {
+    options.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    options.truncate(LARGEST_TRANSIENTS);
+}